@@ -1,38 +1,91 @@
-// mod loader;
+// `return x;` is how this crate is written
+#![allow(clippy::needless_return)]
 
-// use std::cmp::Ordering;
-// use std::collections::{HashMap, HashSet};
-// use std::marker::PhantomData;
-// use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-// use crate::model::object::ObjectID;
-// use crate::model::{link, res, rule};
-// use crate::rule_engine::Value;
-// use crate::storage_backends;
-// use crate::storage_backends::filesystem;
+use criterion::{criterion_group, criterion_main, Criterion};
 
-// #[bench]
-// fn test_load_res_and_query() {
-//     let project_base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-//     let base_path = project_base_dir
-//         .join("test")
-//         .join("mock_data")
-//         .join("filesystem_backend");
-//     let backend = filesystem::FilesystemBackend::new(base_path);
-//     let cc = CFGCenter::new(backend);
-//     cc.loader.load_data(&cc);
+use eafcc::bench_support::{CFGCenter, FilesystemBackend, UpdateNotifyLevel, ViewMode, Value};
 
+const RULE_COUNT: usize = 2000;
 
-// 	let mut ctx = HashMap::new();
-// 	ctx.insert("foo".to_string(), Value::Str("123".to_string()));
-// 	ctx.insert("bar".to_string(), Value::Str("456".to_string()));
+// Write a filesystem backend with `RULE_COUNT` rules, each linked to its own res. Every rule
+// is built from `rule_tpl`, where `{}` is replaced by the index of the rule.
+fn build_mock_data(name: &str, rule_tpl: &str) -> PathBuf {
+    let base_path = std::env::temp_dir().join("eafcc_bench").join(name);
+    let _ = fs::remove_dir_all(&base_path);
+    let v1 = base_path.join("v1");
+    for dir in &["rules", "links", "reses"] {
+        fs::create_dir_all(v1.join(dir)).unwrap();
+    }
+    fs::write(base_path.join("head"), "v1").unwrap();
 
-// 	for _ in 0..1000000 {
+    let write = |dir: &str, i: usize, kind: &str, spec: String| {
+        let content = format!(
+            r#"{{"version": 1, "kind": "{}", "meta": {{"desc": "", "tags": []}}, "spec": {}}}"#,
+            kind, spec
+        );
+        fs::write(Path::new(&v1).join(dir).join(format!("{}.json", i)), content).unwrap();
+    };
+    for i in 0..RULE_COUNT {
+        let rule = rule_tpl.replace("{}", &i.to_string());
+        write("rules", i, "Rule", serde_json::json!({ "rule": rule }).to_string());
+        write(
+            "links",
+            i,
+            "Link",
+            serde_json::json!({
+                "pri": 1.0,
+                "is_neg": false,
+                "ver": "latest:master",
+                "rule": format!("path:/{}.json", i),
+                "res": [format!("path:/{}.json", i)],
+            })
+            .to_string(),
+        );
+        write(
+            "reses",
+            i,
+            "Res",
+            serde_json::json!([{
+                "content_type": "application/json",
+                "key": "my_key",
+                "data": format!("{{\"tenant\": {}}}", i),
+                "schema": {},
+            }])
+            .to_string(),
+        );
+    }
+    return base_path;
+}
 
-	
-// 		let t = cc.get_cfg(&ctx, "aaa/1/bbb", "/").unwrap();
-// 	}
-	
-// }
+fn bench_query(c: &mut Criterion, name: &str, rule_tpl: &str) {
+    let base_path = build_mock_data(name, rule_tpl);
+    let backend = Box::new(FilesystemBackend::new(base_path));
+    let cc = CFGCenter::new(backend).unwrap();
+    let ns = cc
+        .create_namespace_scoped_cfg_center("/", UpdateNotifyLevel::NoNotify, None)
+        .unwrap();
 
+    let mut whoami = HashMap::new();
+    whoami.insert("tenant".to_string(), Value::Str("tenant-42".to_string()));
+    whoami.insert("build".to_string(), Value::Str("1300".to_string()));
+    let keys = vec!["my_key"];
 
+    assert!(ns.get_cfg(&whoami, &keys, ViewMode::OverlaidView, false).unwrap().len() == 1);
+    c.bench_function(name, |b| {
+        b.iter(|| ns.get_cfg(&whoami, &keys, ViewMode::OverlaidView, false).unwrap())
+    });
+}
+
+fn query_benchmark(c: &mut Criterion) {
+    // equality leaves, only the rule of tenant-42 is evaluated
+    bench_query(c, "query_indexed_rules", r#"tenant == "tenant-{}" && build >= 1200"#);
+    // same result, but `!(... != ...)` can't be indexed, so every rule is evaluated
+    bench_query(c, "query_unindexed_rules", r#"!(tenant != "tenant-{}") && build >= 1200"#);
+}
+
+criterion_group!(benches, query_benchmark);
+criterion_main!(benches);
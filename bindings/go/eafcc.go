@@ -1,14 +1,17 @@
 package main
 
 import (
+	"errors"
 	"fmt"
 	"hash"
 	"log"
+	"math"
 	"math/rand"
 	"net/http"
 	_ "net/http/pprof"
 	"os"
 	"reflect"
+	"runtime"
 	"sync"
 	"sync/atomic"
 	"time"
@@ -57,8 +60,11 @@ type WhoAmI struct {
 type CFGValue struct {
 	Key         string
 	ContextType string
+	// any bytes, binary for content types that are not text or JSON
 	Value       string
 	Reason *CFGValueReason
+	// why the key could not be resolved, Value is empty then, the other keys are not affected
+	Err error
 }
 
 type CFGValueReason struct {
@@ -67,6 +73,63 @@ type CFGValueReason struct {
 	LinkPath string
 	RulePath string
 	ResPath string
+	// how the rule evaluated against the whoami, empty if not available
+	Trace string
+	// the value before its placeholders were expanded, empty if it is not a template
+	Source string
+}
+
+type KeyCandidateState uint32
+
+const (
+	// the rule matched and this link decides the key
+	KeyCandidateDecides KeyCandidateState = 0
+	// the rule matched and this negative link hides the key
+	KeyCandidateHidden KeyCandidateState = 1
+	// the rule matched and the value is merged into the value of StateLinkPath
+	KeyCandidateMergedInto KeyCandidateState = 2
+	// the rule matched, but StateLinkPath decides or hides the key
+	KeyCandidateShadowedBy KeyCandidateState = 3
+	// the rule did not match or failed to evaluate
+	KeyCandidateNotMatched KeyCandidateState = 4
+)
+
+// A link whose resource has the key, see Namespace.ExplainKey.
+type KeyCandidate struct {
+	Pri float32
+	IsNeg bool
+	LinkPath string
+	RulePath string
+	ResPath string
+	State KeyCandidateState
+	// empty unless State is KeyCandidateMergedInto or KeyCandidateShadowedBy
+	StateLinkPath string
+	// the rule in rule syntax, empty if the link points to a rule that does not exist
+	Rule string
+	// how the rule evaluated against the whoami, empty if the rule does not exist
+	Trace string
+	// the leaf that made the rule fail, empty if the rule matched
+	FailingLeaf string
+}
+
+type RuleWarningKind uint32
+
+const (
+	// the condition can never match
+	RuleWarningContradiction RuleWarningKind = 0
+	// the condition always matches
+	RuleWarningTautology RuleWarningKind = 1
+	// the same condition appears twice under one `&&` or `||`
+	RuleWarningDuplicate RuleWarningKind = 2
+	// a branch that can be removed without changing the result
+	RuleWarningSubsumed RuleWarningKind = 3
+)
+
+// A likely mistake in a rule, found when the namespace was loaded, the rule is still used.
+type RuleWarning struct {
+	RulePath string
+	Kind RuleWarningKind
+	Message string
 }
 
 type namespaceInstanceStorageForCGo struct {
@@ -250,7 +313,10 @@ func (c *Namespace) GetCfgRawNoCache(whoami *WhoAmI, keys []string, viewMode CFG
 		return nil, err
 	}
 
-	t := C.get_config((*C.eafcc_NamespaceScopedCFGCenter)(c.cc), (*C.eafcc_WhoAmI)(whoami.ctx), (**C.char)(unsafe.Pointer(&ckeys[0])), C.ulong(len(keys)), cViewMode, C.uchar(cNeedExplain))
+	// the last error is kept per thread
+	runtime.LockOSThread()
+	defer runtime.UnlockOSThread()
+	t := C.get_config_v2((*C.eafcc_NamespaceScopedCFGCenter)(c.cc), (*C.eafcc_WhoAmI)(whoami.ctx), (**C.char)(unsafe.Pointer(&ckeys[0])), C.ulong(len(keys)), cViewMode, C.uchar(cNeedExplain))
 	for _, ckey := range ckeys {
 		C.free(unsafe.Pointer(ckey))
 	}
@@ -272,6 +338,134 @@ func (c *Namespace) NewWhoAmI(whoAmI string) *WhoAmI {
 	return ret
 }
 
+// Every link that could have provided key to whoami and why it did or did not, in the order
+// the overlaid view looks at them. It is not cached.
+func (c *Namespace) ExplainKey(whoami *WhoAmI, key string) ([]*KeyCandidate, error) {
+	if err := whoami.ensureCtx(); err != nil {
+		return nil, err
+	}
+	ckey := C.CString(key)
+	defer C.free(unsafe.Pointer(ckey))
+
+	// the last error is kept per thread
+	runtime.LockOSThread()
+	defer runtime.UnlockOSThread()
+	t := C.explain_key((*C.eafcc_NamespaceScopedCFGCenter)(c.cc), (*C.eafcc_WhoAmI)(whoami.ctx), ckey)
+	if t == nil {
+		return nil, lastError()
+	}
+	defer C.free_key_candidates(t)
+
+	optString := func(p *C.char) string {
+		if p == nil {
+			return ""
+		}
+		return C.GoString(p)
+	}
+	ret := make([]*KeyCandidate, 0, int(t.len))
+	for i := 0; i < int(t.len); i++ {
+		tmpP := unsafe.Pointer(uintptr(unsafe.Pointer(t.ptr)) + uintptr(i)*unsafe.Sizeof(C.eafcc_KeyCandidate{}))
+		item := (*C.eafcc_KeyCandidate)(tmpP)
+		ret = append(ret, &KeyCandidate{
+			Pri: float32(item.pri),
+			IsNeg: bool(item.is_neg),
+			LinkPath: C.GoString(item.link_path),
+			RulePath: C.GoString(item.rule_path),
+			ResPath: C.GoString(item.res_path),
+			State: KeyCandidateState(item.state),
+			StateLinkPath: optString(item.state_link_path),
+			Rule: optString(item.rule),
+			Trace: optString(item.trace),
+			FailingLeaf: optString(item.failing_leaf),
+		})
+	}
+	return ret, nil
+}
+
+// The warnings of the rules in the config the namespace has currently loaded.
+func (c *Namespace) GetRuleWarnings() ([]*RuleWarning, error) {
+	// the last error is kept per thread
+	runtime.LockOSThread()
+	defer runtime.UnlockOSThread()
+	t := C.get_rule_warnings((*C.eafcc_NamespaceScopedCFGCenter)(c.cc))
+	if t == nil {
+		return nil, lastError()
+	}
+	defer C.free_rule_warnings(t)
+
+	ret := make([]*RuleWarning, 0, int(t.len))
+	for i := 0; i < int(t.len); i++ {
+		tmpP := unsafe.Pointer(uintptr(unsafe.Pointer(t.ptr)) + uintptr(i)*unsafe.Sizeof(C.eafcc_RuleWarning{}))
+		item := (*C.eafcc_RuleWarning)(tmpP)
+		ret = append(ret, &RuleWarning{
+			RulePath: C.GoString(item.rule_path),
+			Kind: RuleWarningKind(item.kind),
+			Message: C.GoString(item.message),
+		})
+	}
+	return ret, nil
+}
+
+// Seal plaintext for the holder of the private key of publicKey, in base64, known as kid. The
+// result is the data of a value with "encrypted": true.
+func SealValue(kid string, publicKey string, plaintext []byte) (string, error) {
+	ckid := C.CString(kid)
+	defer C.free(unsafe.Pointer(ckid))
+	cpk := C.CString(publicKey)
+	defer C.free(unsafe.Pointer(cpk))
+	var cplain *C.uint8_t = nil
+	if len(plaintext) > 0 {
+		cplain = (*C.uint8_t)(C.CBytes(plaintext))
+		defer C.free(unsafe.Pointer(cplain))
+	}
+
+	// the last error is kept per thread
+	runtime.LockOSThread()
+	defer runtime.UnlockOSThread()
+	t := C.seal_value(ckid, cpk, cplain, C.uintptr_t(len(plaintext)))
+	if t == nil {
+		return "", lastError()
+	}
+	defer C.free_string(t)
+	return C.GoString(t), nil
+}
+
+// The public key of a private key, both in base64, to hand out to whoever seals values for it.
+func PublicKeyOf(privateKey string) (string, error) {
+	cpk := C.CString(privateKey)
+	defer C.free(unsafe.Pointer(cpk))
+
+	// the last error is kept per thread
+	runtime.LockOSThread()
+	defer runtime.UnlockOSThread()
+	t := C.public_key_of(cpk)
+	if t == nil {
+		return "", lastError()
+	}
+	defer C.free_string(t)
+	return C.GoString(t), nil
+}
+
+// the error of the last call into the C library that failed on this thread, the caller must
+// have locked the OS thread since that call
+func lastError() error {
+	return errors.New(C.GoString(C.get_last_error().msg))
+}
+
+// create the whoami in the C library on first use, so a cached query never needs it
+func (c *WhoAmI) ensureCtx() error {
+	if c.ctx != nil {
+		return nil
+	}
+	cctx := C.CString(c.raw)
+	defer C.free(unsafe.Pointer(cctx))
+	if handler := C.new_whoami(cctx); handler != nil {
+		c.ctx = unsafe.Pointer(handler)
+		return nil
+	}
+	return fmt.Errorf("create whoami in C library got error")
+}
+
 func (c *WhoAmI) Free() {
 	if c.ctx != nil {
 		C.free_whoami((*C.eafcc_WhoAmI)(c.ctx))
@@ -292,14 +486,8 @@ func convertGetCFGInput(whoami *WhoAmI, keys []string, viewMode CFGViewMode, nee
 		// defer C.free(unsafe.Pointer(ckey))
 	}
 
-	if whoami.ctx == nil {
-		cctx := C.CString(whoami.raw)
-		defer C.free(unsafe.Pointer(cctx))
-		if handler := C.new_whoami(cctx); handler != nil {
-			whoami.ctx = unsafe.Pointer(handler)
-		} else {
-			return nil, 0, 0, fmt.Errorf("create whoami in C library got error")
-		}
+	if err = whoami.ensureCtx(); err != nil {
+		return nil, 0, 0, err
 	}
  
 	cViewMode = C.eafcc_ViewMode(viewMode)
@@ -310,7 +498,21 @@ func convertGetCFGInput(whoami *WhoAmI, keys []string, viewMode CFGViewMode, nee
 	return
 }
 
-func convertGetCFGOutput(cValues *C.eafcc_ConfigValues) (map[string][]*CFGValue, error) {
+// a copy of the bytes, C.GoBytes takes a C int so a longer value is an error instead of being
+// truncated
+func goBytes(b C.eafcc_Bytes) ([]byte, error) {
+	if uint64(b.len) > math.MaxInt32 {
+		return nil, fmt.Errorf("the value is %d bytes, longer than the %d bytes supported", uint64(b.len), math.MaxInt32)
+	}
+	return C.GoBytes(unsafe.Pointer(b.ptr), C.int(b.len)), nil
+}
+
+// the caller must have locked the OS thread since the query, see lastError
+func convertGetCFGOutput(cValues *C.eafcc_ConfigValuesV2) (map[string][]*CFGValue, error) {
+	if cValues == nil {
+		return nil, lastError()
+	}
+	defer C.free_config_values_v2(cValues)
 
 	valueCnt := int(cValues.len)
 	t := cValues.ptr
@@ -318,11 +520,19 @@ func convertGetCFGOutput(cValues *C.eafcc_ConfigValues) (map[string][]*CFGValue,
 	ret := make(map[string][]*CFGValue, valueCnt)
 
 	for i := 0; i < valueCnt; i++ {
-		tmpP := unsafe.Pointer(uintptr(unsafe.Pointer(t)) + uintptr(i)*unsafe.Sizeof(C.eafcc_ConfigValue{}))
-		t := (*C.eafcc_ConfigValue)(tmpP)
+		tmpP := unsafe.Pointer(uintptr(unsafe.Pointer(t)) + uintptr(i)*unsafe.Sizeof(C.eafcc_ConfigValueV2{}))
+		t := (*C.eafcc_ConfigValueV2)(tmpP)
 		key := C.GoString(t.key)
 		contextType := C.GoString(t.content_type)
-		value := C.GoString(t.value)
+
+		var valueErr error = nil
+		if t.error != nil {
+			valueErr = errors.New(C.GoString(t.error))
+		}
+		value, err := goBytes(t.value)
+		if err != nil && valueErr == nil {
+			valueErr = err
+		}
 
 		var reason *CFGValueReason = nil
 		if t.reason != nil {
@@ -334,12 +544,21 @@ func convertGetCFGOutput(cValues *C.eafcc_ConfigValues) (map[string][]*CFGValue,
 				LinkPath: C.GoString(r.link_path),
 				ResPath: C.GoString(r.res_path),
 			}
+			if r.trace != nil {
+				reason.Trace = C.GoString(r.trace)
+			}
+			if r.source.ptr != nil {
+				source, err := goBytes(r.source)
+				if err != nil && valueErr == nil {
+					valueErr = err
+				}
+				reason.Source = toString(source)
+			}
 		}
 
-		ret[key] = append(ret[key], &CFGValue{key, contextType, value, reason})
+		ret[key] = append(ret[key], &CFGValue{key, contextType, toString(value), reason, valueErr})
 	}
 
-	C.free_config_values(cValues)
 	return ret, nil
 }
 
@@ -350,7 +569,10 @@ func (d *Differ) GetFromOld(whoami *WhoAmI, keys []string, viewMode CFGViewMode,
 		return nil, err
 	}
 
-	t := C.differ_get_from_old((*C.eafcc_Differ)(d.ptr), (*C.eafcc_WhoAmI)(whoami.ctx), (**C.char)(unsafe.Pointer(&ckeys[0])), C.ulong(len(keys)), cViewMode, C.uchar(cNeedExplain))
+	// the last error is kept per thread
+	runtime.LockOSThread()
+	defer runtime.UnlockOSThread()
+	t := C.differ_get_from_old_v2((*C.eafcc_Differ)(d.ptr), (*C.eafcc_WhoAmI)(whoami.ctx), (**C.char)(unsafe.Pointer(&ckeys[0])), C.ulong(len(keys)), cViewMode, C.uchar(cNeedExplain))
 	for _, ckey := range ckeys {
 		C.free(unsafe.Pointer(ckey))
 	}
@@ -363,7 +585,10 @@ func (d *Differ) GetFromNew(whoami *WhoAmI, keys []string, viewMode CFGViewMode,
 		return nil, err
 	}
 
-	t := C.differ_get_from_new((*C.eafcc_Differ)(d.ptr), (*C.eafcc_WhoAmI)(whoami.ctx), (**C.char)(unsafe.Pointer(&ckeys[0])), C.ulong(len(keys)), cViewMode, C.uchar(cNeedExplain))
+	// the last error is kept per thread
+	runtime.LockOSThread()
+	defer runtime.UnlockOSThread()
+	t := C.differ_get_from_new_v2((*C.eafcc_Differ)(d.ptr), (*C.eafcc_WhoAmI)(whoami.ctx), (**C.char)(unsafe.Pointer(&ckeys[0])), C.ulong(len(keys)), cViewMode, C.uchar(cNeedExplain))
 	for _, ckey := range ckeys {
 		C.free(unsafe.Pointer(ckey))
 	}
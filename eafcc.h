@@ -1,3 +1,20 @@
+enum eafcc_KeyCandidateState {
+  Decides,
+  Hidden,
+  MergedInto,
+  ShadowedBy,
+  NotMatched,
+};
+typedef uint32_t eafcc_KeyCandidateState;
+
+enum eafcc_RuleWarningKind {
+  Contradiction,
+  Tautology,
+  Duplicate,
+  Subsumed,
+};
+typedef uint32_t eafcc_RuleWarningKind;
+
 enum eafcc_UpdateNotifyLevel {
   NoNotify,
   NotifyWithoutChangedKeysByGlobal,
@@ -20,12 +37,19 @@ typedef struct eafcc_NamespaceScopedCFGCenter eafcc_NamespaceScopedCFGCenter;
 
 typedef struct eafcc_WhoAmI eafcc_WhoAmI;
 
+typedef struct {
+  uintptr_t len;
+  uint8_t *ptr;
+} eafcc_Bytes;
+
 typedef struct {
   float pri;
   bool is_neg;
   char *link_path;
   char *rule_path;
   char *res_path;
+  char *trace;
+  eafcc_Bytes source;
 } eafcc_ConfigValueReason;
 
 typedef struct {
@@ -40,11 +64,53 @@ typedef struct {
   eafcc_ConfigValue *ptr;
 } eafcc_ConfigValues;
 
+typedef struct {
+  char *key;
+  char *content_type;
+  eafcc_Bytes value;
+  eafcc_ConfigValueReason *reason;
+  char *error;
+} eafcc_ConfigValueV2;
+
+typedef struct {
+  uintptr_t len;
+  eafcc_ConfigValueV2 *ptr;
+} eafcc_ConfigValuesV2;
+
 typedef struct {
   const char *msg;
   intptr_t code;
 } eafcc_EAFCCError;
 
+typedef struct {
+  float pri;
+  bool is_neg;
+  char *link_path;
+  char *rule_path;
+  char *res_path;
+  eafcc_KeyCandidateState state;
+  char *state_link_path;
+  char *rule;
+  char *trace;
+  char *failing_leaf;
+} eafcc_KeyCandidate;
+
+typedef struct {
+  uintptr_t len;
+  eafcc_KeyCandidate *ptr;
+} eafcc_KeyCandidates;
+
+typedef struct {
+  char *rule_path;
+  eafcc_RuleWarningKind kind;
+  char *message;
+} eafcc_RuleWarning;
+
+typedef struct {
+  uintptr_t len;
+  eafcc_RuleWarning *ptr;
+} eafcc_RuleWarnings;
+
 const eafcc_CFGCenter *new_config_center_client(const char *cfg);
 
 void free_config_center(eafcc_CFGCenter *cc);
@@ -70,6 +136,15 @@ eafcc_ConfigValues *get_config(const eafcc_NamespaceScopedCFGCenter *ns,
 
 void free_config_values(eafcc_ConfigValues *v);
 
+eafcc_ConfigValuesV2 *get_config_v2(const eafcc_NamespaceScopedCFGCenter *ns,
+                                    const eafcc_WhoAmI *whoami,
+                                    char **keys,
+                                    uintptr_t key_cnt,
+                                    eafcc_ViewMode view_mode,
+                                    uint8_t need_explain);
+
+void free_config_values_v2(eafcc_ConfigValuesV2 *v);
+
 eafcc_ConfigValues *differ_get_from_old(const eafcc_Differ *differ,
                                         const eafcc_WhoAmI *whoami,
                                         char **keys,
@@ -84,4 +159,34 @@ eafcc_ConfigValues *differ_get_from_new(const eafcc_Differ *differ,
                                         eafcc_ViewMode view_mode,
                                         uint8_t need_explain);
 
+eafcc_ConfigValuesV2 *differ_get_from_old_v2(const eafcc_Differ *differ,
+                                             const eafcc_WhoAmI *whoami,
+                                             char **keys,
+                                             uintptr_t key_cnt,
+                                             eafcc_ViewMode view_mode,
+                                             uint8_t need_explain);
+
+eafcc_ConfigValuesV2 *differ_get_from_new_v2(const eafcc_Differ *differ,
+                                             const eafcc_WhoAmI *whoami,
+                                             char **keys,
+                                             uintptr_t key_cnt,
+                                             eafcc_ViewMode view_mode,
+                                             uint8_t need_explain);
+
+eafcc_KeyCandidates *explain_key(const eafcc_NamespaceScopedCFGCenter *ns,
+                                 const eafcc_WhoAmI *whoami,
+                                 const char *key);
+
+void free_key_candidates(eafcc_KeyCandidates *v);
+
+eafcc_RuleWarnings *get_rule_warnings(const eafcc_NamespaceScopedCFGCenter *ns);
+
+void free_rule_warnings(eafcc_RuleWarnings *v);
+
+char *seal_value(const char *kid, const char *public_key, const uint8_t *plaintext, uintptr_t len);
+
+char *public_key_of(const char *private_key);
+
+void free_string(char *s);
+
 const eafcc_EAFCCError *get_last_error(void);
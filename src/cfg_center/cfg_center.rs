@@ -1,16 +1,18 @@
-use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, Weak};
 use std::thread;
+use std::time::Duration;
+#[cfg(test)]
+use std::path::PathBuf;
 
-use crate::storage_backends::{StorageBackend, StorageChangeEvent, VersionItem, filesystem};
-use crate::{rule_engine::Value, storage_backends};
+use crate::storage_backends::{StorageChangeEvent, VersionItem};
+use crate::{rule_engine::{Clock, SystemClock}, storage_backends};
+#[cfg(test)]
+use crate::{rule_engine::Value, storage_backends::filesystem};
 
-use super::differ::Differ;
 use super::mem_store::MemStorage;
-use super::namespace::{NamespaceScopedCFGCenter, UpdateEventItem};
-use super::querier::CFGResult;
+use super::namespace::{NamespaceScopedCFGCenter, UpdateCallback};
+use super::secret::Keyring;
 
 use crate::error::{Result, CCLibError};
 
@@ -20,6 +22,72 @@ pub struct CFGCenterInner {
     backend: Arc<dyn storage_backends::StorageBackend + Send + Sync>,
     namespaces: Mutex<HashMap<String, Arc<NamespaceScopedCFGCenter>>>,
 	current_version: Mutex<VersionItem>,
+	clock: Arc<dyn Clock>,
+	// private keys for the encrypted values of every namespace
+	keyring: Keyring,
+	timer: Arc<TimerSignal>,
+}
+
+// The timer thread sleeps until the next time window in the rules opens or closes, but wakes
+// at least this often, in case the wall clock was changed meanwhile.
+const MAX_TIMER_SLEEP: Duration = Duration::from_secs(3600);
+
+// Wakes the timer thread early, when its next time boundary may have changed or when the config
+// center is dropped.
+#[derive(Default)]
+struct TimerSignal {
+    state: Mutex<TimerState>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct TimerState {
+    woken: bool,
+    stopped: bool,
+}
+
+impl TimerSignal {
+    fn wake(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.woken = true;
+            self.cond.notify_all();
+        }
+    }
+
+    fn stop(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.stopped = true;
+            self.cond.notify_all();
+        }
+    }
+
+    // Wait until woken, stopped or `timeout`, forever if it is None, false if stopped.
+    fn wait(&self, timeout: Option<Duration>) -> bool {
+        let mut state = match self.state.lock() {
+            Ok(t) => t,
+            Err(_) => return false,
+        };
+        if state.woken == false && state.stopped == false {
+            state = match timeout {
+                Some(t) => match self.cond.wait_timeout(state, t) {
+                    Ok((t, _)) => t,
+                    Err(_) => return false,
+                },
+                None => match self.cond.wait(state) {
+                    Ok(t) => t,
+                    Err(_) => return false,
+                },
+            };
+        }
+        state.woken = false;
+        return state.stopped == false;
+    }
+}
+
+impl Drop for CFGCenterInner {
+    fn drop(&mut self) {
+        self.timer.stop();
+    }
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -57,7 +125,7 @@ impl CFGCenterInner {
         &self,
         namespace: &str,
         notify_level: UpdateNotifyLevel,
-		callback: Option<Box<dyn Fn(&Differ)+ Send + Sync>>,
+		callback: Option<UpdateCallback>,
     ) -> Result<Arc<NamespaceScopedCFGCenter>> {
         if !namespace.starts_with("/") || !namespace.ends_with("/") {
             return Err(CCLibError::NamespaceError("namespace must starts and end with `/`"));
@@ -65,14 +133,13 @@ impl CFGCenterInner {
 
 		let cur_version = self.current_version.lock().or(Err(CCLibError::NamespaceError("get internal lock error")))?;
 
-        let mem_store = Box::new(MemStorage::new(self.backend.as_ref(), namespace, &cur_version)?);
+        let mem_store = Box::new(MemStorage::new(self.backend.as_ref(), namespace, &cur_version, &self.keyring)?);
 
         let v = Arc::new(NamespaceScopedCFGCenter::new(
-            namespace,
             mem_store,
-			self.backend.clone(),
             notify_level,
 			callback,
+			self.clock.clone(),
         ));
 
         let mut namespaces = self
@@ -80,6 +147,8 @@ impl CFGCenterInner {
             .lock()
             .or(Err(CCLibError::NamespaceError("get lock error")))?;
         namespaces.insert(namespace.to_owned(), v.clone());
+        // the new namespace may have a time boundary sooner than the others
+        self.timer.wake();
         Ok(v)
     }
 
@@ -98,42 +167,43 @@ impl CFGCenterInner {
         for (ns, scoped_cfg_center) in &*namespaces {
             match scoped_cfg_center.notify_level {
                 UpdateNotifyLevel::NotifyWithoutChangedKeysByGlobal => {
-                    let new_mem_store = match MemStorage::new(self.backend.as_ref(), ns, &e.new_version) {
+                    let new_mem_store = match MemStorage::new(self.backend.as_ref(), ns, &e.new_version, &self.keyring) {
                         Ok(t) => Box::new(t),
                         Err(e) => {
                             print_error_with_switch!("error occured while loading changed configs in background, namespace = {}, {}", ns, e);
                             continue
                         }
                     };
-					scoped_cfg_center.update_callback(new_mem_store, Vec::new());
+					scoped_cfg_center.update_callback(new_mem_store);
 				}
 				UpdateNotifyLevel::NotifyWithoutChangedKeysInNamespace => {
-					let changed_files = match self.backend.get_diff_list(&old_version, &e.new_version, &ns){
+					let changed_files = match self.backend.get_diff_list(&old_version, &e.new_version, ns){
 						Ok(t) => t,
 						Err(_) => continue,
 					};
-					if changed_files.len() == 0 {
+					if changed_files.is_empty() {
 						continue
 					}
 
-                    let new_mem_store = match MemStorage::new(self.backend.as_ref(), &ns, &e.new_version) {
+                    let new_mem_store = match MemStorage::new(self.backend.as_ref(), ns, &e.new_version, &self.keyring) {
                         Ok(t) => Box::new(t),
                         Err(e) => {
                             print_error_with_switch!("error occured while loading changed configs in background, namespace = {}, {}", ns, e);
                             continue
                         }
                     };
-					scoped_cfg_center.update_callback(new_mem_store, changed_files);
+					scoped_cfg_center.update_callback(new_mem_store);
 				}
                 UpdateNotifyLevel::NotifyWithMaybeChangedKeys => {
-					let changed_files = match self.backend.get_diff_list(&old_version, &e.new_version, &ns){
+					let changed_files = match self.backend.get_diff_list(&old_version, &e.new_version, ns){
 						Ok(t) => t,
 						Err(_) => continue,
 					};
-					if changed_files.len() == 0 {
+					if changed_files.is_empty() {
 						continue
 					}
-					let new_mem_store = match MemStorage::new(self.backend.as_ref(), &ns, &e.new_version) {
+					// TODO compute the keys that may have changed and notify with them
+					let _new_mem_store = match MemStorage::new(self.backend.as_ref(), ns, &e.new_version, &self.keyring) {
                         Ok(t) => Box::new(t),
                         Err(e) => {
                             print_error_with_switch!("error occured while loading changed configs in background, namespace = {}, {}", ns, e);
@@ -146,6 +216,27 @@ impl CFGCenterInner {
         }
 
 		*old_version = e.new_version;
+        self.timer.wake();
+    }
+
+    fn check_time_boundaries(&self) {
+        // the lock is released before any callback runs, callbacks may look up namespaces
+        let namespaces: Vec<_> = match self.namespaces.lock() {
+            Ok(t) => t.values().cloned().collect(),
+            Err(_) => return,
+        };
+        for scoped_cfg_center in namespaces {
+            scoped_cfg_center.check_time_boundary();
+        }
+    }
+
+    // when a time window in the rules of any namespace opens or closes next
+    fn next_time_boundary(&self) -> Option<i64> {
+        let namespaces = match self.namespaces.lock() {
+            Ok(t) => t,
+            Err(_) => return None,
+        };
+        return namespaces.values().filter_map(|t| t.next_time_boundary()).min();
     }
 }
 
@@ -160,6 +251,21 @@ pub struct CFGCenter(Arc<CFGCenterInner>);
 
 impl CFGCenter {
     pub fn new(backend: Box<dyn storage_backends::StorageBackend + Send + Sync>) -> Result<Self> {
+        return Self::new_with_clock(backend, Arc::new(SystemClock));
+    }
+
+    /// Like `new`, but time windows in rules are evaluated with the given clock.
+    pub fn new_with_clock(backend: Box<dyn storage_backends::StorageBackend + Send + Sync>, clock: Arc<dyn Clock>) -> Result<Self> {
+        return Self::new_with_options(backend, clock, Keyring::new());
+    }
+
+    /// Like `new_with_clock`, encrypted values are decrypted with the keys in `keyring`, values
+    /// sealed for other keys fail when they are queried.
+    pub fn new_with_options(
+        backend: Box<dyn storage_backends::StorageBackend + Send + Sync>,
+        clock: Arc<dyn Clock>,
+        keyring: Keyring,
+    ) -> Result<Self> {
 
 		let version = backend.get_current_version().or(Err(CCLibError::NamespaceError("can not get newest config version")))?;
 
@@ -167,17 +273,45 @@ impl CFGCenter {
             backend: Arc::from(backend),
             namespaces: Mutex::new(HashMap::new()),
 			current_version: Mutex::new(version),
+			clock,
+			keyring,
+			timer: Arc::new(TimerSignal::default()),
         });
 
-        let inner_for_capture = inner.clone();
-        inner
-            .backend
-            .set_update_cb(Box::new(move |x| inner_for_capture.update_callback(x)))?;
+        // neither the backend nor the timer keep the config center alive, the timer stops when
+        // the last `CFGCenter` handle is dropped
+        let inner_for_capture = Arc::downgrade(&inner);
+        inner.backend.set_update_cb(Box::new(move |x| {
+            if let Some(inner) = inner_for_capture.upgrade() {
+                inner.update_callback(x);
+            }
+        }))?;
+
+        let inner_for_timer = Arc::downgrade(&inner);
+        let timer = inner.timer.clone();
+        thread::spawn(move || Self::time_boundary_timer(inner_for_timer, timer));
 
         let t = CFGCenter(inner);
         Ok(t)
     }
 
+    fn time_boundary_timer(inner: Weak<CFGCenterInner>, timer: Arc<TimerSignal>) {
+        loop {
+            // the config center is not kept alive while waiting
+            let timeout = match inner.upgrade() {
+                Some(inner) => {
+                    inner.check_time_boundaries();
+                    let now = inner.clock.now();
+                    inner.next_time_boundary().map(|t| Duration::from_secs((t - now).max(0) as u64).min(MAX_TIMER_SLEEP))
+                }
+                None => return,
+            };
+            if timer.wait(timeout) == false {
+                return;
+            }
+        }
+    }
+
     pub fn get_namespace_scoped_cfg_center(
         &self,
         namespace: &str,
@@ -189,10 +323,343 @@ impl CFGCenter {
         &self,
         namespace: &str,
         notify_level: UpdateNotifyLevel,
-		callback: Option<Box<dyn Fn(&Differ) + Send + Sync>>,
+		callback: Option<UpdateCallback>,
     ) -> Result<Arc<NamespaceScopedCFGCenter>> {
         return self
             .0
             .create_namespace_scoped_cfg_center(namespace, notify_level, callback);
     }
 }
+
+#[test]
+fn test_time_window_notify() {
+    use crate::rule_engine::{parse_timestamp, ManualClock};
+    use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+
+    let base_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join("mock_data")
+        .join("time_window");
+    let start = parse_timestamp("2026-11-01T00:00Z").unwrap();
+    let clock = Arc::new(ManualClock::new(start - 10));
+    let cc = CFGCenter::new_with_clock(Box::new(filesystem::FilesystemBackend::new(base_path)), clock.clone()).unwrap();
+
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let fired_for_cb = fired.clone();
+    let cc_for_cb = cc.clone();
+    let reloading = AtomicBool::new(false);
+    let ns = cc.create_namespace_scoped_cfg_center(
+        "/",
+        UpdateNotifyLevel::NotifyWithoutChangedKeysByGlobal,
+        Some(Box::new(move |differ| {
+            // the reload below notifies too
+            if reloading.load(SeqCst) {
+                return;
+            }
+            // callbacks may use the config center they are registered on, and query or reload
+            // the namespace they are notified for
+            let ns = cc_for_cb.get_namespace_scoped_cfg_center("/").unwrap();
+            let whoami = HashMap::new();
+            let keys = vec!["promo_banner"];
+            let old = differ.get_from_old(&whoami, &keys, ViewMode::OverlaidView, false).unwrap();
+            let new = differ.get_from_new(&whoami, &keys, ViewMode::OverlaidView, false).unwrap();
+            assert_eq!(ns.get_cfg(&whoami, &keys, ViewMode::OverlaidView, false).unwrap().len(), new.len());
+            fired_for_cb.lock().unwrap().push((old.len(), new.len()));
+
+            let inner = &cc_for_cb.0;
+            let version = inner.current_version.lock().unwrap().clone();
+            let mem_store = MemStorage::new(inner.backend.as_ref(), "/", &version, &inner.keyring).unwrap();
+            reloading.store(true, SeqCst);
+            ns.update_callback(Box::new(mem_store));
+            reloading.store(false, SeqCst);
+        })),
+    ).unwrap();
+
+    let query = || ns.get_cfg(&HashMap::new(), &vec!["promo_banner"], ViewMode::OverlaidView, false).unwrap().len();
+    assert_eq!(query(), 0);
+
+    cc.0.check_time_boundaries();
+    assert_eq!(*fired.lock().unwrap(), vec![]);
+
+    clock.advance(20);
+    assert_eq!(query(), 1);
+    // explain mode tells why the rule matched
+    let explained = ns.get_cfg(&HashMap::new(), &vec!["promo_banner"], ViewMode::OverlaidView, true).unwrap();
+    assert_eq!(
+        explained[0].trace.as_ref().unwrap().to_string(),
+        "true  now between \"2026-11-01T00:00Z\" and \"2026-11-02T00:00Z\"  (now = 2026-11-01T00:00:10Z)"
+    );
+    cc.0.check_time_boundaries();
+    assert_eq!(*fired.lock().unwrap(), vec![(0, 1)]);
+
+    // nothing more happens until the window closes, the rule no link uses opens and closes
+    // in between
+    clock.advance(3600);
+    cc.0.check_time_boundaries();
+    assert_eq!(fired.lock().unwrap().len(), 1);
+
+    clock.advance(86400);
+    assert_eq!(query(), 0);
+    cc.0.check_time_boundaries();
+    assert_eq!(*fired.lock().unwrap(), vec![(0, 1), (1, 0)]);
+}
+
+#[test]
+fn test_time_boundary_timer() {
+    use crate::rule_engine::{parse_timestamp, ManualClock};
+
+    let base_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join("mock_data")
+        .join("time_window");
+    let start = parse_timestamp("2026-11-01T00:00Z").unwrap();
+    // the timer sleeps for the one second left until the window opens
+    let clock = Arc::new(ManualClock::new(start - 1));
+    let cc = CFGCenter::new_with_clock(Box::new(filesystem::FilesystemBackend::new(base_path)), clock.clone()).unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let tx = Mutex::new(tx);
+    cc.create_namespace_scoped_cfg_center(
+        "/",
+        UpdateNotifyLevel::NotifyWithoutChangedKeysByGlobal,
+        Some(Box::new(move |_| tx.lock().unwrap().send(()).unwrap())),
+    )
+    .unwrap();
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    // no one checks the time boundaries but the timer
+    clock.advance(10);
+    assert!(rx.recv_timeout(Duration::from_secs(10)).is_ok());
+
+    // nothing keeps the config center alive once dropped, and the timer thread exits
+    let inner = Arc::downgrade(&cc.0);
+    let timer = cc.0.timer.clone();
+    drop(cc);
+    assert!(inner.upgrade().is_none());
+    for _ in 0..100 {
+        if Arc::strong_count(&timer) == 1 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(Arc::strong_count(&timer), 1);
+}
+
+#[test]
+fn test_explain_key() {
+    use super::CandidateState;
+
+    let base_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join("mock_data")
+        .join("why_not");
+    let cc = CFGCenter::new(Box::new(filesystem::FilesystemBackend::new(base_path))).unwrap();
+    let ns = cc.create_namespace_scoped_cfg_center("/", UpdateNotifyLevel::NoNotify, None).unwrap();
+
+    let mut whoami = HashMap::new();
+    whoami.insert("region".to_string(), Value::Str("eu".to_string()));
+    whoami.insert("build".to_string(), Value::Str("1100".to_string()));
+    whoami.insert("tenant".to_string(), Value::Str("other".to_string()));
+
+    let summary = |whoami: &HashMap<String, Value>| {
+        ns.explain_key(whoami, "banner").unwrap().iter().map(|c| {
+            let state = match &c.state {
+                CandidateState::Decides => "decides".to_string(),
+                CandidateState::Hidden => "hides".to_string(),
+                CandidateState::MergedInto(link) => format!("merged into {}", link.link_path),
+                CandidateState::ShadowedBy(link) => format!("shadowed by {}", link.link_path),
+                CandidateState::NotMatched => format!("failed at `{}`", c.failing_leaf().unwrap().node),
+            };
+            format!("{} {} {}", c.link.link_path, c.link.pri, state)
+        }).collect::<Vec<_>>()
+    };
+
+    // the beta value needs a newer build
+    assert_eq!(summary(&whoami), vec![
+        "block.json 3 failed at `tenant in [\"acme\"]`",
+        "beta.json 2 failed at `build >= 1200`",
+        "default.json 1 decides",
+    ]);
+    let values = ns.get_cfg(&whoami, &vec!["banner"], ViewMode::OverlaidView, false).unwrap();
+    assert_eq!(values[0].value.as_str().unwrap(), "{\"text\":\"hello\"}");
+
+    // the negative link hides the key
+    whoami.insert("tenant".to_string(), Value::Str("acme".to_string()));
+    assert_eq!(summary(&whoami), vec![
+        "block.json 3 hides",
+        "beta.json 2 failed at `build >= 1200`",
+        "default.json 1 shadowed by block.json",
+    ]);
+    assert_eq!(ns.get_cfg(&whoami, &vec!["banner"], ViewMode::OverlaidView, false).unwrap().len(), 0);
+    assert_eq!(ns.explain_key(&whoami, "no_such_key").unwrap().len(), 0);
+}
+
+#[test]
+fn test_merge_overlaid_view() {
+    use super::CandidateState;
+
+    let base_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join("mock_data")
+        .join("merge");
+    let cc = CFGCenter::new(Box::new(filesystem::FilesystemBackend::new(base_path))).unwrap();
+    let ns = cc.create_namespace_scoped_cfg_center("/", UpdateNotifyLevel::NoNotify, None).unwrap();
+
+    let query = |region: &str| {
+        let mut whoami = HashMap::new();
+        whoami.insert("region".to_string(), Value::Str(region.to_string()));
+        ns.get_cfg(&whoami, &vec!["servers"], ViewMode::OverlaidView, true).unwrap()
+    };
+
+    // without a merging link the value is returned as written
+    let ret = query("us");
+    assert_eq!(ret[0].value.as_str().unwrap(), "{\"timeout\": 10, \"list\": [{\"name\": \"a\", \"weight\": 1}, {\"name\": \"b\", \"weight\": 1}]}");
+    assert!(ret[0].merged.is_empty());
+
+    // the eu value is merged onto the defaults
+    let ret = query("eu");
+    assert_eq!(ret[0].value.as_str().unwrap(), "{\"list\":[{\"name\":\"a\",\"weight\":1},{\"name\":\"eu\",\"weight\":2}],\"timeout\":10}");
+    assert_eq!(ret[0].reason.as_ref().unwrap().link_path.as_str(), "eu.json");
+    assert_eq!(ret[0].merged.iter().map(|t| t.link_path.as_str()).collect::<Vec<_>>(), vec!["base.json"]);
+    let mut whoami = HashMap::new();
+    whoami.insert("region".to_string(), Value::Str("eu".to_string()));
+    let states: Vec<_> = ns.explain_key(&whoami, "servers").unwrap().iter().map(|c| match &c.state {
+        CandidateState::Decides => format!("{} decides", c.link.link_path),
+        CandidateState::MergedInto(link) => format!("{} merged into {}", c.link.link_path, link.link_path),
+        CandidateState::NotMatched => format!("{} not matched", c.link.link_path),
+        _ => panic!("unexpected state of {}", c.link.link_path),
+    }).collect();
+    assert_eq!(states, vec!["cn.json not matched", "eu.json decides", "base.json merged into eu.json"]);
+
+    // a deletion marker removes the key
+    assert!(query("cn").is_empty());
+
+    // the merged value must still match the schema, only that key fails
+    let mut whoami = HashMap::new();
+    whoami.insert("region".to_string(), Value::Str("eu".to_string()));
+    let ret = ns.get_cfg(&whoami, &vec!["limits", "servers"], ViewMode::OverlaidView, false).unwrap();
+    assert_eq!(
+        ret[0].error.as_ref().unwrap().to_string(),
+        "merged value is invalid: key `limits` of `eu.json` does not match its schema at ``: required field `rps` is missing"
+    );
+    assert_eq!((ret[0].value.key.as_str(), ret[0].value.value.len()), ("limits", 0));
+    assert!(ret[1].error.is_none());
+    assert_eq!(ret[1].value.key, "servers");
+}
+
+#[test]
+fn test_template() {
+    let base_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join("mock_data")
+        .join("template");
+    let cc = CFGCenter::new(Box::new(filesystem::FilesystemBackend::new(base_path))).unwrap();
+    let ns = cc.create_namespace_scoped_cfg_center("/", UpdateNotifyLevel::NoNotify, None).unwrap();
+
+    let mut whoami = HashMap::new();
+    whoami.insert("region".to_string(), Value::Str("eu".to_string()));
+    whoami.insert("owner".to_string(), Value::Str("a \"team\"".to_string()));
+
+    let ret = ns.get_cfg(&whoami, &vec!["cfg"], ViewMode::OverlaidView, true).unwrap();
+    assert_eq!(ret[0].value.as_str().unwrap(), r#"{"db": "db.eu.local", "owner": "a \"team\"", "doc": "${whoami.region} is the region"}"#);
+    assert!(ret[0].source.as_ref().unwrap().as_str().unwrap().starts_with(r#"{"db": "${res:/common/db.json#host}""#));
+    let ret = ns.get_cfg(&whoami, &vec!["cfg"], ViewMode::AllLinkedResView, false).unwrap();
+    assert!(ret[0].value.as_str().unwrap().starts_with(r#"{"db": "db.eu.local""#));
+    assert!(ret[0].source.is_none());
+
+    // outside a string the referenced JSON is inserted as is, the expanded value is checked
+    // against the schema of the template
+    let ret = ns.get_cfg(&whoami, &vec!["svc", "team", "bad"], ViewMode::OverlaidView, false).unwrap();
+    assert_eq!(ret[0].value.as_str().unwrap(), r#"{"db": {"host": "db.eu.local", "port": 5432}, "owner": "a \"team\""}"#);
+    assert!(ret[0].error.is_none());
+    assert_eq!(
+        ret[1].error.as_ref().unwrap().to_string(),
+        "error when expanding key `team` of `app.json`: the expanded value does not match its schema at ``: \"a \\\"team\\\"\" is not one of [\"core\",\"infra\"]"
+    );
+    assert_eq!(ret[2].error.as_ref().unwrap().to_string(), "error when expanding key `bad` of `app.json`: the expanded value is not valid json, expected value at line 1 column 11");
+    assert_eq!(ret[2].value.as_str().unwrap(), "");
+
+    // a failing template only fails its own key
+    let ret = ns.get_cfg(&whoami, &vec!["ping", "svc"], ViewMode::AllLinkedResView, false).unwrap();
+    assert_eq!(
+        ret[0].error.as_ref().unwrap().to_string(),
+        "error when expanding key `ping` of `app.json`: template references form a cycle, /app.json#ping -> /app.json#pong -> /app.json#ping"
+    );
+    assert!(ret[1].error.is_none());
+    whoami.remove("owner");
+    let ret = ns.get_cfg(&whoami, &vec!["cfg"], ViewMode::OverlaidView, false).unwrap();
+    assert_eq!(ret[0].error.as_ref().unwrap().to_string(), "error when expanding key `cfg` of `app.json`: whoami has no `owner`");
+}
+
+#[test]
+fn test_authoring_formats() {
+    let base_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join("mock_data")
+        .join("formats");
+    let cc = CFGCenter::new(Box::new(filesystem::FilesystemBackend::new(base_path))).unwrap();
+
+    let mut whoami = HashMap::new();
+    whoami.insert("region".to_string(), Value::Str("eu".to_string()));
+    whoami.insert("build".to_string(), Value::Str("1300".to_string()));
+
+    // the same config written in each format, one namespace per format
+    for format in &["json", "yaml", "toml"] {
+        let ns = cc.create_namespace_scoped_cfg_center(&format!("/{}/", format), UpdateNotifyLevel::NoNotify, None).unwrap();
+        let ret = ns.get_cfg(&whoami, &vec!["banner", "motd"], ViewMode::OverlaidView, true).unwrap();
+        let values: Vec<_> = ret.iter().map(|t| (t.value.key.as_str(), t.value.as_str().unwrap(), t.reason.as_ref().unwrap().pri)).collect();
+        assert_eq!(values, vec![("banner", "{\"text\":\"hello eu\"}", 1.5), ("motd", "welcome\nto eu\n", 1.5)], "{}", format);
+    }
+}
+
+#[test]
+fn test_payloads() {
+    let base_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join("mock_data")
+        .join("payloads");
+    let cc = CFGCenter::new(Box::new(filesystem::FilesystemBackend::new(base_path))).unwrap();
+    let ns = cc.create_namespace_scoped_cfg_center("/", UpdateNotifyLevel::NoNotify, None).unwrap();
+
+    let mut whoami = HashMap::new();
+    whoami.insert("region".to_string(), Value::Str("eu".to_string()));
+    let ret = ns.get_cfg(&whoami, &vec!["blob", "limits"], ViewMode::OverlaidView, false).unwrap();
+    // the binary value keeps its NUL byte and is not text
+    assert_eq!(ret[0].value.value, b"\0ea\xff");
+    assert_eq!(ret[0].value.as_str(), None);
+    assert_eq!(ret[1].value.as_str(), Some("{\"burst\":[1,2],\"rps\":10}"));
+}
+
+#[test]
+fn test_encrypted_values() {
+    let data_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test").join("mock_data");
+    let mut keyring = Keyring::new();
+    keyring.add_from_file("ops", &data_path.join("keys").join("ops.key")).unwrap();
+    let backend = Box::new(filesystem::FilesystemBackend::new(data_path.join("secrets")));
+    let cc = CFGCenter::new_with_options(backend, Arc::new(SystemClock), keyring).unwrap();
+    // the value sealed for a key this client does not have does not fail the namespace
+    let ns = cc.create_namespace_scoped_cfg_center("/", UpdateNotifyLevel::NoNotify, None).unwrap();
+
+    let mut whoami = HashMap::new();
+    whoami.insert("region".to_string(), Value::Str("eu".to_string()));
+    let ret = ns.get_cfg(&whoami, &vec!["db_host", "db_password", "api_token"], ViewMode::OverlaidView, false).unwrap();
+    let values: Vec<_> = ret.iter().map(|t| t.value.as_str().unwrap()).collect();
+    assert_eq!(values, vec!["db.internal", "hunter2", "{\"token\": \"abc\"}"]);
+
+    // it only fails its own key, in both views
+    for view_mode in [ViewMode::OverlaidView, ViewMode::AllLinkedResView] {
+        let ret = ns.get_cfg(&whoami, &vec!["dev_password", "db_host"], view_mode, false).unwrap();
+        assert_eq!(
+            ret[0].error.as_ref().unwrap().to_string(),
+            "can not decrypt key `dev_password` of `creds.json`: key `dev` is not in the keyring"
+        );
+        assert_eq!(ret[0].value.as_str().unwrap(), "");
+        assert!(ret[1].error.is_none());
+        assert_eq!(ret[1].value.as_str().unwrap(), "db.internal");
+    }
+
+    // a template can't copy a decrypted value into a key that is not encrypted
+    let ret = ns.get_cfg(&whoami, &vec!["db_url"], ViewMode::OverlaidView, false).unwrap();
+    assert_eq!(
+        ret[0].error.as_ref().unwrap().to_string(),
+        "error when expanding key `db_url` of `creds.json`: key `db_password` of resource `/creds.json` is encrypted, templates can not reference it"
+    );
+}
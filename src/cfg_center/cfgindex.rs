@@ -1,11 +1,16 @@
 use std::path::PathBuf;
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{HashMap, HashSet}, sync::Arc};
 
-use crate::error::MemoryIndexError;
-use crate::rule_engine::{Condition, MatchContext};
+use crate::error::{DataLoaderError, MemoryIndexError};
+use crate::rule_engine::{analyze, Condition, Finding, FindingKind, KeyInterner, LeafOperator, MatchContext, Program, Value};
 
 use crate::model;
-use crate::storage_backends::{DirItem, StorageBackend, VersionItem, WalkRetCtl};
+use crate::model::link::ObjRef;
+use crate::model::res::DataEncoding;
+use crate::model::schema::Schema;
+use crate::storage_backends::{StorageBackend, VersionItem, WalkRetCtl};
+
+use super::secret::Keyring;
 
 type Result<T> = std::result::Result<T, MemoryIndexError>;
 
@@ -13,6 +18,26 @@ pub struct CFGIndex {
     pub rule_stor: RuleIndex,
     pub res_stor: ResIndex,
     pub link_stor: LinkIndex,
+    // resources of links that pin them to a version, by the version name in the link
+    pub pinned_res_stor: HashMap<String, ResIndex>,
+}
+
+impl CFGIndex {
+    /// The first moment after `after` at which a rule some link uses starts or stops matching
+    /// because of a time window, rules no link uses can't change any result.
+    pub fn next_time_boundary(&self, after: i64) -> Option<i64> {
+        return self
+            .rule_stor
+            .next_time_boundary(after, |path| self.link_stor.get_link_by_rule_path(path).is_some());
+    }
+
+    /// The resource of a link, at the version the link pins it to.
+    pub fn get_link_res(&self, link: &IdxLinkItem) -> Option<&Resource> {
+        match &link.ver {
+            Some(ver) => return self.pinned_res_stor.get(ver.as_str())?.get_res_by_path(&link.abs_res_path),
+            None => return self.res_stor.get_res_by_path(&link.abs_res_path),
+        }
+    }
 }
 
 
@@ -20,10 +45,6 @@ pub struct CFGIndex {
 pub struct IndexBuilder {}
 
 impl IndexBuilder {
-    pub fn new() -> Self {
-        return Self {};
-    }
-
     fn load_rule(
         backend: &dyn StorageBackend,
         namespace: &str,
@@ -31,35 +52,93 @@ impl IndexBuilder {
         version: &VersionItem,
     ) -> Result<()> {
         let path =  PathBuf::from("/rules").join(namespace.strip_prefix("/").ok_or(MemoryIndexError::NamespaceNotAbsolutePath)?);
+        let mut rules = HashMap::new();
         backend.walk_dir(version, &path, &mut |cur_node| {
             if !cur_node.is_dir() {
                 let rule_raw_data = backend.get_obj_by_hash(&cur_node.hash)?;
-                let rule_obj = model::rule::Rule::load_from_slice(&rule_raw_data)?;
+                let rule_obj = model::rule::Rule::load(&rule_raw_data, model::Format::from_path(&cur_node.abs_path))
+                    .map_err(|e| e.with_path(&cur_node.abs_path.to_string_lossy()))?;
 				let str_skio_internal_prefix = cur_node.abs_path.strip_prefix("/rules").expect("should not reach here, /rules prefix must be there").to_string_lossy();
-                index.rule_stor.add_rule(&str_skio_internal_prefix, &rule_obj);
+                rules.insert(str_skio_internal_prefix.into_owned(), rule_obj);
             }
             return Ok(WalkRetCtl::Next)
         })?;
 
-        return Ok(());
+        // rules may reference each other, so they are added once all of them are loaded
+        return index.rule_stor.add_rules(rules);
     }
 
+    // Links select rules and resources by path, tag or glob, so they are loaded last and their
+    // references are resolved against the loaded rules and resources. Resources of links with a
+    // `ver` are selected from that version, which is loaded once.
     fn load_link(
         backend: &dyn StorageBackend,
         namespace: &str,
         index: &mut CFGIndex,
         version: &VersionItem,
+        keyring: &Keyring,
     ) -> Result<()> {
         let path =  PathBuf::from("/links").join(namespace.strip_prefix("/").ok_or(MemoryIndexError::NamespaceNotAbsolutePath)?);
+        let mut links = Vec::new();
         backend.walk_dir(version, &path, &mut |cur_node| {
             if !cur_node.is_dir() {
                 let link_raw_data = backend.get_obj_by_hash(&cur_node.hash)?;
-                let link_obj = model::link::Link::load_from_slice(&link_raw_data)?;
+                let link_obj = model::link::Link::load(&link_raw_data, model::Format::from_path(&cur_node.abs_path))
+                    .map_err(|e| e.with_path(&cur_node.abs_path.to_string_lossy()))?;
 				let str_skio_internal_prefix = cur_node.abs_path.strip_prefix("/links").expect("should not reach here, /links prefix must be there").to_string_lossy();
-                index.link_stor.add_link(&str_skio_internal_prefix, &link_obj);
+                links.push((str_skio_internal_prefix.into_owned(), link_obj));
             }
             return Ok(WalkRetCtl::Next)
         })?;
+        links.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (link_path, link) in links {
+            let rule_paths = index.rule_stor.select(&link.rule_ref);
+            let mut res_paths = Vec::new();
+            for (res_ref, res_ref_src) in link.res_refs.iter().zip(&link.spec.reses) {
+                let ver = match &link.spec.ver {
+                    Some(t) => t,
+                    None => {
+                        res_paths.extend(index.res_stor.select(res_ref));
+                        continue;
+                    }
+                };
+                let err = |reason: String| match res_ref {
+                    ObjRef::Path(t) => MemoryIndexError::PinnedResError {
+                        link_path: link_path.clone(),
+                        res_path: t.clone(),
+                        ver: ver.clone(),
+                        reason,
+                    },
+                    _ => MemoryIndexError::PinnedRefError {
+                        link_path: link_path.clone(),
+                        reference: res_ref_src.clone(),
+                        ver: ver.clone(),
+                        reason,
+                    },
+                };
+                if index.pinned_res_stor.contains_key(ver) == false {
+                    let pinned_version = backend.resolve_version(ver).map_err(|e| err(e.to_string()))?;
+                    let mut res_stor = ResIndex::new();
+                    Self::load_res(backend, namespace, &mut res_stor, &pinned_version, keyring).map_err(|e| err(e.to_string()))?;
+                    index.pinned_res_stor.insert(ver.clone(), res_stor);
+                }
+                let res_stor = &index.pinned_res_stor[ver];
+                if let ObjRef::Path(t) = res_ref {
+                    if res_stor.get_res_by_path(t).is_none() {
+                        return Err(err("it does not exist at this version".to_owned()));
+                    }
+                }
+                // a tag or glob that selects nothing at a pinned version is most likely a typo
+                // in `ver` or in the reference
+                let selected = res_stor.select(res_ref);
+                if selected.is_empty() {
+                    return Err(err("it selects nothing at this version".to_owned()));
+                }
+                res_paths.extend(selected);
+            }
+            index.link_stor.add_link(&link_path, &link, &rule_paths, &res_paths);
+        }
 
         return Ok(());
     }
@@ -67,16 +146,18 @@ impl IndexBuilder {
     fn load_res(
         backend: &dyn StorageBackend,
         namespace: &str,
-        index: &mut CFGIndex,
+        res_stor: &mut ResIndex,
         version: &VersionItem,
+        keyring: &Keyring,
     ) -> Result<()> {
         let path =  PathBuf::from("/reses").join(namespace.strip_prefix("/").ok_or(MemoryIndexError::NamespaceNotAbsolutePath)?);
         backend.walk_dir(version, &path, &mut |cur_node| {
             if !cur_node.is_dir() {
                 let res_raw_data = backend.get_obj_by_hash(&cur_node.hash)?;
-                let res_group_obj = model::res::Res::load_from_slice(&res_raw_data)?;
+                let res_group_obj = model::res::Res::load(&res_raw_data, model::Format::from_path(&cur_node.abs_path))
+                    .map_err(|e| e.with_path(&cur_node.abs_path.to_string_lossy()))?;
 				let str_skio_internal_prefix = cur_node.abs_path.strip_prefix("/reses").expect("should not reach here, /reses prefix must be there").to_string_lossy();
-                index.res_stor.add_res(&str_skio_internal_prefix, res_group_obj);
+                res_stor.add_res(&str_skio_internal_prefix, res_group_obj, keyring)?;
             }
             return Ok(WalkRetCtl::Next)
         })?;
@@ -84,16 +165,17 @@ impl IndexBuilder {
         return Ok(());
     }
 
-    pub fn load(backend: &dyn StorageBackend, namespace:&str, version: &VersionItem) -> Result<CFGIndex> {
+    pub fn load(backend: &dyn StorageBackend, namespace:&str, version: &VersionItem, keyring: &Keyring) -> Result<CFGIndex> {
         let mut cfg_index = CFGIndex {
             rule_stor: RuleIndex::new(),
             res_stor: ResIndex::new(),
             link_stor: LinkIndex::new(),
+            pinned_res_stor: HashMap::new(),
         };
 
         Self::load_rule(backend, namespace, &mut cfg_index, version)?;
-        Self::load_link(backend, namespace, &mut cfg_index, version)?;
-        Self::load_res(backend, namespace, &mut cfg_index, version)?;
+        Self::load_res(backend, namespace, &mut cfg_index.res_stor, version, keyring)?;
+        Self::load_link(backend, namespace, &mut cfg_index, version, keyring)?;
 		Ok(cfg_index)
     }
 }
@@ -102,17 +184,43 @@ impl IndexBuilder {
 
 pub struct IdxRuleItem {
     pub rule: Condition,
+    // `rule` compiled against the key slots of the `RuleIndex` it belongs to
+    pub program: Program,
     pub abs_path: String,
+    // what `rule_engine::analyze` found in `rule`, reported by `RuleIndex::warnings`
+    pub findings: Vec<Finding>,
+    pub tags: Vec<String>,
+}
+
+/// A likely mistake in a rule, found when the namespace was loaded. The rule is still loaded
+/// and evaluated as written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleWarning {
+    pub rule_path: String,
+    pub kind: FindingKind,
+    pub message: String,
 }
 
+// Rules are indexed by the (key, value) pairs of their string equality leaves, e.g.,
+// `region == "eu" && build >= 1200` is stored under ("region", "eu"), and a whoami without
+// region=eu never needs to evaluate it. A rule is indexed only if it can not match unless at
+// least one of its pairs is in the whoami, rules that can't be indexed are always evaluated.
 pub struct RuleIndex {
     storage: HashMap<String, Arc<IdxRuleItem>>,
+    // key -> value -> rules
+    inverted: HashMap<String, HashMap<String, Vec<Arc<IdxRuleItem>>>>,
+    unindexed: Vec<Arc<IdxRuleItem>>,
+    keys: KeyInterner,
 }
 
 impl RuleIndex {
     pub fn new() -> Self {
-        let storage = HashMap::new();
-        return Self { storage };
+        return Self {
+            storage: HashMap::new(),
+            inverted: HashMap::new(),
+            unindexed: Vec::new(),
+            keys: KeyInterner::new(),
+        };
     }
 
     pub fn add_rule(
@@ -120,19 +228,222 @@ impl RuleIndex {
         abs_path: &str,
         rule: &model::rule::Rule,
     ) {
-        self.storage.insert(
-            abs_path.to_owned(),
-            Arc::new(IdxRuleItem {
-                rule: rule.spec.rule.clone(),
-                abs_path: abs_path.to_owned(),
-            }),
-        );
+        let item = Arc::new(IdxRuleItem {
+            rule: rule.spec.rule.clone(),
+            program: Program::compile(&rule.spec.rule, &mut self.keys),
+            abs_path: abs_path.to_owned(),
+            findings: analyze(&rule.spec.rule),
+            tags: rule.meta.tags.clone(),
+        });
+
+        if let Some(old) = self.storage.insert(abs_path.to_owned(), item.clone()) {
+            self.remove_from_inverted(&old);
+        }
+
+        match index_pairs(&item.rule) {
+            Some(pairs) => {
+                for (key, value) in pairs {
+                    self.inverted
+                        .entry(key.to_owned())
+                        .or_default()
+                        .entry(value.to_owned())
+                        .or_default()
+                        .push(item.clone());
+                }
+            }
+            None => self.unindexed.push(item),
+        }
+    }
+
+    /// Add rules that may reference each other with `rule("/path")`, where the path is under
+    /// `/rules` and `rules` is keyed by the same path without the leading `/`, like links refer
+    /// to rules. Every reference is replaced by the rule it points to before the rule is
+    /// compiled, so references cost nothing at query time.
+    pub fn add_rules(&mut self, rules: HashMap<String, model::rule::Rule>) -> Result<()> {
+        let mut paths: Vec<_> = rules.keys().collect();
+        paths.sort();
+        let mut resolved = HashMap::new();
+        for path in paths {
+            Self::resolve_rule_refs(path, &rules, &mut resolved, &mut Vec::new())?;
+        }
+
+        let mut rules: Vec<_> = rules.into_iter().collect();
+        rules.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, mut rule) in rules {
+            rule.spec.rule = resolved.remove(&path).expect("every rule is resolved above");
+            self.add_rule(&path, &rule);
+        }
+        return Ok(());
     }
 
+    // `stack` holds the rules being resolved, a path that is already on it closes a cycle
+    fn resolve_rule_refs(
+        path: &str,
+        rules: &HashMap<String, model::rule::Rule>,
+        resolved: &mut HashMap<String, Condition>,
+        stack: &mut Vec<String>,
+    ) -> Result<Condition> {
+        if let Some(cond) = resolved.get(path) {
+            return Ok(cond.clone());
+        }
+        if let Some(pos) = stack.iter().position(|t| t == path) {
+            let mut cycle: Vec<_> = stack[pos..].iter().map(|t| format!("/{}", t)).collect();
+            cycle.push(format!("/{}", path));
+            return Err(MemoryIndexError::RuleRefCycle(cycle.join(" -> ")));
+        }
+
+        stack.push(path.to_owned());
+        let mut cond = rules[path].spec.rule.clone();
+        cond.inline_rule_refs(&mut |target| {
+            let target_path = target.strip_prefix('/').unwrap_or(target);
+            if rules.contains_key(target_path) == false {
+                return Err(MemoryIndexError::DanglingRuleRef {
+                    rule_path: format!("/{}", path),
+                    target: target.to_owned(),
+                });
+            }
+            return Self::resolve_rule_refs(target_path, rules, resolved, stack);
+        })?;
+        stack.pop();
+
+        resolved.insert(path.to_owned(), cond.clone());
+        return Ok(cond);
+    }
+
+    fn remove_from_inverted(&mut self, old: &Arc<IdxRuleItem>) {
+        self.unindexed.retain(|t| !Arc::ptr_eq(t, old));
+        for by_value in self.inverted.values_mut() {
+            for rules in by_value.values_mut() {
+                rules.retain(|t| !Arc::ptr_eq(t, old));
+            }
+        }
+    }
+
+    pub fn get_rule_by_path(&self, rule_path: &str) -> Option<&Arc<IdxRuleItem>> {
+        return self.storage.get(rule_path);
+    }
+
+    /// Paths of the rules selected by a link reference, sorted. A path is selected even if
+    /// there is no rule at it, so the link is kept and simply never matches.
+    pub fn select(&self, obj_ref: &ObjRef) -> Vec<String> {
+        if let ObjRef::Path(t) = obj_ref {
+            return vec![t.clone()];
+        }
+        let mut ret: Vec<_> = self
+            .storage
+            .values()
+            .filter(|t| obj_ref.is_match(&t.abs_path, &t.tags))
+            .map(|t| t.abs_path.clone())
+            .collect();
+        ret.sort();
+        return ret;
+    }
+
+    /// Warnings of all rules in this index, ordered by rule path.
+    pub fn warnings(&self) -> Vec<RuleWarning> {
+        let mut items: Vec<_> = self.storage.values().collect();
+        items.sort_by(|a, b| a.abs_path.cmp(&b.abs_path));
+
+        let mut ret = Vec::new();
+        for item in items {
+            for finding in &item.findings {
+                ret.push(RuleWarning {
+                    rule_path: item.abs_path.clone(),
+                    kind: finding.kind,
+                    message: finding.message.clone(),
+                });
+            }
+        }
+        return ret;
+    }
+
+    /// The whoami as the slot array read by the `program` of every rule in this index.
+    pub fn resolve_slots<'a>(&self, whoami: &'a MatchContext) -> Vec<Option<&'a Value>> {
+        return self.keys.resolve(whoami);
+    }
+
+    /// Call back on every rule that may match `whoami`, each rule at most once.
     pub fn iter_related_rules(&self, whoami: &MatchContext, mut cb: impl FnMut(&IdxRuleItem)) {
-        for (_, v) in &self.storage {
+        for v in &self.unindexed {
             cb(v);
         }
+
+        let mut seen = HashSet::new();
+        let mut visit = |rules: &Vec<Arc<IdxRuleItem>>| {
+            for v in rules {
+                if seen.insert(Arc::as_ptr(v)) {
+                    cb(v);
+                }
+            }
+        };
+        for (key, value) in whoami {
+            let by_value = match self.inverted.get(key) {
+                Some(t) => t,
+                None => continue,
+            };
+            match value {
+                Value::Str(s) => {
+                    if let Some(rules) = by_value.get(s) {
+                        visit(rules);
+                    }
+                }
+                // a typed whoami value may equal a string literal after conversion,
+                // e.g., Int(1) == "01", so every rule on this key is a candidate
+                _ => {
+                    for rules in by_value.values() {
+                        visit(rules);
+                    }
+                }
+            }
+        }
+    }
+
+    /// the first moment after `after` at which the time window of a rule that `is_used` opens
+    /// or closes
+    pub fn next_time_boundary(&self, after: i64, is_used: impl Fn(&str) -> bool) -> Option<i64> {
+        self.storage
+            .values()
+            .filter(|v| is_used(&v.abs_path))
+            .filter_map(|v| v.rule.next_time_boundary(after))
+            .min()
+    }
+}
+
+// The (key, value) pairs of which at least one must be in the whoami for `cond` to match,
+// None if there is no such set of pairs.
+fn index_pairs(cond: &Condition) -> Option<Vec<(&str, &str)>> {
+    match cond {
+        Condition::Leaf { lhs, op: LeafOperator::Eq, rhs: Some(Value::Str(v)) } => {
+            return Some(vec![(lhs, v)]);
+        }
+        Condition::Leaf { lhs, op: LeafOperator::In, rhs: Some(rhs) } => {
+            let items = match rhs {
+                Value::Set(set) => set.items(),
+                Value::List(items) => items,
+                _ => return None,
+            };
+            let mut pairs = Vec::with_capacity(items.len());
+            for item in items {
+                match item {
+                    Value::Str(v) => pairs.push((lhs.as_str(), v.as_str())),
+                    _ => return None,
+                }
+            }
+            return Some(pairs);
+        }
+        // any indexable child is enough, pick the most selective one
+        Condition::And(conds) => {
+            return conds.iter().filter_map(index_pairs).min_by_key(|pairs| pairs.len());
+        }
+        // every branch must be indexable, otherwise the unindexable one could match alone
+        Condition::Or(conds) => {
+            let mut pairs = Vec::new();
+            for cond in conds {
+                pairs.extend(index_pairs(cond)?);
+            }
+            return Some(pairs);
+        }
+        _ => return None,
     }
 }
 
@@ -140,9 +451,12 @@ pub struct IdxLinkItem {
     pub pri: f32,
     pub is_neg: bool,
 
-    pub rule_path: String,
+    pub rule_path: String, // like `abs_res_path`, the path of the rule even if the link selects rules by tag or glob
     pub abs_res_path: String, // the abs path in filesystem, eg, if the origin res is selected by tag, then this field should not be tag, it must be a real link object file
     pub link_path: Arc<String>,
+    // the version the resource is pinned to, None to follow the version the rest of the config
+    // is loaded from
+    pub ver: Option<Arc<String>>,
 }
 
 pub struct LinkIndex {
@@ -156,35 +470,39 @@ impl LinkIndex {
         };
     }
 
+    /// Add a link with the rules and resources its references selected, every selected rule
+    /// is linked to every selected resource.
     pub fn add_link(
         &mut self,
         link_path: &str,
         link: &model::link::Link,
+        rule_paths: &[String],
+        res_paths: &[String],
     ) {
         let arc_link_path = Arc::new(link_path.to_owned());
+        let ver = link.spec.ver.clone().map(Arc::new);
 
-        let v: Vec<_> = link
-            .spec
-            .reses
-            .iter()
-            .map(|res_path| {
+        for rule_path in rule_paths {
+            let v = res_paths.iter().map(|res_path| {
                 Arc::new(IdxLinkItem {
                     pri: link.spec.pri,
                     is_neg: link.spec.is_neg,
-                    rule_path: link.spec.rule.to_owned(),
-                    abs_res_path: remove_path_type_prefix(res_path).to_owned(),
+                    rule_path: rule_path.clone(),
+                    abs_res_path: res_path.clone(),
                     link_path: arc_link_path.clone(),
+                    ver: ver.clone(),
                 })
-            })
-            .collect();
+            });
 
+            self.idx_rule_to_res
+                .entry(rule_path.clone())
+                .or_default()
+                .extend(v);
+        }
+    }
 
-        let rule_abs_path = remove_path_type_prefix(&link.spec.rule).to_owned();
-
-        self.idx_rule_to_res
-            .entry(rule_abs_path)
-            .or_default()
-            .extend(v);
+    pub fn iter_links(&self) -> impl Iterator<Item = &Arc<IdxLinkItem>> {
+        return self.idx_rule_to_res.values().flatten();
     }
 
     pub fn get_link_by_rule_path(&self, rule_path: &str) -> Option<&Vec<Arc<IdxLinkItem>>> {
@@ -195,12 +513,64 @@ impl LinkIndex {
 pub struct KeyValuePair {
     pub content_type: String,
     pub key: String,
-    pub value: String,
+    // any bytes, text for text and JSON content types, see `model::res::DataEncoding`
+    pub value: Vec<u8>,
+    // merge `value` onto lower-priority values of the key in the overlaid view
+    pub merge: Option<model::res::MergeSpec>,
+    // None if the resource does not restrict the value
+    pub schema: Option<Arc<Schema>>,
+    // `value` has placeholders, see `template::Expander`
+    pub template: bool,
+    // `value` is sealed in the resource, templates can't copy it into other keys
+    pub encrypted: bool,
+    // why an encrypted value could not be decrypted, `value` is empty then and querying the
+    // key fails, the rest of the namespace is not affected
+    pub decrypt_error: Option<String>,
+}
+
+impl KeyValuePair {
+    /// The value as text, None if it is not valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        return std::str::from_utf8(&self.value).ok();
+    }
+
+    /// Check `value`, the parsed `self.value` or a value merged from it, against the schema.
+    pub fn check_schema(&self, res_path: &str, value: &serde_json::Value) -> std::result::Result<(), DataLoaderError> {
+        let schema = match &self.schema {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        return schema.validate(value).map_err(|e| DataLoaderError::SchemaViolation {
+            res_path: res_path.to_owned(),
+            key: self.key.clone(),
+            pointer: e.pointer,
+            reason: e.reason,
+        });
+    }
+
+    /// Check the whole value against its schema, a JSON value must be valid JSON.
+    pub fn validate(&self, res_path: &str) -> std::result::Result<(), DataLoaderError> {
+        if self.schema.is_none() {
+            return Ok(());
+        }
+        // binary values can't have a schema, so the value is text
+        let value = if DataEncoding::of(&self.content_type) == DataEncoding::Json {
+            serde_json::from_slice(&self.value).map_err(|e| DataLoaderError::SchemaViolation {
+                res_path: res_path.to_owned(),
+                key: self.key.clone(),
+                pointer: String::new(),
+                reason: format!("the value is not valid json, {}", e),
+            })?
+        } else {
+            serde_json::Value::String(String::from_utf8_lossy(&self.value).into_owned())
+        };
+        return self.check_schema(res_path, &value);
+    }
 }
 
 pub struct Resource {
     pub data: Vec<Arc<KeyValuePair>>,
-    pub(crate) res_path: Arc<String>,
+    pub tags: Vec<String>,
 }
 
 pub struct ResIndex {
@@ -214,38 +584,312 @@ impl ResIndex {
         };
     }
 
+    /// Add a resource, every value that is not merged onto others must match the `schema` of
+    /// its key. Encrypted values are decrypted with `keyring`.
     pub fn add_res(
         &mut self,
         res_path: &str,
         res: model::res::Res,
-    ) {
-        let data: Vec<_> = res
-            .spec
-            .0
-            .into_iter()
-            .map(|spec| {
-                Arc::new(KeyValuePair {
-                    content_type: spec.content_type,
-                    key: spec.key,
-                    value: spec.data,
-                })
-            })
-            .collect();
+        keyring: &Keyring,
+    ) -> std::result::Result<(), DataLoaderError> {
+        let mut data = Vec::with_capacity(res.spec.0.len());
+        for spec in res.spec.0 {
+            let schema = Schema::compile(&spec.schema).map_err(|e| {
+                DataLoaderError::SpecParseError(format!("key `{}` of `{}` has an invalid schema, {}", spec.key, res_path, e))
+            })?;
+            let (value, decrypt_error) = match (&spec.data, spec.encrypted) {
+                (serde_json::Value::String(sealed), true) => match keyring.open(sealed) {
+                    Ok(t) => (t, None),
+                    Err(reason) => (Vec::new(), Some(reason)),
+                },
+                _ => (spec.payload()?, None),
+            };
+            let kv = KeyValuePair {
+                content_type: spec.content_type,
+                key: spec.key,
+                value,
+                merge: spec.merge,
+                schema: schema.map(Arc::new),
+                template: spec.template,
+                encrypted: spec.encrypted,
+                decrypt_error,
+            };
+            // a value merged onto others may be partial, it is checked once merged, and a template
+            // is checked once expanded
+            if kv.merge.is_none() && kv.template == false && kv.decrypt_error.is_none() {
+                kv.validate(res_path)?;
+            }
+            data.push(Arc::new(kv));
+        }
         self.storage.insert(
             res_path.to_owned(),
             Resource {
                 data,
-                res_path: Arc::new(res_path.to_owned()),
+                tags: res.meta.tags,
             },
         );
+        return Ok(());
     }
 
     pub fn get_res_by_path(&self, res_path: &str) -> Option<&Resource> {
         self.storage.get(res_path)
     }
+
+    /// Paths of the resources selected by a link reference, sorted, see `RuleIndex::select`.
+    pub fn select(&self, obj_ref: &ObjRef) -> Vec<String> {
+        if let ObjRef::Path(t) = obj_ref {
+            return vec![t.clone()];
+        }
+        let mut ret: Vec<_> = self
+            .storage
+            .iter()
+            .filter(|(path, res)| obj_ref.is_match(path, &res.tags))
+            .map(|(path, _)| path.clone())
+            .collect();
+        ret.sort();
+        return ret;
+    }
+}
+
+#[test]
+fn test_rule_index() {
+    use crate::parser::rule::do_parse;
+
+    let load = |paths_and_rules: &[(&str, &str)]| {
+        let mut index = RuleIndex::new();
+        for (path, rule) in paths_and_rules {
+            let (_, mut cond) = do_parse(rule).unwrap();
+            cond.prepare().unwrap();
+            let rule = model::rule::Rule {
+                meta: model::rule::RuleMeta{desc: String::new(), tags: Vec::new()},
+                spec: model::rule::RuleSpec{rule: cond},
+            };
+            index.add_rule(path, &rule);
+        }
+        index
+    };
+    let index = load(&[
+        ("/eu", "region == \"eu\" && build >= 1200"),
+        ("/eu_or_us", "region == \"eu\" || region == \"us\""),
+        ("/in", "region in [\"cn\", \"eu\"]"),
+        ("/neg", "!(region == \"eu\")"),
+        ("/or_mixed", "region == \"eu\" || build > 10"),
+        ("/int_list", "build in [1, 2]"),
+    ]);
+    assert_eq!(index.unindexed.len(), 3);
+
+    let related = |pairs: &[(&str, Value)]| {
+        let whoami: MatchContext = pairs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+        let mut ret = Vec::new();
+        index.iter_related_rules(&whoami, |r| ret.push(r.abs_path.clone()));
+        ret.sort();
+        ret
+    };
+    let s = |v: &str| Value::Str(v.into());
+
+    assert_eq!(related(&[("region", s("eu"))]), vec!["/eu", "/eu_or_us", "/in", "/int_list", "/neg", "/or_mixed"]);
+    assert_eq!(related(&[("region", s("cn"))]), vec!["/in", "/int_list", "/neg", "/or_mixed"]);
+    assert_eq!(related(&[("region", s("jp")), ("build", s("1300"))]), vec!["/int_list", "/neg", "/or_mixed"]);
+    assert_eq!(related(&[("region", Value::Bool(true))]), vec!["/eu", "/eu_or_us", "/in", "/int_list", "/neg", "/or_mixed"]);
+
+    // candidates and full evaluation must agree
+    for region in &["eu", "us", "cn", "jp"] {
+        for build in &["1", "11", "1300"] {
+            let whoami: MatchContext = vec![("region".to_string(), s(region)), ("build".to_string(), s(build))].into_iter().collect();
+            let mut by_index = Vec::new();
+            let slots = index.resolve_slots(&whoami);
+            index.iter_related_rules(&whoami, |r| if r.program.eval(&slots, 0) { by_index.push(r.abs_path.clone()) });
+            let mut by_scan: Vec<_> = index.storage.values().filter(|r| r.rule.eval(&whoami)).map(|r| r.abs_path.clone()).collect();
+            by_index.sort();
+            by_scan.sort();
+            assert_eq!(by_index, by_scan);
+        }
+    }
+
+    // none of the rules above is suspicious, re-adding a path replaces its warnings
+    assert_eq!(index.warnings(), vec![]);
+    let index = load(&[
+        ("/b", "region == \"eu\" && region == \"us\""),
+        ("/a", "build > 10 || build > 10"),
+        ("/c", "region == \"eu\" && region == \"us\""),
+        ("/c", "region == \"eu\""),
+    ]);
+    let warnings: Vec<_> = index.warnings().into_iter().map(|w| (w.rule_path, w.kind)).collect();
+    assert_eq!(warnings, vec![("/a".to_string(), FindingKind::Duplicate), ("/b".to_string(), FindingKind::Contradiction)]);
+}
+
+#[test]
+fn test_rule_refs() {
+    use crate::parser::rule::do_parse;
+
+    let load = |paths_and_rules: &[(&str, &str)]| {
+        let rules = paths_and_rules.iter().map(|(path, rule)| {
+            let (_, mut cond) = do_parse(rule).unwrap();
+            cond.prepare().unwrap();
+            let rule = model::rule::Rule {
+                meta: model::rule::RuleMeta{desc: String::new(), tags: Vec::new()},
+                spec: model::rule::RuleSpec{rule: cond},
+            };
+            (path.to_string(), rule)
+        }).collect();
+        let mut index = RuleIndex::new();
+        index.add_rules(rules).map(|_| index)
+    };
+
+    let index = load(&[
+        ("common/prod.json", "env == \"prod\""),
+        ("common/prod_eu.json", "rule(\"/common/prod.json\") && region in [\"eu\", \"uk\"]"),
+        ("svc.json", "rule(\"/common/prod_eu.json\") && svc == \"x\" || !rule(\"/common/prod.json\")"),
+    ]).unwrap();
+    assert_eq!(
+        index.storage["svc.json"].rule.to_string(),
+        "(env == \"prod\" && region in [\"eu\", \"uk\"]) && svc == \"x\" || !env == \"prod\""
+    );
+
+    // the inlined rule is indexed like any other
+    let s = |v: &str| Value::Str(v.into());
+    let whoami: MatchContext = vec![("env".to_string(), s("prod")), ("region".to_string(), s("uk")), ("svc".to_string(), s("x"))].into_iter().collect();
+    let slots = index.resolve_slots(&whoami);
+    let mut matched = Vec::new();
+    index.iter_related_rules(&whoami, |r| if r.program.eval(&slots, 0) { matched.push(r.abs_path.clone()) });
+    matched.sort();
+    assert_eq!(matched, vec!["common/prod.json", "common/prod_eu.json", "svc.json"]);
+
+    match load(&[("a.json", "rule(\"/b.json\")"), ("b.json", "x == 1 || rule(\"/nowhere.json\")")]) {
+        Err(MemoryIndexError::DanglingRuleRef { rule_path, target }) => {
+            assert_eq!((rule_path.as_str(), target.as_str()), ("/b.json", "/nowhere.json"));
+        }
+        _ => panic!("dangling reference must fail the load"),
+    }
+    match load(&[("a.json", "rule(\"/b.json\")"), ("b.json", "x == 1 && rule(\"/c.json\")"), ("c.json", "!rule(\"/a.json\")")]) {
+        Err(e @ MemoryIndexError::RuleRefCycle(_)) => {
+            assert_eq!(e.to_string(), "error while building index: rule references form a cycle, /a.json -> /b.json -> /c.json -> /a.json");
+        }
+        _ => panic!("cycle must fail the load"),
+    }
+    assert!(matches!(load(&[("a.json", "rule(\"/a.json\")")]), Err(MemoryIndexError::RuleRefCycle(_))));
+}
+
+#[test]
+fn test_res_schema() {
+    let add = |content_type: &str, data: &str, schema: &str| {
+        let res = model::res::Res::load_from_slice(format!(
+            r#"{{"version": 1, "kind": "Res", "meta": {{"desc": "", "tags": []}},
+            "spec": [{{"content_type": "{}", "key": "k", "data": {:?}, "schema": {}}}]}}"#,
+            content_type, data, schema
+        ).as_bytes()).unwrap();
+        ResIndex::new().add_res("a/b.json", res, &Keyring::new())
+    };
+
+    assert!(add("application/json", "not json", "{}").is_ok());
+    assert!(add("application/json", r#"{"port": 80}"#, r#"{"properties": {"port": {"type": "integer"}}}"#).is_ok());
+    assert!(add("text/plain", "abc", r#"{"pattern": "^[a-z]+$"}"#).is_ok());
+    match add("application/json", r#"{"port": "80"}"#, r#"{"properties": {"port": {"type": "integer"}}}"#) {
+        Err(DataLoaderError::SchemaViolation { res_path, key, pointer, reason }) => {
+            assert_eq!((res_path.as_str(), key.as_str(), pointer.as_str()), ("a/b.json", "k", "/port"));
+            assert_eq!(reason, "expected integer, found string");
+        }
+        _ => panic!("a value that breaks its schema must fail the load"),
+    }
+    assert!(matches!(add("application/json", "not json", r#"{"type": "object"}"#), Err(DataLoaderError::SchemaViolation { .. })));
+    assert!(matches!(add("text/plain", "ABC", r#"{"pattern": "^[a-z]+$"}"#), Err(DataLoaderError::SchemaViolation { .. })));
+    assert!(matches!(add("text/plain", "abc", r#"{"minLength": -1}"#), Err(DataLoaderError::SpecParseError(_))));
+    match add("application/json", "[1, 1]", r#"{"uniqueItems": true}"#) {
+        Err(e) => assert_eq!(e.to_string(), "error parse `spec` part in config: key `k` of `a/b.json` has an invalid schema, `uniqueItems` at `` is not supported"),
+        Ok(_) => panic!("a schema keyword that is not checked must fail the load"),
+    }
+}
+
+// `test/mock_data/<name>` in memory, so a test can break a copy of it
+#[cfg(test)]
+fn mock_backend(name: &str) -> crate::storage_backends::memory::MemoryBackend {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test").join("mock_data").join(name);
+    return crate::storage_backends::memory::MemoryBackend::from_dir(&path).unwrap();
+}
+
+#[cfg(test)]
+fn load_index(backend: &dyn StorageBackend) -> Result<CFGIndex> {
+    return IndexBuilder::load(backend, "/", &backend.get_current_version().unwrap(), &Keyring::new());
+}
+
+#[test]
+fn test_pinned_res() {
+    let mut backend = mock_backend("pinned");
+    let index = load_index(&backend).unwrap();
+    let banner = |link_path: &str| {
+        let link = index.link_stor.iter_links().find(|t| t.link_path.as_str() == link_path).unwrap();
+        index.get_link_res(link).unwrap().data[0].as_str().unwrap().to_owned()
+    };
+    // the frozen link pins the banner to v1, the live one follows head
+    assert_eq!(banner("frozen.json"), "{\"text\":\"frozen\"}");
+    assert_eq!(banner("live.json"), "{\"text\":\"latest\"}");
+
+    // a version that does not exist fails the load
+    let link = String::from_utf8(backend.get("v2", "/links/frozen.json").unwrap().to_vec()).unwrap();
+    backend.insert("v2", "/links/frozen.json", link.replace("\"ver\": \"v1\"", "\"ver\": \"v9\""));
+    match load_index(&backend) {
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "error while building index: link `frozen.json` pins `banner.json` to version `v9`, version `v9` not found"
+        ),
+        Ok(_) => panic!("a link pinned to a missing version must fail the load"),
+    }
+    // so does a resource that does not exist at the pinned version
+    backend.insert("v2", "/links/frozen.json", link.replace("path:/banner.json", "path:/footer.json"));
+    match load_index(&backend) {
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "error while building index: link `frozen.json` pins `footer.json` to version `v1`, it does not exist at this version"
+        ),
+        Ok(_) => panic!("a pinned resource that does not exist must fail the load"),
+    }
+}
+
+#[test]
+fn test_select_by_tag_and_glob() {
+    let mut backend = mock_backend("tagged");
+    let index = load_index(&backend).unwrap();
+    let pairs = |link_path: &str| {
+        let mut ret: Vec<_> = index
+            .link_stor
+            .iter_links()
+            .filter(|t| t.link_path.as_str() == link_path)
+            .map(|t| format!("{} {}", t.rule_path, t.abs_res_path))
+            .collect();
+        ret.sort();
+        ret
+    };
+    // the eu link selects its rules by tag, and its resources by glob and path
+    assert_eq!(pairs("eu.json"), vec![
+        "eu/de.json banner/eu.json", "eu/de.json footer/eu.json",
+        "eu/fr.json banner/eu.json", "eu/fr.json footer/eu.json",
+    ]);
+    assert_eq!(pairs("us.json"), vec!["us.json banner/us.json"]);
+
+    // at a pinned version, a tag or glob that selects nothing fails the load
+    let link = String::from_utf8(backend.get("v1", "/links/eu.json").unwrap().to_vec()).unwrap();
+    let pinned = link.replace("\"latest:master\"", "\"v1\"");
+    backend.insert("v1", "/links/eu.json", pinned.clone());
+    assert!(load_index(&backend).is_ok());
+    backend.insert("v1", "/links/eu.json", pinned.replace("glob:/banner/eu*.json", "glob:/banner/ue*.json"));
+    match load_index(&backend) {
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "error while building index: link `eu.json` selects `glob:/banner/ue*.json` at version `v1`, it selects nothing at this version"
+        ),
+        Ok(_) => panic!("a pinned glob that selects nothing must fail the load"),
+    }
 }
 
-fn remove_path_type_prefix(i: &str) -> &str {
-    // remove `path:/`
-    return &i[6..];
+#[test]
+fn test_kind_mismatch() {
+    let mut backend = mock_backend("why_not");
+    assert!(load_index(&backend).is_ok());
+    // a resource dropped under /rules
+    let res = backend.get("v1", "/reses/beta.json").unwrap().to_vec();
+    backend.insert("v1", "/rules/stray.json", res);
+    match load_index(&backend) {
+        Err(e) => assert!(e.to_string().ends_with("/rules/stray.json: kind `Res` does not belong here, expected `Rule`"), "{}", e),
+        Ok(_) => panic!("a file of the wrong kind must fail the load"),
+    }
 }
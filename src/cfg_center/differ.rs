@@ -1,26 +1,25 @@
-use crate::{error::DifferError, rule_engine::MatchContext, storage_backends::StorageBackend};
+use crate::{error::DifferError, rule_engine::MatchContext};
 
-use super::{cfg_center::{UpdateNotifyLevel, ViewMode}, mem_store::MemStorage, querier::{CFGResult, Querier}};
+use super::{cfg_center::ViewMode, mem_store::MemStorage, querier::{CFGResult, Querier}};
 
 type Result<T> = std::result::Result<T, DifferError>;
 
 pub struct Differ<'a> {
-	notify_level: UpdateNotifyLevel,
 	old_mem_store: &'a MemStorage,
 	new_mem_store: &'a MemStorage,
-	changed_files: &'a Vec<String>, 
-	backend: &'a dyn StorageBackend, 
+	// the time at which rules are evaluated, they differ when the update is caused by a time window
+	old_now: i64,
+	new_now: i64,
 }
 
 impl <'a> Differ<'a> {
 
-	pub (crate) fn new(notify_level: UpdateNotifyLevel, old_mem_store: &'a MemStorage,new_mem_store: &'a MemStorage, changed_files: &'a Vec<String>, backend: &'a dyn StorageBackend) -> Self {
+	pub (crate) fn new(old_mem_store: &'a MemStorage,new_mem_store: &'a MemStorage, old_now: i64, new_now: i64) -> Self {
 		return Differ{
-			notify_level,
 			old_mem_store,
 			new_mem_store,
-			changed_files,
-			backend,
+			old_now,
+			new_now,
 		}
 	}
 
@@ -39,7 +38,7 @@ impl <'a> Differ<'a> {
         view_mode: ViewMode,
         need_explain: bool,
     ) -> Result<Vec<CFGResult>>{
-		Ok(Querier::get(self.old_mem_store, whoami, keys, view_mode, need_explain)?)
+		Ok(Querier::get(self.old_mem_store, whoami, self.old_now, keys, view_mode, need_explain)?)
     }
 
     pub fn get_from_new(
@@ -49,6 +48,6 @@ impl <'a> Differ<'a> {
         view_mode: ViewMode,
         need_explain: bool,
     ) -> Result<Vec<CFGResult>>{
-		Ok(Querier::get(self.new_mem_store, whoami, keys, view_mode, need_explain)?)
+		Ok(Querier::get(self.new_mem_store, whoami, self.new_now, keys, view_mode, need_explain)?)
     }
 }
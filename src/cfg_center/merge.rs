@@ -0,0 +1,141 @@
+use serde_json::{Map, Value};
+
+use crate::model::res::{ArrayMerge, MergeSpec};
+
+/// Merge `overlay` onto `base`, the value of a lower-priority link, None if there is no such
+/// value. Objects merge field by field, arrays as `spec` says, anything else in `overlay`
+/// replaces `base`. Returns None if `overlay` deletes the value.
+pub fn merge_json(base: Option<Value>, overlay: Value, spec: &MergeSpec) -> Option<Value> {
+    return merge_at(base, overlay, spec, &mut String::new());
+}
+
+// `{"$delete": true}`, or any object with it, deletes what it is merged onto
+fn is_delete_marker(v: &Value) -> bool {
+    match v {
+        Value::Object(map) => return map.get("$delete") == Some(&Value::Bool(true)),
+        _ => return false,
+    }
+}
+
+// `pointer` is the JSON pointer of `overlay`, with `*` for array elements
+fn merge_at(base: Option<Value>, overlay: Value, spec: &MergeSpec, pointer: &mut String) -> Option<Value> {
+    if is_delete_marker(&overlay) {
+        return None;
+    }
+
+    match overlay {
+        Value::Object(fields) => {
+            let mut ret = match base {
+                Some(Value::Object(t)) => t,
+                _ => Map::new(),
+            };
+            for (k, v) in fields {
+                let len = pointer.len();
+                pointer.push('/');
+                pointer.push_str(&k.replace('~', "~0").replace('/', "~1"));
+                let base_field = ret.remove(&k);
+                if let Some(t) = merge_at(base_field, v, spec, pointer) {
+                    ret.insert(k, t);
+                }
+                pointer.truncate(len);
+            }
+            return Some(Value::Object(ret));
+        }
+        Value::Array(items) => {
+            let base_items = match base {
+                Some(Value::Array(t)) => t,
+                _ => Vec::new(),
+            };
+            let strategy = spec.array_paths.get(pointer.as_str()).unwrap_or(&spec.arrays);
+
+            let len = pointer.len();
+            pointer.push_str("/*");
+            let mut ret = match strategy {
+                ArrayMerge::Replace => Vec::new(),
+                ArrayMerge::Append | ArrayMerge::MergeById(_) => base_items,
+            };
+            for item in items {
+                let pos = match strategy {
+                    ArrayMerge::MergeById(id) => match item.get(id) {
+                        Some(item_id) => ret.iter().position(|t| t.get(id) == Some(item_id)),
+                        None => None,
+                    },
+                    _ => None,
+                };
+                match pos {
+                    Some(pos) => match merge_at(Some(ret[pos].take()), item, spec, pointer) {
+                        Some(t) => ret[pos] = t,
+                        None => {
+                            ret.remove(pos);
+                        }
+                    },
+                    None => {
+                        if let Some(t) = merge_at(None, item, spec, pointer) {
+                            ret.push(t);
+                        }
+                    }
+                }
+            }
+            pointer.truncate(len);
+            return Some(Value::Array(ret));
+        }
+        t => return Some(t),
+    }
+}
+
+#[test]
+fn test_merge_json() {
+    use serde_json::json;
+
+    let spec: MergeSpec = serde_json::from_value(json!({
+        "arrays": "append",
+        "array_paths": {
+            "/servers": {"merge_by_id": "name"},
+            "/servers/*/ports": "replace"
+        }
+    }))
+    .unwrap();
+    let base = json!({
+        "timeout": 10,
+        "retry": {"times": 3, "backoff": "linear"},
+        "tags": ["a"],
+        "servers": [
+            {"name": "x", "weight": 1, "ports": [80]},
+            {"name": "y", "weight": 1, "ports": [80]}
+        ],
+        "debug": true
+    });
+    let overlay = json!({
+        "timeout": 20,
+        "retry": {"backoff": "exp"},
+        "tags": ["b", {"$delete": true}],
+        "servers": [
+            {"name": "x", "ports": [443]},
+            {"name": "y", "$delete": true},
+            {"name": "z", "weight": 2}
+        ],
+        "debug": {"$delete": true},
+        "new": {"a": {"$delete": true}, "b": 1}
+    });
+    assert_eq!(
+        merge_json(Some(base), overlay, &spec),
+        Some(json!({
+            "timeout": 20,
+            "retry": {"times": 3, "backoff": "exp"},
+            "tags": ["a", "b"],
+            "servers": [
+                {"name": "x", "weight": 1, "ports": [443]},
+                {"name": "z", "weight": 2}
+            ],
+            "new": {"b": 1}
+        }))
+    );
+
+    // anything but two objects or two arrays is replaced, markers are dropped even if there
+    // is nothing to delete
+    let spec = MergeSpec::default();
+    assert_eq!(merge_json(Some(json!({"a": 1})), json!([1, 2]), &spec), Some(json!([1, 2])));
+    assert_eq!(merge_json(Some(json!([1])), json!([2]), &spec), Some(json!([2])));
+    assert_eq!(merge_json(Some(json!("x")), json!({"a": {"$delete": true}}), &spec), Some(json!({})));
+    assert_eq!(merge_json(Some(json!({"a": 1})), json!({"$delete": true}), &spec), None);
+}
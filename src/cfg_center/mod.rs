@@ -2,23 +2,29 @@ mod namespace;
 mod cfgindex;
 mod mem_store;
 mod querier;
+#[allow(clippy::module_inception)]
 mod cfg_center;
 mod differ;
+mod merge;
+mod template;
+mod secret;
 
+#[cfg(test)]
 use core::time;
-use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
-use std::thread;
-
+#[cfg(test)]
+use std::{collections::HashMap, path::PathBuf, thread};
+#[cfg(test)]
 use crate::rule_engine::Value;
-use crate::storage_backends::{filesystem, StorageBackend, git};
+#[cfg(test)]
+use crate::storage_backends::{filesystem, git};
 
 pub use crate::cfg_center::cfg_center::{CFGCenter, ViewMode};
+pub use crate::cfg_center::cfgindex::RuleWarning;
 pub use crate::cfg_center::differ::Differ;
 pub use crate::cfg_center::namespace::NamespaceScopedCFGCenter;
 pub use cfg_center::UpdateNotifyLevel;
-pub use querier::CFGResult;
+pub use querier::{CandidateState, CFGResult, KeyCandidate};
+pub use secret::{public_key_of, seal, Keyring};
 
 #[test]
 fn test_load_res_and_query() {
@@ -27,8 +33,8 @@ fn test_load_res_and_query() {
         .join("test")
         .join("mock_data")
         .join("filesystem_backend");
-    let mut backend = Box::new(filesystem::FilesystemBackend::new(base_path));
-    let mut cc = CFGCenter::new(backend).unwrap();
+    let backend = Box::new(filesystem::FilesystemBackend::new(base_path));
+    let cc = CFGCenter::new(backend).unwrap();
     
     let cfg_ns = cc.create_namespace_scoped_cfg_center("/", UpdateNotifyLevel::NotifyWithoutChangedKeysByGlobal, Some(Box::new(|_|{println!("update...")}))).unwrap();
 
@@ -36,7 +42,7 @@ fn test_load_res_and_query() {
     let cc2 = cfg_ns.clone();
 
     let t1 = thread::spawn(move || {
-        for i in 0..6000000 {
+        for _ in 0..6000000 {
             let mut ctx = HashMap::new();
             ctx.insert("foo".to_string(), Value::Str("123".to_string()));
             ctx.insert("bar".to_string(), Value::Str("456".to_string()));
@@ -45,8 +51,8 @@ fn test_load_res_and_query() {
             let t = cc1
                 .get_cfg(&ctx, &my_key, ViewMode::OverlaidView, true).unwrap();
             
-            if t.len() > 0 {
-                println!("{}", t[0].value.value);
+            if !t.is_empty() {
+                println!("{}", String::from_utf8_lossy(&t[0].value.value));
             } else {
                 println!("no result");
             }
@@ -69,8 +75,8 @@ fn test_load_res_and_query() {
         }
     });
 
-    t1.join();
-    t2.join();
+    t1.join().unwrap();
+    t2.join().unwrap();
 
     // thread::sleep(time::Duration::from_secs(10000))
 }
@@ -81,8 +87,8 @@ fn test_load_res_and_query() {
 fn test_git_backend_load_res_and_query() {
     let base_path = PathBuf::from("/data/git_backend/.git");
     let local_git_repo = format!("{}@localhost:/data/git_backend_r/.git", env!("USER"));
-    let mut backend = Box::new(git::GitBackend::new(base_path, local_git_repo, "master".to_owned()).unwrap());
-    let mut cc = CFGCenter::new(backend).unwrap();
+    let backend = Box::new(git::GitBackend::new(base_path, local_git_repo, "master".to_owned()).unwrap());
+    let cc = CFGCenter::new(backend).unwrap();
     
     let cfg_ns = cc.create_namespace_scoped_cfg_center("/", UpdateNotifyLevel::NotifyWithoutChangedKeysByGlobal, Some(Box::new(|_|{println!("update...")}))).unwrap();
 
@@ -90,7 +96,7 @@ fn test_git_backend_load_res_and_query() {
     let cc2 = cfg_ns.clone();
 
     let t1 = thread::spawn(move || {
-        for i in 0..6000000 {
+        for _ in 0..6000000 {
             let mut ctx = HashMap::new();
             ctx.insert("foo".to_string(), Value::Str("123".to_string()));
             ctx.insert("bar".to_string(), Value::Str("456".to_string()));
@@ -99,8 +105,8 @@ fn test_git_backend_load_res_and_query() {
             let t = cc1
                 .get_cfg(&ctx, &my_key, ViewMode::OverlaidView, true).unwrap();
 
-            if t.len() > 0 {
-                println!("{}", t[0].value.value);
+            if !t.is_empty() {
+                println!("{}", String::from_utf8_lossy(&t[0].value.value));
             } else {
                 println!("no result");
             }
@@ -123,8 +129,8 @@ fn test_git_backend_load_res_and_query() {
         }
     });
 
-    t1.join();
-    t2.join();
+    t1.join().unwrap();
+    t2.join().unwrap();
 
     // thread::sleep(time::Duration::from_secs(10000))
 }
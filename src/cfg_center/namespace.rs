@@ -1,55 +1,54 @@
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
-use crate::{error::QueryError, rule_engine::MatchContext, storage_backends};
+use crate::{error::QueryError, rule_engine::{Clock, MatchContext}};
 
 use super::{
     cfg_center::{UpdateNotifyLevel, ViewMode},
-    cfgindex::IndexBuilder,
+    cfgindex::RuleWarning,
     differ::Differ,
     mem_store::MemStorage,
-    querier::{CFGResult, Querier},
+    querier::{CFGResult, KeyCandidate, Querier},
 };
 
 type Result<T> = std::result::Result<T, QueryError>;
 
-#[repr(u32)]
-pub enum UpdateInfoEventType {
-    KeyCreate = 1,
-    KeyModify = 2,
-    KeyDelete = 3,
-    KeyNotSure = 4,
-}
-
-pub struct UpdateEventItem {
-    pub event_type: UpdateInfoEventType,
-    pub key: String,
-}
+pub(crate) type UpdateCallback = Box<dyn Fn(&Differ) + Send + Sync>;
 
 pub struct NamespaceScopedCFGCenter {
-    pub(crate) namespace: String,
-    pub(crate) current_memstore: RwLock<Box<MemStorage>>,
+    // shared, so the time boundary callback can use it without holding the lock
+    pub(crate) current_memstore: RwLock<Arc<MemStorage>>,
     pub(crate) notify_level: UpdateNotifyLevel,
-    pub(crate) callback: Option<Box<dyn Fn(&Differ) + Send + Sync>>,
-    backend: Arc<dyn storage_backends::StorageBackend + Send + Sync>,
+    pub(crate) callback: Option<UpdateCallback>,
+    clock: Arc<dyn Clock>,
+    time_state: Mutex<TimeState>,
+}
+
+// when rules were last evaluated by the timer, and when a time window in them opens or closes next
+struct TimeState {
+    last_check: i64,
+    next_boundary: Option<i64>,
 }
 
 impl NamespaceScopedCFGCenter {
     pub(crate) fn new(
-        namespace: &str,
         mem_store: Box<MemStorage>,
-        backend: Arc<dyn storage_backends::StorageBackend + Send + Sync>,
         notify_level: UpdateNotifyLevel,
-        callback: Option<Box<dyn Fn(&Differ) + Send + Sync>>,
+        callback: Option<UpdateCallback>,
+        clock: Arc<dyn Clock>,
     ) -> Self {
-        let ret = NamespaceScopedCFGCenter {
-            namespace: namespace.to_owned(),
-            current_memstore: RwLock::new(mem_store),
+        let now = clock.now();
+        let time_state = Mutex::new(TimeState {
+            last_check: now,
+            next_boundary: mem_store.indices.next_time_boundary(now),
+        });
+
+        return NamespaceScopedCFGCenter {
+            current_memstore: RwLock::new(Arc::from(mem_store)),
             notify_level,
             callback,
-            backend,
+            clock,
+            time_state,
         };
-
-        ret
     }
 
     pub fn get_cfg(
@@ -60,10 +59,24 @@ impl NamespaceScopedCFGCenter {
         need_explain: bool,
     ) -> Result<Vec<CFGResult>> {
         let current_memstore = self.current_memstore.read().or(Err(QueryError::GetLockError))?;
-        Querier::get(&current_memstore, whoami, keys, view_mode, need_explain)
+        Querier::get(&current_memstore, whoami, self.clock.now(), keys, view_mode, need_explain)
+    }
+
+    /// Every link that could have provided `key` to `whoami` and why it did or did not, for
+    /// diagnosing a key that resolved to nothing or to an unexpected value.
+    pub fn explain_key(&self, whoami: &MatchContext, key: &str) -> Result<Vec<KeyCandidate>> {
+        let current_memstore = self.current_memstore.read().or(Err(QueryError::GetLockError))?;
+        return Ok(Querier::explain_key(&current_memstore, whoami, self.clock.now(), key));
+    }
+
+    /// Likely mistakes in the rules of the currently loaded config, see `rule_engine::analyze`.
+    pub fn get_rule_warnings(&self) -> Result<Vec<RuleWarning>> {
+        let current_memstore = self.current_memstore.read().or(Err(QueryError::GetLockError))?;
+        return Ok(current_memstore.indices.rule_stor.warnings());
     }
 
-    pub(crate) fn update_callback(&self, new_mem_store: Box<MemStorage>, changes: Vec<String>) {
+    pub(crate) fn update_callback(&self, new_mem_store: Box<MemStorage>) {
+        let now = self.clock.now();
         match self.notify_level {
             UpdateNotifyLevel::NotifyWithoutChangedKeysByGlobal => {
                 if let Some(cb) = &self.callback {
@@ -71,13 +84,7 @@ impl NamespaceScopedCFGCenter {
                         Ok(t) => t,
                         Err(_) => return,
                     };
-                    let differ = Differ::new(
-                        self.notify_level,
-                        &old_memstore,
-                        &new_mem_store,
-                        &changes,
-                        self.backend.as_ref(),
-                    );
+                    let differ = Differ::new(&old_memstore, &new_mem_store, now, now);
                     cb(&differ);
                 }
             }
@@ -90,6 +97,58 @@ impl NamespaceScopedCFGCenter {
             Ok(t) => t,
             Err(_) => return,
         };
-        *current_memstore = new_mem_store;
+        if let Ok(mut time_state) = self.time_state.lock() {
+            *time_state = TimeState {
+                last_check: now,
+                next_boundary: new_mem_store.indices.next_time_boundary(now),
+            };
+        }
+        *current_memstore = Arc::from(new_mem_store);
+    }
+
+    // when a time window in the rules opens or closes next
+    pub(crate) fn next_time_boundary(&self) -> Option<i64> {
+        return self.time_state.lock().ok()?.next_boundary;
+    }
+
+    /// Called by the timer of the `CFGCenter` when a time window may have opened or closed,
+    /// fire the callback if one did since the last call. The config itself did not change, so the
+    /// old and the new view of the `Differ` share one memstore and only differ in time.
+    pub(crate) fn check_time_boundary(&self) {
+        let now = self.clock.now();
+        let (memstore, last_check) = {
+            let current_memstore = match self.current_memstore.read() {
+                Ok(t) => t,
+                Err(_) => return,
+            };
+            let mut time_state = match self.time_state.lock() {
+                Ok(t) => t,
+                Err(_) => return,
+            };
+
+            let last_check = time_state.last_check;
+            time_state.last_check = now;
+            let crossed = match time_state.next_boundary {
+                Some(t) => t <= now,
+                None => false,
+            };
+            if crossed == false {
+                return;
+            }
+            time_state.next_boundary = current_memstore.indices.next_time_boundary(now);
+            (current_memstore.clone(), last_check)
+        };
+
+        // both locks are released, the callback may query the namespace, and an update may
+        // replace the memstore meanwhile
+        match self.notify_level {
+            UpdateNotifyLevel::NoNotify => {}
+            _ => {
+                if let Some(cb) = &self.callback {
+                    let differ = Differ::new(&memstore, &memstore, last_check, now);
+                    cb(&differ);
+                }
+            }
+        }
     }
 }
@@ -1,36 +1,124 @@
-use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use crate::cfg_center::mem_store::MemStorage;
-use crate::error::QueryError;
-use crate::rule_engine;
+use crate::error::{DataLoaderError, QueryError};
+use crate::model::res::DataEncoding;
+use crate::rule_engine::{self, EvalTrace};
 
 use super::ViewMode;
 use super::cfgindex::{IdxLinkItem, KeyValuePair};
+use super::merge::merge_json;
+use super::template::Expander;
 pub struct Querier {}
 
 type Result<T> = std::result::Result<T, QueryError>;
 
 pub struct CFGResult {
     pub reason: Option<Arc<IdxLinkItem>>,
+    // how the rule of `reason` evaluated against the whoami, only in explain mode
+    pub trace: Option<Arc<EvalTrace>>,
+    // the lower-priority links merged into `value`, highest priority first, only in explain mode
+    pub merged: Vec<Arc<IdxLinkItem>>,
+    // `value` as written when it is a template, only in explain mode
+    pub source: Option<Arc<KeyValuePair>>,
+    // why the key could not be resolved, `value` then only has the key and content type, the
+    // other keys of the query are not affected
+    pub error: Option<QueryError>,
     pub value: Arc<KeyValuePair>,
 }
 
+// The value of a result whose key could not be resolved from `kv_item`.
+fn failed_value(kv_item: &KeyValuePair) -> Arc<KeyValuePair> {
+    return Arc::new(KeyValuePair {
+        content_type: kv_item.content_type.clone(),
+        key: kv_item.key.clone(),
+        value: Vec::new(),
+        merge: None,
+        schema: None,
+        template: false,
+        encrypted: kv_item.encrypted,
+        decrypt_error: None,
+    });
+}
+
+/// Whether a link that has the key took part in the result, see `Querier::explain_key`.
+#[derive(Clone)]
+pub enum CandidateState {
+    // the rule matched and this link decides the key, its value is returned, with the values
+    // merged into it if any
+    Decides,
+    // the rule matched and this negative link hides the key, nothing is returned
+    Hidden,
+    // the rule matched and the value is merged into the value of the contained link
+    MergedInto(Arc<IdxLinkItem>),
+    // the rule matched, but the contained higher-priority link decides or hides the key
+    ShadowedBy(Arc<IdxLinkItem>),
+    // the rule did not match or failed to evaluate
+    NotMatched,
+}
+
+/// A link whose resource has the key, in the order the overlaid view looks at them.
+pub struct KeyCandidate {
+    pub link: Arc<IdxLinkItem>,
+    pub value: Arc<KeyValuePair>,
+    // the rule in rule syntax, None if the link points to a rule that does not exist
+    pub rule: Option<String>,
+    pub trace: Option<EvalTrace>,
+    pub state: CandidateState,
+}
+
+impl KeyCandidate {
+    /// The leaf that made the rule fail, None if the rule matched.
+    pub fn failing_leaf(&self) -> Option<&EvalTrace> {
+        match &self.trace {
+            Some(trace) if trace.result != Ok(true) => return Some(trace.deciding_atom()),
+            _ => return None,
+        }
+    }
+}
+
+// Builds the explanation of results in explain mode, the trace of a rule is shared by all
+// keys it resolves.
+struct Explainer<'a> {
+    mem_store: &'a MemStorage,
+    whoami: &'a rule_engine::MatchContext,
+    now: i64,
+    traces: HashMap<String, Arc<EvalTrace>>,
+}
+
+impl<'a> Explainer<'a> {
+    fn explain(&mut self, link: &Arc<IdxLinkItem>) -> (Option<Arc<IdxLinkItem>>, Option<Arc<EvalTrace>>) {
+        let rule_path = link.rule_path.as_str();
+        if let Some(trace) = self.traces.get(rule_path) {
+            return (Some(link.clone()), Some(trace.clone()));
+        }
+        let trace = match self.mem_store.indices.rule_stor.get_rule_by_path(rule_path) {
+            Some(rule) => Arc::new(rule.rule.trace_at(self.whoami, self.now)),
+            None => return (Some(link.clone()), None),
+        };
+        self.traces.insert(rule_path.to_owned(), trace.clone());
+        return (Some(link.clone()), Some(trace));
+    }
+}
+
 impl Querier {
     pub fn get(
         mem_store: &MemStorage,
         whoami: &rule_engine::MatchContext,
+        now: i64,
         keys: &Vec<&str>,
         view_mode: ViewMode,
         need_explain: bool,
     ) -> Result<Vec<CFGResult>> {
         let mut act_links = Vec::new();
 
+        let slots = mem_store.indices.rule_stor.resolve_slots(whoami);
         mem_store
             .indices
             .rule_stor
             .iter_related_rules(whoami, |rule| {
-                if rule.rule.eval(whoami) {
+                if rule.program.eval(&slots, now) {
 					if let Some(links) = mem_store.indices.link_stor.get_link_by_rule_path(&rule.abs_path){
 						for link in links{
 							act_links.push(link.clone());
@@ -40,75 +128,295 @@ impl Querier {
                 }
             });
 
-		if act_links.len() == 0{
+		if act_links.is_empty(){
 			return Ok(Vec::new());
 		}
         
+        let explainer = if need_explain {
+            Some(Explainer { mem_store, whoami, now, traces: HashMap::new() })
+        } else {
+            None
+        };
         let ret = match view_mode {
-            ViewMode::OverlaidView => fetch_res_by_overlaid_view(&*mem_store, keys, act_links, need_explain),
-            ViewMode::AllLinkedResView => fetch_res_by_all_linked_res_view(&*mem_store, keys, act_links, need_explain),
+            ViewMode::OverlaidView => fetch_res_by_overlaid_view(mem_store, whoami, keys, act_links, explainer)?,
+            ViewMode::AllLinkedResView => fetch_res_by_all_linked_res_view(mem_store, whoami, keys, act_links, explainer)?,
         };
         return Ok(ret);
     }
+
+    /// Tell why `key` resolved the way it did for `whoami`: every link whose resource has the
+    /// key, whether its rule matched and which link decided the key in the overlaid view.
+    pub fn explain_key(
+        mem_store: &MemStorage,
+        whoami: &rule_engine::MatchContext,
+        now: i64,
+        key: &str,
+    ) -> Vec<KeyCandidate> {
+        let indices = &mem_store.indices;
+        let mut links: Vec<_> = indices.link_stor.iter_links().cloned().collect();
+        sort_by_overlay_order(&mut links);
+
+        let mut candidates = Vec::new();
+        for link in links {
+            let value = match indices.get_link_res(&link) {
+                Some(res) => match res.data.iter().find(|kv| kv.key == key) {
+                    Some(kv) => kv.clone(),
+                    None => continue,
+                },
+                None => continue,
+            };
+            let rule = indices.rule_stor.get_rule_by_path(&link.rule_path);
+            candidates.push(KeyCandidate {
+                link,
+                value,
+                rule: rule.map(|r| r.rule.to_string()),
+                trace: rule.map(|r| r.rule.trace_at(whoami, now)),
+                state: CandidateState::NotMatched,
+            });
+        }
+
+        // the verdicts come from the same walk the overlaid view does over the matched links
+        let matched: Vec<_> = candidates
+            .iter()
+            .filter(|c| matches!(&c.trace, Some(t) if t.result == Ok(true)))
+            .map(|c| c.link.clone())
+            .collect();
+        let layers = overlay_layers(mem_store, &matched, key);
+        let decider = layers.hidden_by.or_else(|| layers.values.first().map(|(link, _)| *link)).cloned();
+        for c in &mut candidates {
+            if matched.iter().any(|t| Arc::ptr_eq(t, &c.link)) == false {
+                continue;
+            }
+            let decider = decider.clone().expect("a matched link has the key");
+            c.state = if Arc::ptr_eq(&decider, &c.link) {
+                if layers.hidden_by.is_some() {
+                    CandidateState::Hidden
+                } else {
+                    CandidateState::Decides
+                }
+            } else if layers.values.iter().skip(1).any(|(link, _)| Arc::ptr_eq(link, &c.link)) {
+                CandidateState::MergedInto(decider)
+            } else {
+                CandidateState::ShadowedBy(decider)
+            };
+        }
+        return candidates;
+    }
+}
+
+// Higher priority first, on the same priority negative links come first, then by link path so
+// the order does not depend on how the links were loaded.
+fn sort_by_overlay_order(links: &mut [Arc<IdxLinkItem>]) {
+    links.sort_by(|a, b| {
+        return b
+            .pri
+            .total_cmp(&a.pri)
+            .then(b.is_neg.cmp(&a.is_neg))
+            .then(a.link_path.cmp(&b.link_path));
+    });
+}
+
+// The values of a key in the overlaid view, see `overlay_layers`.
+struct Layers<'a> {
+    // highest priority first, all but the last one are merged onto the ones after them
+    values: Vec<(&'a Arc<IdxLinkItem>, &'a Arc<KeyValuePair>)>,
+    // the negative link that hides the key, `values` is then empty
+    hidden_by: Option<&'a Arc<IdxLinkItem>>,
+}
+
+// Walk the matched `links`, in overlay order, for the values of `key`.
+fn overlay_layers<'a>(mem_store: &'a MemStorage, links: &'a [Arc<IdxLinkItem>], key: &str) -> Layers<'a> {
+    let mut ret = Layers {
+        values: Vec::new(),
+        hidden_by: None,
+    };
+    for link in links {
+        let kv_item = match mem_store.indices.get_link_res(link) {
+            Some(res) => match res.data.iter().find(|kv| kv.key == key) {
+                Some(t) => t,
+                None => continue,
+            },
+            None => continue,
+        };
+        // a negative link hides the key, or stops merging below it
+        if link.is_neg {
+            if ret.values.is_empty() {
+                ret.hidden_by = Some(link);
+            }
+            break;
+        }
+        if !ret.values.is_empty() && DataEncoding::of(&kv_item.content_type) != DataEncoding::Json {
+            break;
+        }
+        ret.values.push((link, kv_item));
+        if kv_item.merge.is_none() {
+            break;
+        }
+    }
+    return ret;
 }
 
 fn fetch_res_by_overlaid_view(
     mem_store: &MemStorage,
+    whoami: &rule_engine::MatchContext,
     keys: &Vec<&str>,
     mut links: Vec<Arc<IdxLinkItem>>,
-    need_explain: bool,
-) -> Vec<CFGResult> {
+    mut explainer: Option<Explainer>,
+) -> Result<Vec<CFGResult>> {
     let mut ret_buf = Vec::with_capacity(keys.len());
 
-    links.sort_unstable_by(|a, b| {
-        // safety: infinate value is filtered out when loading links from storage
-        if a.pri > b.pri {
-            return Ordering::Less;
-        } else if a.pri == b.pri {
-            // if a is neg, no matter what b is, we can put a before b, if b is also neg, the order between a and b does not matter anymore
-            return if a.is_neg {
-                Ordering::Less
-            } else {
-                Ordering::Greater
+    sort_by_overlay_order(&mut links);
+
+    for key in keys {
+        let layers = overlay_layers(mem_store, &links, key).values;
+        let (link, mut value) = match layers.first() {
+            Some((link, kv_item)) => (*link, (*kv_item).clone()),
+            None => continue,
+        };
+        let (reason, trace) = match &mut explainer {
+            Some(e) => e.explain(link),
+            None => (None, None),
+        };
+        let merged = match &explainer {
+            Some(_) => layers[1..].iter().map(|(link, _)| (*link).clone()).collect(),
+            None => Vec::new(),
+        };
+        if value.merge.is_some() {
+            value = match merge_layers(&layers) {
+                Ok(Some(t)) => t,
+                // deleted by a `$delete` marker
+                Ok(None) => continue,
+                Err(e) => {
+                    ret_buf.push(CFGResult {
+                        reason,
+                        trace,
+                        merged,
+                        source: None,
+                        error: Some(e),
+                        value: failed_value(&value),
+                    });
+                    continue;
+                }
             };
-        } else {
-            return Ordering::Greater;
         }
-    });
 
-    for key in keys {
-		'next_key: 
-		for link in &links {
-			if let Some(res) = mem_store.indices.res_stor.get_res_by_path(&link.abs_res_path) {
-				for kv_item in &res.data {
-					if kv_item.key == *key {
-                        if !link.is_neg {
-                            let reason = if need_explain {
-                                Some(link.clone())
-                            } else {
-                                None
-                            };
-
-                            ret_buf.push(CFGResult {
-                                reason,
-                                value: kv_item.clone(),
-                            });
-                        }
-                        break 'next_key;
-                    }
-				}
-			}
-		}
+        let failed = failed_value(&value);
+        let (value, source, error) = match check_decrypted(link, &value).and_then(|_| expand_template(mem_store, whoami, link, value)) {
+            Ok((value, source)) => (value, source, None),
+            Err(e) => (failed, None, Some(e)),
+        };
+        ret_buf.push(CFGResult {
+            reason,
+            trace,
+            merged,
+            source: source.filter(|_| explainer.is_some()),
+            error,
+            value,
+        });
+    }
+    return Ok(ret_buf);
+}
+
+// Merge the values from the lowest priority up, None if the key is deleted.
+fn merge_layers(layers: &[(&Arc<IdxLinkItem>, &Arc<KeyValuePair>)]) -> Result<Option<Arc<KeyValuePair>>> {
+    let mut merged: Option<serde_json::Value> = None;
+    for (link, kv_item) in layers.iter().rev() {
+        check_decrypted(link, kv_item)?;
+        let value = serde_json::from_slice(&kv_item.value).map_err(|e| QueryError::MergeError {
+            link_path: link.link_path.to_string(),
+            res_path: link.abs_res_path.clone(),
+            key: kv_item.key.clone(),
+            reason: e.to_string(),
+        })?;
+        merged = match &kv_item.merge {
+            Some(spec) => merge_json(merged, value, spec),
+            None => Some(value),
+        };
+    }
+
+    // each value matched its own schema when loaded, the merged one must match the schema of
+    // the top value, once expanded if it is a template
+    let (top_link, top) = layers[0];
+    let merged = match merged {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+    if top.template == false {
+        top.check_schema(&top_link.abs_res_path, &merged)?;
+    }
+    return Ok(Some(Arc::new(KeyValuePair {
+        content_type: top.content_type.clone(),
+        key: top.key.clone(),
+        value: merged.to_string().into_bytes(),
+        merge: top.merge.clone(),
+        schema: top.schema.clone(),
+        template: top.template,
+        encrypted: top.encrypted,
+        decrypt_error: None,
+    })));
+}
+
+// An encrypted value that could not be decrypted fails only the query of its key.
+fn check_decrypted(link: &IdxLinkItem, kv_item: &KeyValuePair) -> Result<()> {
+    match &kv_item.decrypt_error {
+        Some(reason) => {
+            return Err(QueryError::SecretError {
+                res_path: link.abs_res_path.clone(),
+                key: kv_item.key.clone(),
+                reason: reason.clone(),
+            })
+        }
+        None => return Ok(()),
+    }
+}
+
+// Expand the placeholders of a template value, the value as written is returned too.
+fn expand_template(
+    mem_store: &MemStorage,
+    whoami: &rule_engine::MatchContext,
+    link: &IdxLinkItem,
+    value: Arc<KeyValuePair>,
+) -> Result<(Arc<KeyValuePair>, Option<Arc<KeyValuePair>>)> {
+    if value.template == false {
+        return Ok((value, None));
     }
-    return ret_buf;
+    let expanded = Expander::new(&mem_store.indices.res_stor, whoami)
+        .expand(&link.abs_res_path, &value)
+        .map_err(|reason| QueryError::TemplateError {
+            res_path: link.abs_res_path.clone(),
+            key: value.key.clone(),
+            reason,
+        })?;
+    let ret = Arc::new(KeyValuePair {
+        content_type: value.content_type.clone(),
+        key: value.key.clone(),
+        value: expanded.into_bytes(),
+        merge: value.merge.clone(),
+        schema: value.schema.clone(),
+        template: false,
+        encrypted: value.encrypted,
+        decrypt_error: None,
+    });
+    ret.validate(&link.abs_res_path).map_err(|e| QueryError::TemplateError {
+        res_path: link.abs_res_path.clone(),
+        key: value.key.clone(),
+        reason: match e {
+            DataLoaderError::SchemaViolation { pointer, reason, .. } => {
+                format!("the expanded value does not match its schema at `{}`: {}", pointer, reason)
+            }
+            e => e.to_string(),
+        },
+    })?;
+    return Ok((ret, Some(value)));
 }
 
 fn fetch_res_by_all_linked_res_view(
 	mem_store: &MemStorage,
+    whoami: &rule_engine::MatchContext,
     keys: &Vec<&str>,
-    mut links: Vec<Arc<IdxLinkItem>>,
-    need_explain: bool,
-) -> Vec<CFGResult> {
+    links: Vec<Arc<IdxLinkItem>>,
+    mut explainer: Option<Explainer>,
+) -> Result<Vec<CFGResult>> {
     let mut ret_buf = Vec::with_capacity(keys.len());
 
 
@@ -116,23 +424,27 @@ fn fetch_res_by_all_linked_res_view(
 	for key in keys {
 
 		for link in &links {
-			if let Some(res) = mem_store.indices.res_stor.get_res_by_path(&link.abs_res_path) {
+			if let Some(res) = mem_store.indices.get_link_res(link) {
 				for kv_item in &res.data {
 					if kv_item.key == *key {
-                        let reason = if need_explain {
-                            Some(link.clone())
-                        } else {
-                            None
+                        let (reason, trace) = match &mut explainer {
+                            Some(e) => e.explain(link),
+                            None => (None, None),
+                        };
+                        let (value, source, error) = match check_decrypted(link, kv_item)
+                            .and_then(|_| expand_template(mem_store, whoami, link, kv_item.clone()))
+                        {
+                            Ok((value, source)) => (value, source, None),
+                            Err(e) => (failed_value(kv_item), None, Some(e)),
                         };
-                        unsafe {
-                            // safety: we can ensure only one of the closure will be called, so ret_buf can be mut borrowed in to closures
-                            let mut t =
-                                &mut *(&ret_buf as *const Vec<CFGResult> as *mut Vec<CFGResult>);
-                            t.push(CFGResult {
-                                reason,
-                                value: kv_item.clone(),
-                            });
-                        }
+                        ret_buf.push(CFGResult {
+                            reason,
+                            trace,
+                            merged: Vec::new(),
+                            source: source.filter(|_| explainer.is_some()),
+                            error,
+                            value,
+                        });
                     }
 				}
 			}
@@ -141,5 +453,5 @@ fn fetch_res_by_all_linked_res_view(
 
 
     
-    return ret_buf;
+    return Ok(ret_buf);
 }
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::path::Path;
+
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use hkdf::Hkdf;
+use rand_core::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+// A value with `"encrypted": true` is sealed for the public key of a recipient:
+//
+// eafcc-sealed:v1:<kid>:<base64 of the ephemeral public key (32 bytes) and the ciphertext>
+//
+// The key id is not empty and has no `:`. The base64 is the standard alphabet with padding.
+// The ChaCha20-Poly1305 key is derived from the X25519 shared secret with HKDF-SHA256, the salt
+// is the ephemeral public key followed by the recipient's public key, the info is the prefix
+// followed by the length of the key id (4 bytes, big endian) and the key id. A new ephemeral key
+// is made for every value, so the key of the cipher is never reused and the nonce is always zero
+// (12 bytes). There is no associated data, the ciphertext ends with the 16 byte tag. Keys are
+// 32 bytes, in base64 too. `seal_value` and `public_key_of` in the FFI seal values, the README
+// has the same steps for other tools.
+pub const SEALED_PREFIX: &str = "eafcc-sealed:v1:";
+
+/// The private keys a client decrypts sealed values with, by key id.
+#[derive(Default)]
+pub struct Keyring {
+    keys: HashMap<String, StaticSecret>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn add(&mut self, kid: &str, private_key: [u8; 32]) {
+        self.keys.insert(kid.to_owned(), StaticSecret::from(private_key));
+    }
+
+    /// Add the private key in the file at `path`, written in base64.
+    pub fn add_from_file(&mut self, kid: &str, path: &Path) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("can not read key `{}` from {:?}, {}", kid, path, e))?;
+        let key = decode_key(text.trim()).map_err(|e| format!("key `{}` in {:?} {}", kid, path, e))?;
+        self.add(kid, key);
+        return Ok(());
+    }
+
+    /// Decrypt a sealed value, the error tells why it can't be, but never anything about the
+    /// plaintext.
+    pub fn open(&self, sealed: &str) -> Result<Vec<u8>, String> {
+        let rest = sealed
+            .strip_prefix(SEALED_PREFIX)
+            .ok_or_else(|| format!("the value is not sealed, expected `{}<kid>:<data>`", SEALED_PREFIX))?;
+        let (kid, data) = rest.split_once(':').ok_or("the sealed value has no key id")?;
+        let secret = self.keys.get(kid).ok_or_else(|| format!("key `{}` is not in the keyring", kid))?;
+        let data = base64::decode(data.trim()).map_err(|e| format!("the sealed value is not valid base64, {}", e))?;
+        if data.len() < 32 {
+            return Err("the sealed value is truncated".to_owned());
+        }
+        let (ephemeral, ciphertext) = data.split_at(32);
+        let ephemeral: [u8; 32] = ephemeral.try_into().expect("split at 32 bytes");
+        let ephemeral = PublicKey::from(ephemeral);
+        let cipher = cipher(kid, &secret.diffie_hellman(&ephemeral), &ephemeral, &PublicKey::from(secret));
+        return cipher
+            .decrypt(&Nonce::default(), ciphertext)
+            .map_err(|_| format!("the value can not be decrypted with key `{}`", kid));
+    }
+}
+
+/// Seal `plaintext` for the holder of the private key of `public_key`, known as `kid`. The
+/// public key is in base64, see `public_key_of`.
+pub fn seal(kid: &str, public_key: &str, plaintext: &[u8]) -> Result<String, String> {
+    if kid.is_empty() || kid.contains(':') {
+        return Err(format!("key id `{}` must not be empty or contain `:`", kid));
+    }
+    let recipient = PublicKey::from(decode_key(public_key.trim()).map_err(|e| format!("the public key {}", e))?);
+    let secret = EphemeralSecret::new(OsRng);
+    let ephemeral = PublicKey::from(&secret);
+    let cipher = cipher(kid, &secret.diffie_hellman(&recipient), &ephemeral, &recipient);
+    let ciphertext = cipher
+        .encrypt(&Nonce::default(), plaintext)
+        .expect("a value fits in a ChaCha20Poly1305 message");
+
+    let mut data = ephemeral.as_bytes().to_vec();
+    data.extend_from_slice(&ciphertext);
+    return Ok(format!("{}{}:{}", SEALED_PREFIX, kid, base64::encode(data)));
+}
+
+/// The public key of a private key, both in base64, to hand out to the authors of sealed values.
+pub fn public_key_of(private_key: &str) -> Result<String, String> {
+    let secret = StaticSecret::from(decode_key(private_key.trim()).map_err(|e| format!("the private key {}", e))?);
+    return Ok(base64::encode(PublicKey::from(&secret).as_bytes()));
+}
+
+fn decode_key(text: &str) -> Result<[u8; 32], String> {
+    let bytes = base64::decode(text).map_err(|e| format!("is not valid base64, {}", e))?;
+    return bytes.try_into().map_err(|t: Vec<u8>| format!("has {} bytes, expected 32", t.len()));
+}
+
+// the key id is authenticated too, so a value can't be passed off as sealed for another key
+fn cipher(kid: &str, shared: &x25519_dalek::SharedSecret, ephemeral: &PublicKey, recipient: &PublicKey) -> ChaCha20Poly1305 {
+    let mut salt = ephemeral.as_bytes().to_vec();
+    salt.extend_from_slice(recipient.as_bytes());
+    let mut info = SEALED_PREFIX.as_bytes().to_vec();
+    info.extend_from_slice(&(kid.len() as u32).to_be_bytes());
+    info.extend_from_slice(kid.as_bytes());
+
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(&info, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    return ChaCha20Poly1305::new(&key.into());
+}
+
+#[test]
+fn test_seal_and_open() {
+    let private = [7u8; 32];
+    let public = public_key_of(&base64::encode(private)).unwrap();
+    assert_eq!(public, base64::encode(PublicKey::from(&StaticSecret::from(private)).as_bytes()));
+    let mut keyring = Keyring::new();
+    keyring.add("ops", private);
+
+    let sealed = seal("ops", &public, b"hunter2").unwrap();
+    assert!(sealed.starts_with("eafcc-sealed:v1:ops:"));
+    assert_ne!(sealed, seal("ops", &public, b"hunter2").unwrap());
+    assert_eq!(keyring.open(&sealed), Ok(b"hunter2".to_vec()));
+
+    assert_eq!(keyring.open(&seal("dev", &public, b"x").unwrap()), Err("key `dev` is not in the keyring".to_owned()));
+    assert_eq!(seal("ops:1", &public, b"x"), Err("key id `ops:1` must not be empty or contain `:`".to_owned()));
+    assert_eq!(seal("ops", "AAAA", b"x"), Err("the public key has 3 bytes, expected 32".to_owned()));
+    // the same private key under another id does not open it either
+    keyring.add("ops-copy", private);
+    let renamed = sealed.replacen(":ops:", ":ops-copy:", 1);
+    assert_eq!(keyring.open(&renamed), Err("the value can not be decrypted with key `ops-copy`".to_owned()));
+    let mut tampered = base64::decode(sealed.rsplit(':').next().unwrap()).unwrap();
+    *tampered.last_mut().unwrap() ^= 1;
+    let tampered = format!("eafcc-sealed:v1:ops:{}", base64::encode(tampered));
+    assert_eq!(keyring.open(&tampered), Err("the value can not be decrypted with key `ops`".to_owned()));
+    assert!(keyring.open("hunter2").is_err());
+}
@@ -0,0 +1,171 @@
+use crate::model::res::DataEncoding;
+use crate::rule_engine::{MatchContext, Value};
+
+use super::cfgindex::{KeyValuePair, ResIndex};
+
+// Placeholders in a value with `"template": true`:
+//
+// ${whoami.region}          the value of `region` in the whoami of the query
+// ${res:/common/db.json#host}  the value of key `host` in resource `/common/db.json`, expanded
+//                           first if it is a template too, it can't be an encrypted value
+// $${                       a literal `${`
+//
+// In JSON values, see `DataEncoding::of`, the expanded text is escaped as the inside of a JSON string
+// when the placeholder is in a string, so `"${whoami.name}"` stays valid whatever the name is,
+// and inserted as is elsewhere, so `"db": ${res:/common/db.json#conn}` can insert an object.
+// The expanded value must be valid JSON.
+pub(crate) struct Expander<'a> {
+    pub res_stor: &'a ResIndex,
+    pub whoami: &'a MatchContext,
+    // `path#key` of the values being expanded, a reference to one of them closes a cycle
+    stack: Vec<String>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(res_stor: &'a ResIndex, whoami: &'a MatchContext) -> Self {
+        return Self {
+            res_stor,
+            whoami,
+            stack: Vec::new(),
+        };
+    }
+
+    /// Expand `kv` of the resource at `res_path`, which has no leading `/`.
+    pub fn expand(&mut self, res_path: &str, kv: &KeyValuePair) -> Result<String, String> {
+        let id = format!("/{}#{}", res_path, kv.key);
+        if let Some(pos) = self.stack.iter().position(|t| *t == id) {
+            let mut cycle = self.stack[pos..].to_vec();
+            cycle.push(id);
+            return Err(format!("template references form a cycle, {}", cycle.join(" -> ")));
+        }
+
+        // binary values can't be templates, they are rejected when loaded
+        let src = kv.as_str().ok_or("the value is not text")?;
+        let json = DataEncoding::of(&kv.content_type) == DataEncoding::Json;
+        self.stack.push(id);
+        let ret = expand_placeholders(src, json, &mut |placeholder| self.resolve(placeholder));
+        self.stack.pop();
+        let ret = ret?;
+        if json {
+            serde_json::from_str::<serde_json::Value>(&ret)
+                .map_err(|e| format!("the expanded value is not valid json, {}", e))?;
+        }
+        return Ok(ret);
+    }
+
+    fn resolve(&mut self, placeholder: &str) -> Result<String, String> {
+        if let Some(key) = placeholder.strip_prefix("whoami.") {
+            match self.whoami.get(key) {
+                Some(Value::Str(s)) => return Ok(s.clone()),
+                Some(Value::Ip(ip)) => return Ok(ip.to_string()),
+                Some(v) => return Ok(v.to_string()),
+                None => return Err(format!("whoami has no `{}`", key)),
+            }
+        }
+        if let Some(target) = placeholder.strip_prefix("res:/") {
+            let (res_path, key) = target
+                .split_once('#')
+                .ok_or_else(|| format!("`${{{}}}` has no key, expected `res:/<path>#<key>`", placeholder))?;
+            let res = self
+                .res_stor
+                .get_res_by_path(res_path)
+                .ok_or_else(|| format!("resource `/{}` does not exist", res_path))?;
+            let kv = res
+                .data
+                .iter()
+                .find(|t| t.key == key)
+                .ok_or_else(|| format!("resource `/{}` has no key `{}`", res_path, key))?;
+            // the plaintext of a sealed value must not end up in a key that is not sealed
+            if kv.encrypted {
+                return Err(format!("key `{}` of resource `/{}` is encrypted, templates can not reference it", key, res_path));
+            }
+            if kv.template {
+                return self.expand(res_path, kv);
+            }
+            return kv
+                .as_str()
+                .map(str::to_owned)
+                .ok_or_else(|| format!("key `{}` of resource `/{}` is not text", key, res_path));
+        }
+        return Err(format!(
+            "unknown placeholder `${{{}}}`, expected `whoami.<key>` or `res:/<path>#<key>`",
+            placeholder
+        ));
+    }
+}
+
+fn expand_placeholders(
+    src: &str,
+    json: bool,
+    resolve: &mut dyn FnMut(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut ret = String::with_capacity(src.len());
+    let mut rest = src;
+    // whether the text of `src` so far ends inside a JSON string, only tracked for JSON
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut track = |text: &str| {
+        for c in text.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                _ => {}
+            }
+        }
+        return in_string;
+    };
+    while let Some(pos) = rest.find('$') {
+        ret.push_str(&rest[..pos]);
+        let in_string = track(&rest[..pos]);
+        let tail = &rest[pos..];
+        if let Some(t) = tail.strip_prefix("$${") {
+            ret.push_str("${");
+            rest = t;
+        } else if let Some(t) = tail.strip_prefix("${") {
+            let end = t
+                .find('}')
+                .ok_or_else(|| format!("`${{` at offset {} is not closed", src.len() - tail.len()))?;
+            let text = resolve(&t[..end])?;
+            if json && in_string {
+                // the quotes added by serializing the text as a JSON string are dropped
+                let quoted = serde_json::Value::String(text).to_string();
+                ret.push_str(&quoted[1..quoted.len() - 1]);
+            } else {
+                ret.push_str(&text);
+            }
+            rest = &t[end + 1..];
+        } else {
+            ret.push('$');
+            rest = &tail[1..];
+        }
+    }
+    ret.push_str(rest);
+    return Ok(ret);
+}
+
+#[test]
+fn test_expand_placeholders() {
+    let mut resolve = |p: &str| match p {
+        "whoami.name" => Ok("a \"b\"".to_string()),
+        "whoami.port" => Ok("8080".to_string()),
+        _ => Err(format!("no {}", p)),
+    };
+    assert_eq!(expand_placeholders("port=${whoami.port}, $5", false, &mut resolve), Ok("port=8080, $5".to_string()));
+    assert_eq!(expand_placeholders("$${whoami.port} ${whoami.name}", false, &mut resolve), Ok("${whoami.port} a \"b\"".to_string()));
+    assert_eq!(
+        expand_placeholders(r#"{"name": "${whoami.name}", "port": ${whoami.port}}"#, true, &mut resolve),
+        Ok(r#"{"name": "a \"b\"", "port": 8080}"#.to_string())
+    );
+    // outside a string the text is inserted as is, a `"` escaped in a string does not end it
+    let mut resolve_json = |p: &str| match p {
+        "conn" => Ok(r#"{"host": "h"}"#.to_string()),
+        _ => Ok(r#"a "b""#.to_string()),
+    };
+    assert_eq!(
+        expand_placeholders(r#"{"db": ${conn}, "note": "\"${x}\" ${x}"}"#, true, &mut resolve_json),
+        Ok(r#"{"db": {"host": "h"}, "note": "\"a \"b\"\" a \"b\""}"#.to_string())
+    );
+    assert_eq!(expand_placeholders("${other}", false, &mut resolve), Err("no other".to_string()));
+    assert_eq!(expand_placeholders("ok ${whoami.port", false, &mut resolve), Err("`${` at offset 3 is not closed".to_string()));
+}
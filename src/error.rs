@@ -4,12 +4,10 @@ use std::result;
 use std::ffi::NulError;
 use std::str::Utf8Error;
 use std::io::Error as IOError;
-use std::sync::PoisonError;
 use serde_json::Error as SerdeError;
 
 use git2::Error as Git2Error;
 
-use serde_json;
 use thiserror::Error;
 
 
@@ -17,6 +15,7 @@ pub type Result<T> = result::Result<T, CCLibError>;
 
 // 顶级Error
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum CCLibError {
 	#[error("{0}")]
 	NamespaceError(&'static str),
@@ -38,6 +37,14 @@ pub enum DifferError {
 pub enum QueryError {
 	#[error("error when locking internal state")]
 	GetLockError,
+	#[error("error when merging key `{key}` of `{res_path}` from link `{link_path}`: {reason}")]
+	MergeError { link_path: String, res_path: String, key: String, reason: String },
+	#[error("merged value is invalid: {0}")]
+	InvalidMergedValue(#[from] DataLoaderError),
+	#[error("error when expanding key `{key}` of `{res_path}`: {reason}")]
+	TemplateError { res_path: String, key: String, reason: String },
+	#[error("can not decrypt key `{key}` of `{res_path}`: {reason}")]
+	SecretError { res_path: String, key: String, reason: String },
 }
 
 #[derive(Error, Debug)]
@@ -48,6 +55,14 @@ pub enum MemoryIndexError {
 	StorageBackendError(#[from] StorageBackendError),
 	#[error("error, namespace must in absolute form, i.e., start with `/`")]
 	NamespaceNotAbsolutePath,
+	#[error("error while building index: rule `{rule_path}` references `{target}`, which does not exist")]
+	DanglingRuleRef { rule_path: String, target: String },
+	#[error("error while building index: rule references form a cycle, {0}")]
+	RuleRefCycle(String),
+	#[error("error while building index: link `{link_path}` pins `{res_path}` to version `{ver}`, {reason}")]
+	PinnedResError { link_path: String, res_path: String, ver: String, reason: String },
+	#[error("error while building index: link `{link_path}` selects `{reference}` at version `{ver}`, {reason}")]
+	PinnedRefError { link_path: String, reference: String, ver: String, reason: String },
 }
 
 
@@ -56,10 +71,69 @@ pub enum MemoryIndexError {
 pub enum DataLoaderError {
 	#[error("error parse `spec` part in config: {0}")]
 	SpecParseError(String),
-	#[error("error parse json in config: {0}")]
+	#[error("error parse config: {0}")]
 	UnmarshalError(#[from] SerdeError),
+	#[error("{path}: line {line}, col {col}: expected {expected}, found {found}")]
+	RuleSyntaxError {
+		// the rule file, filled in by the index builder
+		path: String,
+		// byte offset in the rule string
+		offset: usize,
+		line: usize,
+		col: usize,
+		expected: String,
+		found: String,
+	},
+	#[error("key `{key}` of `{res_path}` does not match its schema at `{pointer}`: {reason}")]
+	SchemaViolation {
+		res_path: String,
+		key: String,
+		// JSON pointer into the value
+		pointer: String,
+		reason: String,
+	},
+	#[error("{path}: kind `{found}` does not belong here, expected `{expected}`")]
+	KindMismatch {
+		// the file, filled in by the index builder
+		path: String,
+		expected: &'static str,
+		found: String,
+	},
+	#[error("version {version} of kind `{kind}` is not supported, the newest is {newest}")]
+	UnsupportedVersion { kind: String, version: u32, newest: u32 },
+	#[error("error migrating kind `{kind}` from version {from}: {reason}")]
+	MigrationError { kind: String, from: u32, reason: String },
 }
 
+impl DataLoaderError {
+	/// Tell which file the error comes from, for errors that carry a path.
+	pub fn with_path(self, file_path: &str) -> Self {
+		match self {
+			DataLoaderError::RuleSyntaxError { offset, line, col, expected, found, .. } => {
+				return DataLoaderError::RuleSyntaxError { path: file_path.to_owned(), offset, line, col, expected, found };
+			}
+			DataLoaderError::KindMismatch { expected, found, .. } => {
+				return DataLoaderError::KindMismatch { path: file_path.to_owned(), expected, found };
+			}
+			e => return e,
+		}
+	}
+}
+
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum RuleEvalError {
+	#[error("type mismatch, can not compare {0} with {1}")]
+	TypeMismatch(&'static str, &'static str),
+	#[error("can not convert `{0}` to {1}")]
+	ConvertError(String, &'static str),
+	#[error("{0} is not supported on {1}")]
+	UnsupportedOperator(&'static str, &'static str),
+	#[error("values are not comparable")]
+	NotComparable,
+	#[error("malformed rule leaf, {0}")]
+	MalformedLeaf(&'static str),
+}
 
 #[derive(Error, Debug)]
 pub enum StorageBackendError {
@@ -79,6 +153,8 @@ pub enum StorageBackendError {
 	StripPrefixError(#[from] StripPrefixError),
 	#[error("error while internally getting lock")]	
 	LockPosionedError,
+	#[error("version `{0}` not found")]
+	VersionNotFound(String),
 }
 
 #[derive(Error, Debug)]
@@ -97,6 +173,7 @@ pub enum WalkDirError {
 }
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum FFIError {
 	#[error("error convert string between cffi: {0}")]
 	StringConvertNulError(#[from] NulError),
@@ -106,6 +183,8 @@ pub enum FFIError {
 	CreateBackendError(&'static str),
 	#[error("error building backend from config: {0}")]	
 	StorageBackendError(#[from] StorageBackendError),
+	#[error("error loading keyring from config: {0}")]
+	KeyringError(String),
 
 }
 
@@ -1,12 +1,11 @@
-use crate::cfg_center::{self, CFGResult, Differ, NamespaceScopedCFGCenter, UpdateNotifyLevel};
+use crate::cfg_center::{self, CFGResult, CandidateState, Differ, NamespaceScopedCFGCenter, UpdateNotifyLevel};
 use crate::error::FFIError;
-use crate::rule_engine::Value;
+use crate::rule_engine::{FindingKind, SystemClock, Value};
 use crate::storage_backends::{self, filesystem, git};
-use serde_json;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ffi::c_void;
-use std::ffi::{CStr, CString, NulError};
+use std::ffi::{CStr, CString};
 use std::mem::ManuallyDrop;
 use std::os::raw::c_char;
 use std::path::PathBuf;
@@ -52,12 +51,18 @@ pub extern "C" fn new_config_center_client(cfg: *const c_char) -> *const CFGCent
         Some(t) => t,
     };
 
-    let mut backend = match build_storage_backend_from_cfg(backend_cfg) {
+    let backend = match build_storage_backend_from_cfg(backend_cfg) {
         Err(e) => {set_last_error(0, e.to_string());return ptr::null()},
         Ok(t) => t,
     };
 
-    match cfg_center::CFGCenter::new(backend) {
+    // `"keyring": {"<kid>": "<path of the private key file>"}`, for encrypted values
+    let keyring = match build_keyring_from_cfg(cfg.get("keyring")) {
+        Err(e) => {set_last_error(0, e.to_string());return ptr::null()},
+        Ok(t) => t,
+    };
+
+    match cfg_center::CFGCenter::new_with_options(backend, Arc::new(SystemClock), keyring) {
         Ok(cc) => {
             let ret = Box::new(cc);
             return Box::into_raw(ret);
@@ -68,7 +73,7 @@ pub extern "C" fn new_config_center_client(cfg: *const c_char) -> *const CFGCent
 
 #[no_mangle]
 pub extern "C" fn free_config_center(cc: *mut CFGCenter) {
-    unsafe { Box::from_raw(cc) };
+    unsafe { drop(Box::from_raw(cc)) };
 }
 
 #[no_mangle]
@@ -94,13 +99,11 @@ pub extern "C" fn create_namespace(
 
     // `*const void` is not `Sned`, convert it to a normal number
     let uintptr = user_data as usize;
-    let callback = if let Some(cb) = cb {
-        Some(Box::new(move |differ: &Differ| unsafe {
+    let callback = cb.map(|cb| {
+        Box::new(move |differ: &Differ| unsafe {
             cb(differ as *const Differ, uintptr as *const c_void);
-        }) as Box<dyn Fn(&Differ) + Send + Sync>)
-    } else {
-        None
-    };
+        }) as Box<dyn Fn(&Differ) + Send + Sync>
+    });
 
 
     match cc.create_namespace_scoped_cfg_center(namespace, notify_level, callback) {
@@ -113,7 +116,7 @@ pub extern "C" fn create_namespace(
 
 #[no_mangle]
 pub extern "C" fn free_namespace(ns: *const NamespaceScopedCFGCenter) {
-    unsafe { Arc::from_raw(ns) };
+    unsafe { drop(Arc::from_raw(ns)) };
 }
 
 #[no_mangle]
@@ -125,13 +128,12 @@ pub extern "C" fn new_whoami(val: *const c_char) -> *const WhoAmI {
             ret.insert(k.trim().to_owned(), Value::Str(v.trim().to_owned()));
         }
     }
-    let t = Box::into_raw(Box::new(WhoAmI(ret)));
-    t
+    return Box::into_raw(Box::new(WhoAmI(ret)));
 }
 
 #[no_mangle]
 pub extern "C" fn free_whoami(whoami: *mut WhoAmI) {
-    unsafe { Box::from_raw(whoami) };
+    unsafe { drop(Box::from_raw(whoami)) };
 }
 
 #[repr(C)]
@@ -141,14 +143,21 @@ pub struct ConfigValueReason {
     pub link_path: *mut c_char,
     pub rule_path: *mut c_char,
     pub res_path: *mut c_char,
+    // how the rule evaluated against the whoami, one node per line, NULL if not available
+    pub trace: *mut c_char,
+    // the value before its placeholders were expanded, `ptr` is NULL if it is not a template
+    pub source: Bytes,
 }
 
 impl Drop for ConfigValueReason {
     fn drop(&mut self) {
         unsafe {
-            CString::from_raw(self.link_path);
-            CString::from_raw(self.rule_path);
-            CString::from_raw(self.res_path);
+            drop(CString::from_raw(self.link_path));
+            drop(CString::from_raw(self.rule_path));
+            drop(CString::from_raw(self.res_path));
+            if !self.trace.is_null() {
+                drop(CString::from_raw(self.trace));
+            }
         }
     }
 }
@@ -169,6 +178,41 @@ impl Drop for ConfigValues {
     }
 }
 
+// A value that may hold any bytes, NUL included, it is not NUL-terminated.
+#[repr(C)]
+pub struct Bytes {
+    pub len: usize,
+    pub ptr: *mut u8,
+}
+
+impl Bytes {
+    fn new(data: &[u8]) -> Self {
+        let len = data.len();
+        let ptr = Box::into_raw(Box::<[u8]>::from(data)) as *mut u8;
+        return Bytes { len, ptr };
+    }
+
+    fn null() -> Self {
+        return Bytes {
+            len: 0,
+            ptr: ptr::null_mut(),
+        };
+    }
+}
+
+impl Drop for Bytes {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.ptr.is_null() {
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(self.ptr, self.len)));
+            }
+        }
+    }
+}
+
+// The value `get_config` and the `differ_get_from_*` functions return, its layout is kept for
+// the callers built against it. `value` is NUL-terminated, so a binary value fails the query,
+// and a key that could not be resolved has an empty value. See `ConfigValueV2`.
 #[repr(C)]
 pub struct ConfigValue {
     key: *mut c_char,
@@ -180,11 +224,53 @@ pub struct ConfigValue {
 impl Drop for ConfigValue {
     fn drop(&mut self) {
         unsafe {
-            CString::from_raw(self.key);
-            CString::from_raw(self.content_type);
-            CString::from_raw(self.value);
+            drop(CString::from_raw(self.key));
+            drop(CString::from_raw(self.content_type));
+            drop(CString::from_raw(self.value));
+            if !self.reason.is_null() {
+                drop(Box::from_raw(self.reason));
+            }
+        }
+    }
+}
+
+// The value the `*_v2` functions return.
+#[repr(C)]
+pub struct ConfigValueV2 {
+    key: *mut c_char,
+    content_type: *mut c_char,
+    value: Bytes,
+    reason: *mut ConfigValueReason,
+    // why the key could not be resolved, `value` is empty then, NULL if it was
+    error: *mut c_char,
+}
+
+impl Drop for ConfigValueV2 {
+    fn drop(&mut self) {
+        unsafe {
+            drop(CString::from_raw(self.key));
+            drop(CString::from_raw(self.content_type));
             if !self.reason.is_null() {
-                Box::from_raw(self.reason);
+                drop(Box::from_raw(self.reason));
+            }
+            if !self.error.is_null() {
+                drop(CString::from_raw(self.error));
+            }
+        }
+    }
+}
+
+#[repr(C)]
+pub struct ConfigValuesV2 {
+    pub len: usize,
+    pub ptr: *mut ConfigValueV2,
+}
+
+impl Drop for ConfigValuesV2 {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.ptr.is_null() {
+                Vec::from_raw_parts(self.ptr, self.len, self.len);
             }
         }
     }
@@ -224,7 +310,7 @@ pub extern "C" fn get_config(
         &whoami.0,
         &keys,
         view_mode,
-        if need_explain == 0 { false } else { true },
+        need_explain != 0,
     ) {
         Ok(values) => values,
        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
@@ -239,10 +325,71 @@ pub extern "C" fn get_config(
 #[no_mangle]
 pub extern "C" fn free_config_values(v: *mut ConfigValues) {
     unsafe {
-        Box::from_raw(v);
+        drop(Box::from_raw(v));
     };
 }
 
+#[no_mangle]
+pub extern "C" fn get_config_v2(
+    ns: *const NamespaceScopedCFGCenter,
+    whoami: *const WhoAmI,
+    keys: *mut *mut c_char,
+    key_cnt: usize,
+    view_mode: ViewMode,
+    need_explain: u8,
+) -> *mut ConfigValuesV2 {
+    let ns = unsafe {
+        assert!(!ns.is_null());
+        &*ns
+    };
+    return query_v2(whoami, keys, key_cnt, |whoami, keys| ns.get_cfg(whoami, keys, view_mode, need_explain != 0));
+}
+
+#[no_mangle]
+pub extern "C" fn free_config_values_v2(v: *mut ConfigValuesV2) {
+    unsafe {
+        drop(Box::from_raw(v));
+    };
+}
+
+// Run a query for a `*_v2` function, NULL if it fails, see `get_last_error`.
+fn query_v2<E: std::fmt::Display>(
+    whoami: *const WhoAmI,
+    keys: *mut *mut c_char,
+    key_cnt: usize,
+    query: impl FnOnce(&HashMap<String, Value>, &Vec<&str>) -> std::result::Result<Vec<CFGResult>, E>,
+) -> *mut ConfigValuesV2 {
+    let (whoami, keys) = match convert_get_cfg_input_value(whoami, keys, key_cnt) {
+        Ok((whoami, keys)) => (whoami, keys),
+        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+    };
+    let values = match query(&whoami.0, &keys) {
+        Ok(values) => values,
+        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+    };
+    match convert_get_cfg_output_value_v2(values) {
+        Ok(p) => return p,
+        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+    }
+}
+
+fn build_keyring_from_cfg(cfg: Option<&serde_json::Value>) -> Result<cfg_center::Keyring> {
+    let mut keyring = cfg_center::Keyring::new();
+    let cfg = match cfg {
+        None => return Ok(keyring),
+        Some(t) => t
+            .as_object()
+            .ok_or_else(|| FFIError::KeyringError("`keyring` must be an object of key id to key file".to_string()))?,
+    };
+    for (kid, path) in cfg {
+        let path = path
+            .as_str()
+            .ok_or_else(|| FFIError::KeyringError(format!("the key file of `{}` must be a string", kid)))?;
+        keyring.add_from_file(kid, &PathBuf::from(path)).map_err(FFIError::KeyringError)?;
+    }
+    return Ok(keyring);
+}
+
 fn build_storage_backend_from_cfg(
     cfg: &serde_json::Value,
 ) -> Result<Box<dyn storage_backends::StorageBackend + Send + Sync>> {
@@ -342,29 +489,44 @@ fn convert_get_cfg_input_value<'a>(
     return Ok((whoami, key));
 }
 
+// The explanation of a value, NULL if it was not asked for.
+fn convert_reason(v: &CFGResult) -> Result<*mut ConfigValueReason> {
+    let r = match &v.reason {
+        Some(t) => t,
+        None => return Ok(ptr::null_mut()),
+    };
+    // the strings are owned until the reason is made, so an error leaks nothing
+    let trace = v.trace.as_ref().map(|t| CString::new(t.to_string())).transpose()?;
+    let rule_path = CString::new(r.rule_path.as_str())?;
+    let link_path = CString::new(r.link_path.as_str())?;
+    let res_path = CString::new(r.abs_res_path.as_str())?;
+    return Ok(Box::into_raw(Box::new(ConfigValueReason {
+        pri: r.pri,
+        is_neg: r.is_neg,
+        rule_path: rule_path.into_raw(),
+        link_path: link_path.into_raw(),
+        res_path: res_path.into_raw(),
+        trace: trace.map_or(ptr::null_mut(), CString::into_raw),
+        source: v.source.as_ref().map_or(Bytes::null(), |t| Bytes::new(&t.value)),
+    })));
+}
+
+// A value owns its pointers once it is made, and the values made so far are dropped if a later
+// one fails, so an error leaks nothing. The reason is made last for the same reason.
 #[inline(always)]
 fn convert_get_cfg_output_value(values: Vec<CFGResult>) -> Result<*mut ConfigValues> {
     let mut array_ret = Vec::with_capacity(values.len());
     for v in values {
-        let reason = match v.reason {
-            Some(r) => Box::into_raw(Box::new(ConfigValueReason {
-                pri: r.pri,
-                is_neg: r.is_neg,
-                rule_path: CString::new(r.rule_path.as_str())?.into_raw(),
-                link_path: CString::new(r.link_path.as_str())?.into_raw(),
-                res_path: CString::new(r.abs_res_path.as_str())?.into_raw(),
-            })),
-            None => ptr::null_mut(),
-        };
-
-        let item = ConfigValue {
-            content_type: CString::new(&v.value.content_type[..])?.into_raw(),
-            key: CString::new(&v.value.key[..])?.into_raw(),
-            value: CString::new(&v.value.value[..])?.into_raw(),
+        let content_type = CString::new(&v.value.content_type[..])?;
+        let key = CString::new(&v.value.key[..])?;
+        let value = CString::new(&v.value.value[..])?;
+        let reason = convert_reason(&v)?;
+        array_ret.push(ConfigValue {
+            content_type: content_type.into_raw(),
+            key: key.into_raw(),
+            value: value.into_raw(),
             reason,
-        };
-
-        array_ret.push(item);
+        });
     }
 
     array_ret.shrink_to_fit();
@@ -379,6 +541,32 @@ fn convert_get_cfg_output_value(values: Vec<CFGResult>) -> Result<*mut ConfigVal
     return Ok(ret);
 }
 
+#[inline(always)]
+fn convert_get_cfg_output_value_v2(values: Vec<CFGResult>) -> Result<*mut ConfigValuesV2> {
+    let mut array_ret = Vec::with_capacity(values.len());
+    for v in values {
+        let content_type = CString::new(&v.value.content_type[..])?;
+        let key = CString::new(&v.value.key[..])?;
+        let error = v.error.as_ref().map(|e| CString::new(e.to_string())).transpose()?;
+        let reason = convert_reason(&v)?;
+        array_ret.push(ConfigValueV2 {
+            content_type: content_type.into_raw(),
+            key: key.into_raw(),
+            value: Bytes::new(&v.value.value),
+            reason,
+            error: error.map_or(ptr::null_mut(), CString::into_raw),
+        });
+    }
+
+    array_ret.shrink_to_fit();
+    let mut array_ret = ManuallyDrop::new(array_ret);
+    let ret = Box::into_raw(Box::new(ConfigValuesV2 {
+        len: array_ret.len(),
+        ptr: array_ret.as_mut_ptr(),
+    }));
+    return Ok(ret);
+}
+
 #[no_mangle]
 pub extern "C" fn differ_get_from_old(
     differ: *const Differ,
@@ -402,7 +590,7 @@ pub extern "C" fn differ_get_from_old(
         &whoami.0,
         &keys,
         view_mode,
-        if need_explain == 0 { false } else { true },
+        need_explain != 0,
     ) {
         Ok(values) => values,
        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
@@ -437,7 +625,7 @@ pub extern "C" fn differ_get_from_new(
         &whoami.0,
         &keys,
         view_mode,
-        if need_explain == 0 { false } else { true },
+        need_explain != 0,
     ) {
         Ok(values) => values,
        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
@@ -449,6 +637,339 @@ pub extern "C" fn differ_get_from_new(
     }
 }
 
+#[no_mangle]
+pub extern "C" fn differ_get_from_old_v2(
+    differ: *const Differ,
+    whoami: *const WhoAmI,
+    keys: *mut *mut c_char,
+    key_cnt: usize,
+    view_mode: ViewMode,
+    need_explain: u8,
+) -> *mut ConfigValuesV2 {
+    let differ = unsafe {
+        assert!(!differ.is_null());
+        &*differ
+    };
+    return query_v2(whoami, keys, key_cnt, |whoami, keys| differ.get_from_old(whoami, keys, view_mode, need_explain != 0));
+}
+
+#[no_mangle]
+pub extern "C" fn differ_get_from_new_v2(
+    differ: *const Differ,
+    whoami: *const WhoAmI,
+    keys: *mut *mut c_char,
+    key_cnt: usize,
+    view_mode: ViewMode,
+    need_explain: u8,
+) -> *mut ConfigValuesV2 {
+    let differ = unsafe {
+        assert!(!differ.is_null());
+        &*differ
+    };
+    return query_v2(whoami, keys, key_cnt, |whoami, keys| differ.get_from_new(whoami, keys, view_mode, need_explain != 0));
+}
+
+#[repr(u32)]
+pub enum KeyCandidateState {
+    Decides,
+    Hidden,
+    MergedInto,
+    ShadowedBy,
+    NotMatched,
+}
+
+// A link whose resource has the key, see `NamespaceScopedCFGCenter::explain_key`.
+#[repr(C)]
+pub struct KeyCandidate {
+    pub pri: f32,
+    pub is_neg: bool,
+    pub link_path: *mut c_char,
+    pub rule_path: *mut c_char,
+    pub res_path: *mut c_char,
+    pub state: KeyCandidateState,
+    // the link the value is merged into or shadowed by, NULL for the other states
+    pub state_link_path: *mut c_char,
+    // the rule in rule syntax, NULL if the link points to a rule that does not exist
+    pub rule: *mut c_char,
+    // how the rule evaluated against the whoami, one node per line, NULL if the rule does not exist
+    pub trace: *mut c_char,
+    // the leaf that made the rule fail, NULL if the rule matched
+    pub failing_leaf: *mut c_char,
+}
+
+impl Drop for KeyCandidate {
+    fn drop(&mut self) {
+        unsafe {
+            drop(CString::from_raw(self.link_path));
+            drop(CString::from_raw(self.rule_path));
+            drop(CString::from_raw(self.res_path));
+            for p in &[self.state_link_path, self.rule, self.trace, self.failing_leaf] {
+                if !p.is_null() {
+                    drop(CString::from_raw(*p));
+                }
+            }
+        }
+    }
+}
+
+#[repr(C)]
+pub struct KeyCandidates {
+    pub len: usize,
+    pub ptr: *mut KeyCandidate,
+}
+
+impl Drop for KeyCandidates {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.ptr.is_null() {
+                Vec::from_raw_parts(self.ptr, self.len, self.len);
+            }
+        }
+    }
+}
+
+// Every link that could have provided `key` to `whoami` and why it did or did not, in the
+// order the overlaid view looks at them.
+#[no_mangle]
+pub extern "C" fn explain_key(
+    ns: *const NamespaceScopedCFGCenter,
+    whoami: *const WhoAmI,
+    key: *const c_char,
+) -> *mut KeyCandidates {
+    let ns = unsafe {
+        assert!(!ns.is_null());
+        &*ns
+    };
+    let whoami = unsafe {
+        assert!(!whoami.is_null());
+        &*whoami
+    };
+    let key = unsafe {
+        assert!(!key.is_null());
+        match CStr::from_ptr(key).to_str() {
+            Ok(t) => t,
+            Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+        }
+    };
+
+    let candidates = match ns.explain_key(&whoami.0, key) {
+        Ok(t) => t,
+        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+    };
+    match convert_key_candidates(candidates) {
+        Ok(p) => return p,
+        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_key_candidates(v: *mut KeyCandidates) {
+    unsafe {
+        drop(Box::from_raw(v));
+    };
+}
+
+fn convert_key_candidates(candidates: Vec<cfg_center::KeyCandidate>) -> Result<*mut KeyCandidates> {
+    // the strings are all made before any pointer is handed out, so an error leaks nothing
+    let mut strings = Vec::with_capacity(candidates.len());
+    for c in &candidates {
+        let (state, state_link) = match &c.state {
+            CandidateState::Decides => (KeyCandidateState::Decides, None),
+            CandidateState::Hidden => (KeyCandidateState::Hidden, None),
+            CandidateState::MergedInto(link) => (KeyCandidateState::MergedInto, Some(link)),
+            CandidateState::ShadowedBy(link) => (KeyCandidateState::ShadowedBy, Some(link)),
+            CandidateState::NotMatched => (KeyCandidateState::NotMatched, None),
+        };
+        strings.push((
+            state,
+            CString::new(c.link.link_path.as_str())?,
+            CString::new(c.link.rule_path.as_str())?,
+            CString::new(c.link.abs_res_path.as_str())?,
+            state_link.map(|t| CString::new(t.link_path.as_str())).transpose()?,
+            c.rule.as_ref().map(|t| CString::new(t.as_str())).transpose()?,
+            c.trace.as_ref().map(|t| CString::new(t.to_string())).transpose()?,
+            c.failing_leaf().map(|t| CString::new(t.node.as_str())).transpose()?,
+        ));
+    }
+
+    let into_raw = |t: Option<CString>| t.map_or(ptr::null_mut(), CString::into_raw);
+    let mut array_ret = Vec::with_capacity(candidates.len());
+    for (c, (state, link_path, rule_path, res_path, state_link_path, rule, trace, failing_leaf)) in candidates.iter().zip(strings) {
+        array_ret.push(KeyCandidate {
+            pri: c.link.pri,
+            is_neg: c.link.is_neg,
+            link_path: link_path.into_raw(),
+            rule_path: rule_path.into_raw(),
+            res_path: res_path.into_raw(),
+            state,
+            state_link_path: into_raw(state_link_path),
+            rule: into_raw(rule),
+            trace: into_raw(trace),
+            failing_leaf: into_raw(failing_leaf),
+        });
+    }
+
+    array_ret.shrink_to_fit();
+    let mut array_ret = ManuallyDrop::new(array_ret);
+    let ret = Box::into_raw(Box::new(KeyCandidates {
+        len: array_ret.len(),
+        ptr: array_ret.as_mut_ptr(),
+    }));
+    return Ok(ret);
+}
+
+#[repr(u32)]
+pub enum RuleWarningKind {
+    Contradiction,
+    Tautology,
+    Duplicate,
+    Subsumed,
+}
+
+// A likely mistake in a rule, found when the namespace was loaded, the rule is still used.
+#[repr(C)]
+pub struct RuleWarning {
+    pub rule_path: *mut c_char,
+    pub kind: RuleWarningKind,
+    pub message: *mut c_char,
+}
+
+impl Drop for RuleWarning {
+    fn drop(&mut self) {
+        unsafe {
+            drop(CString::from_raw(self.rule_path));
+            drop(CString::from_raw(self.message));
+        }
+    }
+}
+
+#[repr(C)]
+pub struct RuleWarnings {
+    pub len: usize,
+    pub ptr: *mut RuleWarning,
+}
+
+impl Drop for RuleWarnings {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.ptr.is_null() {
+                Vec::from_raw_parts(self.ptr, self.len, self.len);
+            }
+        }
+    }
+}
+
+// The warnings of the rules in the config the namespace has currently loaded.
+#[no_mangle]
+pub extern "C" fn get_rule_warnings(ns: *const NamespaceScopedCFGCenter) -> *mut RuleWarnings {
+    let ns = unsafe {
+        assert!(!ns.is_null());
+        &*ns
+    };
+
+    let warnings = match ns.get_rule_warnings() {
+        Ok(t) => t,
+        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+    };
+    match convert_rule_warnings(warnings) {
+        Ok(p) => return p,
+        Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_rule_warnings(v: *mut RuleWarnings) {
+    unsafe {
+        drop(Box::from_raw(v));
+    };
+}
+
+fn convert_rule_warnings(warnings: Vec<cfg_center::RuleWarning>) -> Result<*mut RuleWarnings> {
+    let mut strings = Vec::with_capacity(warnings.len());
+    for w in &warnings {
+        strings.push((CString::new(w.rule_path.as_str())?, CString::new(w.message.as_str())?));
+    }
+
+    let mut array_ret = Vec::with_capacity(warnings.len());
+    for (w, (rule_path, message)) in warnings.iter().zip(strings) {
+        array_ret.push(RuleWarning {
+            rule_path: rule_path.into_raw(),
+            kind: match w.kind {
+                FindingKind::Contradiction => RuleWarningKind::Contradiction,
+                FindingKind::Tautology => RuleWarningKind::Tautology,
+                FindingKind::Duplicate => RuleWarningKind::Duplicate,
+                FindingKind::Subsumed => RuleWarningKind::Subsumed,
+            },
+            message: message.into_raw(),
+        });
+    }
+
+    array_ret.shrink_to_fit();
+    let mut array_ret = ManuallyDrop::new(array_ret);
+    let ret = Box::into_raw(Box::new(RuleWarnings {
+        len: array_ret.len(),
+        ptr: array_ret.as_mut_ptr(),
+    }));
+    return Ok(ret);
+}
+
+// Seal the `len` bytes at `plaintext` for the holder of the private key of `public_key`, in
+// base64, known as `kid`. The result is the `data` of a value with `"encrypted": true`, NULL if
+// it fails, see `get_last_error`. Free it with `free_string`.
+#[no_mangle]
+pub extern "C" fn seal_value(
+    kid: *const c_char,
+    public_key: *const c_char,
+    plaintext: *const u8,
+    len: usize,
+) -> *mut c_char {
+    let (kid, public_key) = unsafe {
+        assert!(!kid.is_null() && !public_key.is_null());
+        (CStr::from_ptr(kid).to_str(), CStr::from_ptr(public_key).to_str())
+    };
+    let (kid, public_key) = match (kid, public_key) {
+        (Ok(kid), Ok(public_key)) => (kid, public_key),
+        (Err(e), _) | (_, Err(e)) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+    };
+    let plaintext = match len {
+        0 => &[][..],
+        _ => unsafe {
+            assert!(!plaintext.is_null());
+            slice::from_raw_parts(plaintext, len)
+        },
+    };
+
+    match cfg_center::seal(kid, public_key, plaintext) {
+        // base64 and a key id from a C string have no NUL
+        Ok(t) => return CString::new(t).unwrap().into_raw(),
+        Err(e) => {set_last_error(0, e);return ptr::null_mut()},
+    }
+}
+
+// The public key of a private key, both in base64, to hand out to whoever seals values for it.
+// NULL if it fails, see `get_last_error`. Free it with `free_string`.
+#[no_mangle]
+pub extern "C" fn public_key_of(private_key: *const c_char) -> *mut c_char {
+    let private_key = unsafe {
+        assert!(!private_key.is_null());
+        match CStr::from_ptr(private_key).to_str() {
+            Ok(t) => t,
+            Err(e) => {set_last_error(0, e.to_string());return ptr::null_mut()},
+        }
+    };
+    match cfg_center::public_key_of(private_key) {
+        Ok(t) => return CString::new(t).unwrap().into_raw(),
+        Err(e) => {set_last_error(0, e);return ptr::null_mut()},
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_string(s: *mut c_char) {
+    unsafe {
+        drop(CString::from_raw(s));
+    };
+}
+
 thread_local!(static LAST_ERROR: RefCell<InternalLastError> = RefCell::new(
     InternalLastError{code: 0, msg:"".to_string(), exposed_error: EAFCCError{msg:ptr::null(), code:0}, c_string:CString::default()}
 ));
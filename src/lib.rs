@@ -1,3 +1,6 @@
+// `return x;` and `x == false` are how this crate is written
+#![allow(clippy::needless_return, clippy::bool_comparison)]
+
 mod rule_engine;
 mod model;
 #[macro_use]
@@ -7,6 +10,15 @@ mod storage_backends;
 mod cfg_center;
 mod ffi;
 
+// What benches/simple_query.rs drives the library with, the library is only meant to be used
+// through the FFI, so this is not a stable API.
+#[doc(hidden)]
+pub mod bench_support {
+    pub use crate::cfg_center::{CFGCenter, UpdateNotifyLevel, ViewMode};
+    pub use crate::rule_engine::Value;
+    pub use crate::storage_backends::filesystem::FilesystemBackend;
+}
+
 static mut PRINT_BACKGROUND_WATCHER_ERROR: bool = true; 
 
 #[cfg(test)]
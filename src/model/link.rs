@@ -1,10 +1,10 @@
-use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
 use crate::error::DataLoaderError;
+use crate::rule_engine::{Pattern, PatternKind};
 
-use super::{RootCommon, object::ObjectID};
+use super::{Format, RootCommon};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct LinkMeta {
@@ -16,7 +16,10 @@ pub struct LinkMeta {
 pub struct LinkSpec {
     pub pri: f32,
     pub is_neg: bool,
-    pub ver: String,
+    // the version the resources are loaded from, None to follow the version the rest of the
+    // config is loaded from
+    #[serde(default)]
+    pub ver: Option<String>,
     pub rule: String,
     #[serde(rename = "res")]
     pub reses: Vec<String>,
@@ -24,21 +27,139 @@ pub struct LinkSpec {
 
 
 pub struct Link {
-    pub meta: LinkMeta,
     pub spec: LinkSpec,
+    // `spec.rule` and `spec.reses` parsed
+    pub rule_ref: ObjRef,
+    pub res_refs: Vec<ObjRef>,
+}
+
+/// How a link selects rules and resources, resolved against the index when it is built.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjRef {
+    // `path:/dir/a.json`, kept without the leading `/` like the keys of the index
+    Path(String),
+    // `tag:<name>`, every object that has the tag in `meta.tags`
+    Tag(String),
+    // `glob:/dir/*.json`, every object whose path matches, see `glob_to_regex`
+    Glob(Pattern),
+}
+
+impl ObjRef {
+    pub fn parse(s: &str) -> Result<ObjRef, DataLoaderError> {
+        let err = |reason: &str| DataLoaderError::SpecParseError(format!("invalid reference `{}`, {}", s, reason));
+
+        if let Some(path) = s.strip_prefix("path:") {
+            let path = path.strip_prefix('/').ok_or_else(|| err("path must start with `/`"))?;
+            return Ok(ObjRef::Path(path.to_owned()));
+        }
+        if let Some(tag) = s.strip_prefix("tag:") {
+            if tag.is_empty() {
+                return Err(err("tag is empty"));
+            }
+            return Ok(ObjRef::Tag(tag.to_owned()));
+        }
+        if let Some(glob) = s.strip_prefix("glob:") {
+            let glob = glob.strip_prefix('/').ok_or_else(|| err("glob must start with `/`"))?;
+            let pattern = Pattern::new(PatternKind::Glob, glob).map_err(|e| err(&e))?;
+            return Ok(ObjRef::Glob(pattern));
+        }
+        return Err(err("expected `path:/`, `tag:` or `glob:/`"));
+    }
+
+    /// Whether the object at `path` (without the leading `/`) with `tags` is selected.
+    pub fn is_match(&self, path: &str, tags: &[String]) -> bool {
+        match self {
+            ObjRef::Path(p) => return p == path,
+            ObjRef::Tag(tag) => return tags.iter().any(|t| t == tag),
+            ObjRef::Glob(pattern) => return pattern.is_match(path),
+        }
+    }
 }
 
 
 impl Link {
+    #[cfg(test)]
     pub fn load_from_slice(link_data: &[u8]) -> Result<Link, DataLoaderError> {
-        let root = serde_json::from_slice::<RootCommon>(link_data)?;
-        let meta = serde_json::from_value::<LinkMeta>(root.meta)?;
+        return Self::load(link_data, Format::Json);
+    }
+
+    pub fn load(link_data: &[u8], format: Format) -> Result<Link, DataLoaderError> {
+        let root = RootCommon::load(link_data, format, "Link")?;
+        // the meta is checked, but nothing reads it yet
+        serde_json::from_value::<LinkMeta>(root.meta)?;
         let spec = serde_json::from_value::<LinkSpec>(root.spec)?;
         if !spec.pri.is_finite() {
             return Err(DataLoaderError::SpecParseError(
                 "pri field is not a valid float number".to_string(),
             ));
         }
-        return Ok(Link{meta, spec})
+        if spec.ver.as_deref().is_some_and(|t| t.starts_with("latest:")) {
+            return Err(DataLoaderError::SpecParseError(
+                "`ver` must name a version, leave it out to follow the latest version".to_string(),
+            ));
+        }
+        let rule_ref = ObjRef::parse(&spec.rule)?;
+        let res_refs = spec.reses.iter().map(|t| ObjRef::parse(t)).collect::<Result<_, _>>()?;
+        return Ok(Link{spec, rule_ref, res_refs})
+    }
+}
+
+#[test]
+fn test_parse_obj_ref() {
+    let tags = vec!["eu".to_string(), "frozen".to_string()];
+
+    let r = ObjRef::parse("path:/foo/bar/1.json").unwrap();
+    assert_eq!(r, ObjRef::Path("foo/bar/1.json".to_string()));
+    assert!(r.is_match("foo/bar/1.json", &[]));
+    assert!(!r.is_match("foo/bar/2.json", &tags));
+
+    let r = ObjRef::parse("tag:eu").unwrap();
+    assert!(r.is_match("anything.json", &tags));
+    assert!(!r.is_match("anything.json", &[]));
+
+    let r = ObjRef::parse("glob:/foo/*.json").unwrap();
+    assert!(r.is_match("foo/1.json", &[]));
+    assert!(!r.is_match("foo/bar/1.json", &[]));
+    assert!(ObjRef::parse("glob:/foo/**.json").unwrap().is_match("foo/bar/1.json", &[]));
+
+    for bad in &["foo/bar/1.json", "path:foo.json", "paths:/foo.json", "tag:", "glob:/[a"] {
+        assert!(ObjRef::parse(bad).is_err(), "{}", bad);
     }
-}
\ No newline at end of file
+}
+#[test]
+fn test_load_bad_reference() {
+    let link = r#"{"version": 2, "kind": "Link", "meta": {"desc": "", "tags": []},
+        "spec": {"pri": 1, "is_neg": false, "rule": "path:/us.json", "res": ["tags:us"]}}"#;
+    // an unknown reference type fails the load instead of being cut into a wrong path
+    match Link::load_from_slice(link.as_bytes()) {
+        Err(e) => assert_eq!(
+            e.to_string(),
+            "error parse `spec` part in config: invalid reference `tags:us`, expected `path:/`, `tag:` or `glob:/`"
+        ),
+        Ok(_) => panic!("an unknown reference type must fail the load"),
+    }
+}
+
+#[test]
+fn test_load_versions() {
+    let load = |version: u32, ver: &str| {
+        Link::load_from_slice(format!(
+            r#"{{"version": {}, "kind": "Link", "meta": {{"desc": "", "tags": []}},
+            "spec": {{"pri": 1, "is_neg": false, {} "rule": "path:/a.json", "res": ["path:/a.json"]}}}}"#,
+            version, ver
+        ).as_bytes())
+    };
+
+    // version 1 says `latest:*` to follow the latest version, version 2 leaves `ver` out
+    assert_eq!(load(1, r#""ver": "latest:master","#).unwrap().spec.ver, None);
+    assert_eq!(load(1, r#""ver": "v1","#).unwrap().spec.ver.as_deref(), Some("v1"));
+    assert_eq!(load(2, "").unwrap().spec.ver, None);
+    match load(2, r#""ver": "latest:master","#) {
+        Err(e) => assert!(e.to_string().ends_with("`ver` must name a version, leave it out to follow the latest version"), "{}", e),
+        Ok(_) => panic!("`latest:*` is not a version 2 `ver`"),
+    }
+    match load(3, "") {
+        Err(e) => assert_eq!(e.to_string(), "version 3 of kind `Link` is not supported, the newest is 2"),
+        Ok(_) => panic!("a version from the future must fail"),
+    }
+}
@@ -0,0 +1,97 @@
+use serde_json::Value;
+
+use crate::error::DataLoaderError;
+
+use super::RootCommon;
+
+/// Upgrades the envelope of a `kind` from version `from` to `from + 1`.
+pub struct Migration {
+    pub kind: &'static str,
+    pub from: u32,
+    pub migrate: fn(&mut RootCommon) -> Result<(), String>,
+}
+
+// Every kind starts at version 1, a new version of a kind is added by registering the
+// migration from the version before it, the loaders only read the newest version.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { kind: "Link", from: 1, migrate: link_v1_to_v2 },
+];
+
+/// The version the loader of `kind` reads.
+pub fn newest_version(kind: &str) -> u32 {
+    return 1 + MIGRATIONS.iter().filter(|t| t.kind == kind).count() as u32;
+}
+
+/// Bring an envelope of `kind` to the newest version by running the migrations after its
+/// version in order.
+pub fn upgrade(root: &mut RootCommon, kind: &str) -> Result<(), DataLoaderError> {
+    let newest = newest_version(kind);
+    if root.version == 0 || root.version > newest {
+        return Err(DataLoaderError::UnsupportedVersion {
+            kind: kind.to_owned(),
+            version: root.version,
+            newest,
+        });
+    }
+    while root.version < newest {
+        let from = root.version;
+        let migration = MIGRATIONS
+            .iter()
+            .find(|t| t.kind == kind && t.from == from)
+            .expect("versions of a kind are registered one after another");
+        (migration.migrate)(root).map_err(|reason| DataLoaderError::MigrationError {
+            kind: kind.to_owned(),
+            from,
+            reason,
+        })?;
+        root.version = from + 1;
+    }
+    return Ok(());
+}
+
+// Version 2 links leave `ver` out to follow the version the rest of the config is loaded
+// from, version 1 links said so with any `latest:*`.
+fn link_v1_to_v2(root: &mut RootCommon) -> Result<(), String> {
+    let spec = root.spec.as_object_mut().ok_or("`spec` is not an object")?;
+    let is_latest = match spec.get("ver") {
+        Some(Value::String(t)) => t.starts_with("latest:"),
+        _ => false,
+    };
+    if is_latest {
+        spec.remove("ver");
+    }
+    return Ok(());
+}
+
+#[test]
+fn test_upgrade() {
+    use serde_json::json;
+
+    // every kind's versions must be registered one after another from 1
+    for m in MIGRATIONS {
+        assert!(m.from >= 1 && m.from < newest_version(m.kind));
+        assert_eq!(MIGRATIONS.iter().filter(|t| t.kind == m.kind && t.from == m.from).count(), 1);
+    }
+
+    let link = |version: u32, ver: &str| RootCommon {
+        version,
+        kind: "Link".to_string(),
+        meta: json!({}),
+        spec: json!({"ver": ver, "pri": 1}),
+    };
+    let mut root = link(1, "latest:master");
+    upgrade(&mut root, "Link").unwrap();
+    assert_eq!((root.version, root.spec), (2, json!({"pri": 1})));
+    let mut root = link(1, "v1");
+    upgrade(&mut root, "Link").unwrap();
+    assert_eq!((root.version, root.spec), (2, json!({"ver": "v1", "pri": 1})));
+
+    let mut root = link(3, "v1");
+    match upgrade(&mut root, "Link") {
+        Err(e) => assert_eq!(e.to_string(), "version 3 of kind `Link` is not supported, the newest is 2"),
+        Ok(_) => panic!("a version from the future must fail"),
+    }
+    let mut root = link(2, "v1");
+    root.kind = "Rule".to_string();
+    assert!(upgrade(&mut root, "Rule").is_err());
+}
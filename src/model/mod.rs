@@ -1,5 +1,9 @@
+use std::fmt::Display;
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
-use serde_json;
+
+use crate::error::DataLoaderError;
 
 #[derive(Serialize, Deserialize)]
 pub struct RootCommon {
@@ -9,7 +13,71 @@ pub struct RootCommon {
     pub spec: serde_json::Value,
 }
 
+/// The format a config file is written in, chosen by its file extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    // `.yaml`, `.yml` and `.toml`, anything else is JSON as it always was
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|t| t.to_str()) {
+            Some("yaml") | Some("yml") => return Format::Yaml,
+            Some("toml") => return Format::Toml,
+            _ => return Format::Json,
+        }
+    }
+}
+
+impl RootCommon {
+    /// Decode the envelope, `meta` and `spec` are then read the same way whatever the format.
+    pub fn from_slice(data: &[u8], format: Format) -> Result<RootCommon, DataLoaderError> {
+        match format {
+            Format::Json => return Ok(serde_json::from_slice(data)?),
+            Format::Yaml => return serde_yaml::from_slice(data).map_err(|e| decode_error("yaml", e)),
+            Format::Toml => return toml::from_slice(data).map_err(|e| decode_error("toml", e)),
+        }
+    }
+
+    /// Decode the envelope of a `kind` file, which must say it is that kind, and bring it to the
+    /// newest version of the kind, see `migration`.
+    pub fn load(data: &[u8], format: Format, kind: &'static str) -> Result<RootCommon, DataLoaderError> {
+        let mut root = Self::from_slice(data, format)?;
+        if root.kind != kind {
+            return Err(DataLoaderError::KindMismatch {
+                path: "<file>".to_owned(),
+                expected: kind,
+                found: root.kind,
+            });
+        }
+        migration::upgrade(&mut root, kind)?;
+        return Ok(root);
+    }
+}
+
+fn decode_error(format: &str, e: impl Display) -> DataLoaderError {
+    return DataLoaderError::UnmarshalError(serde::de::Error::custom(format!("invalid {}, {}", format, e)));
+}
+
 pub mod link;
+pub mod migration;
 pub mod object;
 pub mod res;
 pub mod rule;
+pub mod schema;
+
+#[test]
+fn test_format() {
+    assert_eq!(Format::from_path(Path::new("/rules/a.yml")), Format::Yaml);
+    assert_eq!(Format::from_path(Path::new("/rules/a.toml")), Format::Toml);
+    assert_eq!(Format::from_path(Path::new("/rules/a")), Format::Json);
+
+    match RootCommon::from_slice(b"version: [1", Format::Yaml) {
+        Err(e @ DataLoaderError::UnmarshalError(_)) => assert!(e.to_string().starts_with("error parse config: invalid yaml, "), "{}", e),
+        _ => panic!("broken yaml must fail"),
+    }
+    assert!(matches!(RootCommon::from_slice(b"version = 1\nkind = \"Rule\"", Format::Toml), Err(DataLoaderError::UnmarshalError(_))));
+}
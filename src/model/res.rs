@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 use serde_json;
 
 use crate::error::DataLoaderError;
 
-use super::RootCommon;
+use super::schema::Schema;
+use super::{Format, RootCommon};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct ResMeta {
@@ -18,8 +21,89 @@ pub struct ResSpec(pub Vec<ResSpecItem>);
 pub struct ResSpecItem {
     pub content_type: String,
     pub key: String,
-    pub data: String,
+    // a string, or for JSON content types any JSON value, see `payload`
+    pub data: serde_json::Value,
     pub schema: serde_json::Value,
+    // set to merge `data` onto the values of lower-priority links in the overlaid view
+    // instead of replacing them, only for `application/json`
+    #[serde(default)]
+    pub merge: Option<MergeSpec>,
+    // `data` has `${...}` placeholders, expanded when queried
+    #[serde(default)]
+    pub template: bool,
+    // `data` is sealed for the public key of the clients that may read it, the value is what
+    // `payload` would be for the content type, see `cfg_center::Keyring`
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+/// How an `application/json` value is merged onto the value below it. Objects are always
+/// merged field by field, a field set to `{"$delete": true}` removes it from the result.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MergeSpec {
+    // for arrays not in `array_paths`
+    #[serde(default)]
+    pub arrays: ArrayMerge,
+    // by the JSON pointer of the array, e.g. `/servers`, fields of array elements are under
+    // `*`, e.g. `/servers/*/ports`
+    #[serde(default)]
+    pub array_paths: HashMap<String, ArrayMerge>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArrayMerge {
+    #[default]
+    Replace,
+    Append,
+    // objects with the same value in this field are merged, an element with
+    // `"$delete": true` removes its counterpart, others are appended
+    MergeById(String),
+}
+
+/// How `data` is written, chosen by the content type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataEncoding {
+    // JSON text in a string, or the JSON value inline
+    Json,
+    // the text itself
+    Text,
+    // anything else may be binary, e.g., certificates, so it is written in base64
+    Base64,
+}
+
+impl DataEncoding {
+    pub fn of(content_type: &str) -> Self {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        if mime == "application/json" || mime.ends_with("+json") {
+            return DataEncoding::Json;
+        }
+        let is_text = mime.starts_with("text/")
+            || mime.ends_with("+xml")
+            || ["application/xml", "application/yaml", "application/x-yaml", "application/toml", "application/javascript"].contains(&mime.as_str());
+        if is_text {
+            return DataEncoding::Text;
+        }
+        return DataEncoding::Base64;
+    }
+}
+
+impl ResSpecItem {
+    /// The value as bytes, decoded according to `DataEncoding::of(content_type)`.
+    pub fn payload(&self) -> Result<Vec<u8>, DataLoaderError> {
+        let err = |reason: &str| DataLoaderError::SpecParseError(format!("`data` of key `{}` {}", self.key, reason));
+        match (DataEncoding::of(&self.content_type), &self.data) {
+            (DataEncoding::Json, serde_json::Value::String(t)) | (DataEncoding::Text, serde_json::Value::String(t)) => {
+                return Ok(t.clone().into_bytes());
+            }
+            (DataEncoding::Json, v) => return Ok(v.to_string().into_bytes()),
+            (DataEncoding::Base64, serde_json::Value::String(t)) => {
+                return base64::decode(t.trim()).map_err(|e| err(&format!("is not valid base64, {}", e)));
+            }
+            (DataEncoding::Text, _) => return Err(err(&format!("must be a string for `{}`", self.content_type))),
+            (DataEncoding::Base64, _) => return Err(err(&format!("must be a base64 string for `{}`", self.content_type))),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -29,10 +113,80 @@ pub struct Res {
 }
 
 impl Res {
+    #[cfg(test)]
     pub fn load_from_slice(res_data: &[u8]) -> Result<Res, DataLoaderError> {
-        let root = serde_json::from_slice::<RootCommon>(res_data)?;
+        return Self::load(res_data, Format::Json);
+    }
+
+    pub fn load(res_data: &[u8], format: Format) -> Result<Res, DataLoaderError> {
+        let root = RootCommon::load(res_data, format, "Res")?;
         let meta = serde_json::from_value::<ResMeta>(root.meta)?;
         let spec = serde_json::from_value::<ResSpec>(root.spec)?;
+        for item in &spec.0 {
+            if item.encrypted {
+                if item.merge.is_some() || item.template {
+                    return Err(DataLoaderError::SpecParseError(format!(
+                        "key `{}` is encrypted, `merge` and `template` are not supported on encrypted values",
+                        item.key
+                    )));
+                }
+                if item.data.is_string() == false {
+                    return Err(DataLoaderError::SpecParseError(format!(
+                        "`data` of key `{}` must be a sealed string, it is encrypted",
+                        item.key
+                    )));
+                }
+            }
+            if DataEncoding::of(&item.content_type) == DataEncoding::Base64 {
+                let is_plain = item.merge.is_none() && item.template == false && Schema::compile(&item.schema).ok().flatten().is_none();
+                if is_plain == false {
+                    return Err(DataLoaderError::SpecParseError(format!(
+                        "key `{}` is binary, `merge`, `template` and `schema` are not supported on `{}`",
+                        item.key, item.content_type
+                    )));
+                }
+            }
+            if item.merge.is_none() {
+                continue;
+            }
+            if DataEncoding::of(&item.content_type) != DataEncoding::Json {
+                return Err(DataLoaderError::SpecParseError(format!(
+                    "key `{}` can not be merged, `merge` is only supported on JSON content types",
+                    item.key
+                )));
+            }
+            if let serde_json::Value::String(t) = &item.data {
+                serde_json::from_str::<serde_json::Value>(t).map_err(|e| {
+                    DataLoaderError::SpecParseError(format!("key `{}` can not be merged, data is not valid json: {}", item.key, e))
+                })?;
+            }
+        }
         return Ok(Res { meta, spec });
     }
 }
+
+#[test]
+fn test_payload() {
+    let item = |content_type: &str, data: serde_json::Value| ResSpecItem {
+        content_type: content_type.to_string(),
+        key: "k".to_string(),
+        data,
+        schema: serde_json::json!({}),
+        merge: None,
+        template: false,
+        encrypted: false,
+    };
+
+    assert_eq!(DataEncoding::of("text/plain; charset=utf-8"), DataEncoding::Text);
+    assert_eq!(DataEncoding::of("application/problem+json"), DataEncoding::Json);
+    assert_eq!(DataEncoding::of("application/x-pem-file"), DataEncoding::Base64);
+
+    assert_eq!(item("application/json", serde_json::json!("{\"a\": 1}")).payload().unwrap(), b"{\"a\": 1}");
+    assert_eq!(item("application/json", serde_json::json!({"a": [1, null]})).payload().unwrap(), b"{\"a\":[1,null]}");
+    assert_eq!(item("text/plain", serde_json::json!("a\nb")).payload().unwrap(), b"a\nb");
+    assert_eq!(item("application/octet-stream", serde_json::json!("AGVh/w==")).payload().unwrap(), b"\0ea\xff");
+
+    assert!(item("text/plain", serde_json::json!(1)).payload().is_err());
+    assert!(item("application/octet-stream", serde_json::json!("not base64!")).payload().is_err());
+    assert!(item("application/octet-stream", serde_json::json!({"a": 1})).payload().is_err());
+}
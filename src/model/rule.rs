@@ -1,14 +1,15 @@
-use super::RootCommon;
-use crate::{error::DataLoaderError, rule_engine::Value};
+use super::{Format, RootCommon};
+use crate::error::DataLoaderError;
 use crate::parser::rule;
 
-use crate::rule_engine::{Condition, LeafOperator};
+use crate::rule_engine::Condition;
+#[cfg(test)]
+use crate::rule_engine::{LeafOperator, Value};
 
 use serde_json;
 
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct RuleMeta {
@@ -28,20 +29,26 @@ pub struct Rule {
 
 
 impl Rule {
+    #[cfg(test)]
     pub fn load_from_slice(rule_data: &[u8]) -> Result<Rule, DataLoaderError> {
-        let root = serde_json::from_slice::<RootCommon>(rule_data)?;
+        return Self::load(rule_data, Format::Json);
+    }
+
+    pub fn load(rule_data: &[u8], format: Format) -> Result<Rule, DataLoaderError> {
+        let root = RootCommon::load(rule_data, format, "Rule")?;
         let meta = serde_json::from_value::<RuleMeta>(root.meta)?;
     
         if let Some(v) = root.spec.pointer("/rule") {
             if let Some(rule_str) = v.as_str() {
-                let (left, cond) = rule::do_parse(rule_str)
-                    .map_err(|e| DataLoaderError::SpecParseError(e.to_string()))?;
-                if left != "" {
-                    return Err(DataLoaderError::SpecParseError(format!(
-                        "syntax error in rule, some part not recognised: {}",
-                        left
-                    )));
-                }
+                let mut cond = rule::parse_rule(rule_str).map_err(|e| DataLoaderError::RuleSyntaxError {
+                    path: "<rule>".to_owned(),
+                    offset: e.offset,
+                    line: e.line,
+                    col: e.col,
+                    expected: e.expected,
+                    found: e.found,
+                })?;
+                cond.prepare().map_err(DataLoaderError::SpecParseError)?;
                 let spec = RuleSpec { rule: cond };
                 return Ok(Rule { meta, spec });
             }
@@ -109,3 +116,128 @@ fn test_load_rule() {
         }
     )
 }
+
+#[test]
+fn test_load_rule_with_list() {
+    let rule = r#"
+	{
+		"version": 1,
+		"kind": "Rule",
+		"meta": {
+			"desc": "balabalabala",
+			"tags": []
+		},
+		"spec": {
+			"rule": "region in [\"cn\", \"us\"] && build not in [1, 2]"
+		}
+	}
+	"#;
+
+    let r = Rule::load_from_slice(rule.as_bytes()).unwrap();
+    match &r.spec.rule {
+        Condition::And(leaves) => {
+            for leaf in leaves {
+                assert!(matches!(leaf, Condition::Leaf { rhs: Some(Value::Set(_)), .. }));
+            }
+        }
+        _ => panic!("should be an and condition"),
+    }
+
+    let rule = rule.replace("build not in", "build ==");
+    assert!(Rule::load_from_slice(rule.as_bytes()).is_err());
+}
+
+#[test]
+fn test_load_rule_with_pattern() {
+    let rule = r#"
+	{
+		"version": 1,
+		"kind": "Rule",
+		"meta": {
+			"desc": "balabalabala",
+			"tags": []
+		},
+		"spec": {
+			"rule": "host =~ \"^web-[0-9]+\\\\.prod\" && svc glob \"payment-*\""
+		}
+	}
+	"#;
+
+    let r = Rule::load_from_slice(rule.as_bytes()).unwrap();
+    match &r.spec.rule {
+        Condition::And(leaves) => {
+            for leaf in leaves {
+                assert!(matches!(leaf, Condition::Leaf { rhs: Some(Value::Pattern(_)), .. }));
+            }
+        }
+        _ => panic!("should be an and condition"),
+    }
+
+    let rule = rule.replace("[0-9]+", "[0-9+");
+    match Rule::load_from_slice(rule.as_bytes()) {
+        Err(DataLoaderError::SpecParseError(msg)) => assert!(msg.contains("[0-9+")),
+        _ => panic!("invalid regex should fail the load"),
+    }
+}
+
+#[test]
+fn test_load_rule_with_cidr() {
+    let rule = r#"
+	{
+		"version": 1,
+		"kind": "Rule",
+		"meta": {
+			"desc": "balabalabala",
+			"tags": []
+		},
+		"spec": {
+			"rule": "client_ip in_cidr [\"10.0.0.0/8\", \"fd00::/8\"]"
+		}
+	}
+	"#;
+
+    let r = Rule::load_from_slice(rule.as_bytes()).unwrap();
+    match &r.spec.rule {
+        Condition::Leaf { rhs: Some(Value::List(nets)), .. } => {
+            assert!(nets.iter().all(|n| matches!(n, Value::Cidr(_))));
+        }
+        _ => panic!("should be a prepared cidr leaf"),
+    }
+
+    let rule = rule.replace("10.0.0.0/8", "10.0.0.0/33");
+    match Rule::load_from_slice(rule.as_bytes()) {
+        Err(DataLoaderError::SpecParseError(msg)) => assert!(msg.contains("10.0.0.0/33")),
+        _ => panic!("invalid cidr should fail the load"),
+    }
+}
+
+#[test]
+fn test_load_rule_syntax_error() {
+    let rule = r#"
+	{
+		"version": 1,
+		"kind": "Rule",
+		"meta": {
+			"desc": "balabalabala",
+			"tags": []
+		},
+		"spec": {
+			"rule": "region == \"eu\" && build >= "
+		}
+	}
+	"#;
+
+    match Rule::load_from_slice(rule.as_bytes()).map_err(|e| e.with_path("/foo/rule.json")) {
+        Err(e @ DataLoaderError::RuleSyntaxError { .. }) => {
+            assert_eq!(
+                e.to_string(),
+                "/foo/rule.json: line 1, col 28: expected a value after `>=`, found end of rule"
+            );
+            match e {
+                DataLoaderError::RuleSyntaxError { offset, .. } => assert_eq!(offset, 27),
+                _ => unreachable!(),
+            }
+        }
+        _ => panic!("should be a syntax error"),
+    }
+}
@@ -0,0 +1,350 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// A compiled JSON Schema, the `schema` of a resource value, as of draft 6 and later, e.g.,
+/// `exclusiveMinimum` is a number, not the draft 4 boolean. The supported keywords are
+/// `type`, `enum`, `const`, `properties`, `required`, `additionalProperties`, `items`,
+/// `minItems`, `maxItems`, `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`,
+/// `minLength`, `maxLength`, `pattern`, `allOf`, `anyOf`, `oneOf` and `not`, plus the
+/// annotations in `ANNOTATIONS`. Any other keyword fails the compile, a schema that relies
+/// on a keyword this does not check would let every value through.
+#[derive(Debug)]
+pub struct Schema {
+    // the `false` schema
+    reject_all: bool,
+    types: Option<Vec<String>>,
+    enum_values: Option<Vec<Value>>,
+    const_value: Option<Value>,
+    properties: Vec<(String, Schema)>,
+    required: Vec<String>,
+    additional_properties: Option<Box<Schema>>,
+    items: Option<Box<Schema>>,
+    min_items: Option<u64>,
+    max_items: Option<u64>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    exclusive_minimum: Option<f64>,
+    exclusive_maximum: Option<f64>,
+    min_length: Option<u64>,
+    max_length: Option<u64>,
+    pattern: Option<Regex>,
+    all_of: Vec<Schema>,
+    any_of: Vec<Schema>,
+    one_of: Vec<Schema>,
+    not: Option<Box<Schema>>,
+}
+
+/// Where and why a value does not match a schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaViolation {
+    // JSON pointer into the value, empty for the value itself
+    pub pointer: String,
+    pub reason: String,
+}
+
+const TYPES: &[&str] = &["null", "boolean", "object", "array", "number", "integer", "string"];
+
+// keywords that describe the schema and never reject a value
+const ANNOTATIONS: &[&str] = &["$schema", "$id", "$comment", "title", "description", "default", "examples", "readOnly", "writeOnly", "deprecated"];
+
+impl Schema {
+    /// None if the schema accepts anything, i.e., it is `{}`, `true` or null, so resources
+    /// without a schema cost nothing.
+    pub fn compile(schema: &Value) -> Result<Option<Schema>, String> {
+        match schema {
+            Value::Null | Value::Bool(true) => return Ok(None),
+            Value::Object(t) if t.is_empty() => return Ok(None),
+            t => return Self::compile_node(t, "").map(Some),
+        }
+    }
+
+    fn compile_node(schema: &Value, pointer: &str) -> Result<Schema, String> {
+        let mut ret = Schema {
+            reject_all: false,
+            types: None,
+            enum_values: None,
+            const_value: None,
+            properties: Vec::new(),
+            required: Vec::new(),
+            additional_properties: None,
+            items: None,
+            min_items: None,
+            max_items: None,
+            minimum: None,
+            maximum: None,
+            exclusive_minimum: None,
+            exclusive_maximum: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+            all_of: Vec::new(),
+            any_of: Vec::new(),
+            one_of: Vec::new(),
+            not: None,
+        };
+        let obj = match schema {
+            Value::Bool(b) => {
+                ret.reject_all = !b;
+                return Ok(ret);
+            }
+            Value::Object(t) => t,
+            _ => return Err(format!("schema at `{}` must be an object or a boolean", pointer)),
+        };
+
+        let err = |keyword: &str, expected: &str| format!("`{}` at `{}` must be {}", keyword, pointer, expected);
+        let sub = |keyword: &str, v: &Value| Self::compile_node(v, &format!("{}/{}", pointer, keyword));
+        let sub_list = |keyword: &str, v: &Value| -> Result<Vec<Schema>, String> {
+            let items = v.as_array().ok_or_else(|| err(keyword, "an array of schemas"))?;
+            let mut ret = Vec::with_capacity(items.len());
+            for (i, item) in items.iter().enumerate() {
+                ret.push(Self::compile_node(item, &format!("{}/{}/{}", pointer, keyword, i))?);
+            }
+            return Ok(ret);
+        };
+        let count = |keyword: &str, v: &Value| v.as_u64().ok_or_else(|| err(keyword, "a non-negative integer"));
+        let number = |keyword: &str, v: &Value| match v {
+            Value::Bool(_) => Err(err(keyword, "a number, the draft 4 boolean form is not supported")),
+            _ => v.as_f64().ok_or_else(|| err(keyword, "a number")),
+        };
+
+        for (keyword, v) in obj {
+            match keyword.as_str() {
+                "type" => {
+                    let types: Vec<String> = match v {
+                        Value::String(t) => vec![t.clone()],
+                        Value::Array(items) => items.iter().filter_map(|t| t.as_str().map(str::to_owned)).collect(),
+                        _ => return Err(err(keyword, "a string or an array of strings")),
+                    };
+                    if let Some(t) = types.iter().find(|t| !TYPES.contains(&t.as_str())) {
+                        return Err(err(keyword, &format!("one of {}, not `{}`", TYPES.join(", "), t)));
+                    }
+                    ret.types = Some(types);
+                }
+                "enum" => ret.enum_values = Some(v.as_array().ok_or_else(|| err(keyword, "an array"))?.clone()),
+                "const" => ret.const_value = Some(v.clone()),
+                "properties" => {
+                    let props = v.as_object().ok_or_else(|| err(keyword, "an object"))?;
+                    for (name, prop) in props {
+                        let prop_pointer = format!("{}/properties/{}", pointer, escape_pointer(name));
+                        ret.properties.push((name.clone(), Self::compile_node(prop, &prop_pointer)?));
+                    }
+                }
+                "required" => {
+                    let names = v.as_array().ok_or_else(|| err(keyword, "an array of strings"))?;
+                    for name in names {
+                        ret.required.push(name.as_str().ok_or_else(|| err(keyword, "an array of strings"))?.to_owned());
+                    }
+                }
+                "additionalProperties" => ret.additional_properties = Some(Box::new(sub(keyword, v)?)),
+                "items" => ret.items = Some(Box::new(sub(keyword, v)?)),
+                "minItems" => ret.min_items = Some(count(keyword, v)?),
+                "maxItems" => ret.max_items = Some(count(keyword, v)?),
+                "minLength" => ret.min_length = Some(count(keyword, v)?),
+                "maxLength" => ret.max_length = Some(count(keyword, v)?),
+                "minimum" => ret.minimum = Some(number(keyword, v)?),
+                "maximum" => ret.maximum = Some(number(keyword, v)?),
+                "exclusiveMinimum" => ret.exclusive_minimum = Some(number(keyword, v)?),
+                "exclusiveMaximum" => ret.exclusive_maximum = Some(number(keyword, v)?),
+                "pattern" => {
+                    let source = v.as_str().ok_or_else(|| err(keyword, "a string"))?;
+                    ret.pattern = Some(Regex::new(source).map_err(|e| err(keyword, &format!("a valid regex, {}", e)))?);
+                }
+                "allOf" => ret.all_of = sub_list(keyword, v)?,
+                "anyOf" => ret.any_of = sub_list(keyword, v)?,
+                "oneOf" => ret.one_of = sub_list(keyword, v)?,
+                "not" => ret.not = Some(Box::new(sub(keyword, v)?)),
+                t if ANNOTATIONS.contains(&t) => {}
+                _ => return Err(format!("`{}` at `{}` is not supported", keyword, pointer)),
+            }
+        }
+        return Ok(ret);
+    }
+
+    /// The first place where `value` does not match the schema.
+    pub fn validate(&self, value: &Value) -> Result<(), SchemaViolation> {
+        return self.validate_at(value, &mut String::new());
+    }
+
+    fn validate_at(&self, value: &Value, pointer: &mut String) -> Result<(), SchemaViolation> {
+        let fail = |pointer: &String, reason: String| Err(SchemaViolation { pointer: pointer.clone(), reason });
+
+        if self.reject_all {
+            return fail(pointer, "no value is allowed here".to_owned());
+        }
+        if let Some(types) = &self.types {
+            if types.iter().any(|t| is_type(value, t)) == false {
+                return fail(pointer, format!("expected {}, found {}", types.join(" or "), type_name(value)));
+            }
+        }
+        if let Some(values) = &self.enum_values {
+            if values.contains(value) == false {
+                return fail(pointer, format!("{} is not one of {}", value, Value::Array(values.clone())));
+            }
+        }
+        if let Some(t) = &self.const_value {
+            if t != value {
+                return fail(pointer, format!("expected {}, found {}", t, value));
+            }
+        }
+
+        match value {
+            Value::Object(fields) => {
+                for name in &self.required {
+                    if fields.contains_key(name) == false {
+                        return fail(pointer, format!("required field `{}` is missing", name));
+                    }
+                }
+                self.validate_fields(fields, pointer)?;
+            }
+            Value::Array(items) => {
+                let len = items.len() as u64;
+                if self.min_items.is_some_and(|t| len < t) || self.max_items.is_some_and(|t| len > t) {
+                    return fail(pointer, format!("{} items is out of the allowed range", len));
+                }
+                if let Some(schema) = &self.items {
+                    for (i, item) in items.iter().enumerate() {
+                        let old_len = pointer.len();
+                        pointer.push_str(&format!("/{}", i));
+                        schema.validate_at(item, pointer)?;
+                        pointer.truncate(old_len);
+                    }
+                }
+            }
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or(f64::NAN);
+                let out_of_range = self.minimum.is_some_and(|t| n < t)
+                    || self.maximum.is_some_and(|t| n > t)
+                    || self.exclusive_minimum.is_some_and(|t| n <= t)
+                    || self.exclusive_maximum.is_some_and(|t| n >= t);
+                if out_of_range {
+                    return fail(pointer, format!("{} is out of the allowed range", value));
+                }
+            }
+            Value::String(s) => {
+                let len = s.chars().count() as u64;
+                if self.min_length.is_some_and(|t| len < t) || self.max_length.is_some_and(|t| len > t) {
+                    return fail(pointer, format!("length {} is out of the allowed range", len));
+                }
+                if let Some(re) = &self.pattern {
+                    if re.is_match(s) == false {
+                        return fail(pointer, format!("{} does not match `{}`", value, re.as_str()));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        for schema in &self.all_of {
+            schema.validate_at(value, pointer)?;
+        }
+        if self.any_of.is_empty() == false && self.any_of.iter().all(|t| t.validate(value).is_err()) {
+            return fail(pointer, "matches none of `anyOf`".to_owned());
+        }
+        if self.one_of.is_empty() == false {
+            let matched = self.one_of.iter().filter(|t| t.validate(value).is_ok()).count();
+            if matched != 1 {
+                return fail(pointer, format!("matches {} of `oneOf`, expected exactly 1", matched));
+            }
+        }
+        if let Some(schema) = &self.not {
+            if schema.validate(value).is_ok() {
+                return fail(pointer, "matches `not`".to_owned());
+            }
+        }
+        return Ok(());
+    }
+
+    fn validate_fields(&self, fields: &Map<String, Value>, pointer: &mut String) -> Result<(), SchemaViolation> {
+        for (name, field) in fields {
+            let schema = match self.properties.iter().find(|(t, _)| t == name) {
+                Some((_, t)) => t,
+                None => match &self.additional_properties {
+                    Some(t) => t,
+                    None => continue,
+                },
+            };
+            let old_len = pointer.len();
+            pointer.push('/');
+            pointer.push_str(&escape_pointer(name));
+            schema.validate_at(field, pointer)?;
+            pointer.truncate(old_len);
+        }
+        return Ok(());
+    }
+}
+
+fn escape_pointer(name: &str) -> String {
+    return name.replace('~', "~0").replace('/', "~1");
+}
+
+fn is_type(value: &Value, t: &str) -> bool {
+    match (t, value) {
+        ("null", Value::Null) | ("boolean", Value::Bool(_)) | ("object", Value::Object(_)) => return true,
+        ("array", Value::Array(_)) | ("number", Value::Number(_)) | ("string", Value::String(_)) => return true,
+        ("integer", Value::Number(n)) => return n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|t| t.fract() == 0.0),
+        _ => return false,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => return "null",
+        Value::Bool(_) => return "boolean",
+        Value::Object(_) => return "object",
+        Value::Array(_) => return "array",
+        Value::Number(_) => return "number",
+        Value::String(_) => return "string",
+    }
+}
+
+#[test]
+fn test_schema() {
+    use serde_json::json;
+
+    assert!(Schema::compile(&json!({})).unwrap().is_none());
+    assert!(Schema::compile(&json!({"type": "text"})).is_err());
+    assert!(Schema::compile(&json!({"properties": {"a": {"pattern": "("}}})).is_err());
+    // a keyword that is not checked must not quietly let values through
+    assert_eq!(
+        Schema::compile(&json!({"title": "x", "properties": {"a": {"$ref": "#/definitions/a"}}})).unwrap_err(),
+        "`$ref` at `/properties/a` is not supported"
+    );
+    assert!(Schema::compile(&json!({"uniqueItems": true})).is_err());
+    assert!(Schema::compile(&json!({"format": "email"})).is_err());
+    assert_eq!(
+        Schema::compile(&json!({"minimum": 0, "exclusiveMinimum": true})).unwrap_err(),
+        "`exclusiveMinimum` at `` must be a number, the draft 4 boolean form is not supported"
+    );
+
+    let schema = Schema::compile(&json!({
+        "type": "object",
+        "required": ["timeout"],
+        "properties": {
+            "timeout": {"type": "integer", "minimum": 1, "exclusiveMaximum": 60},
+            "mode": {"enum": ["fast", "safe"]},
+            "servers": {
+                "type": "array",
+                "maxItems": 2,
+                "items": {"type": "object", "properties": {"host": {"type": "string", "pattern": "^[a-z.]+$"}}}
+            },
+            "retry": {"oneOf": [{"type": "boolean"}, {"type": "integer", "minimum": 0}]}
+        },
+        "additionalProperties": false
+    }))
+    .unwrap()
+    .unwrap();
+
+    let violation = |v: Value| schema.validate(&v).err().map(|t| (t.pointer, t.reason));
+    assert_eq!(violation(json!({"timeout": 10, "mode": "fast", "servers": [{"host": "a.b"}], "retry": 3})), None);
+    assert_eq!(violation(json!({"timeout": 10.0})), None);
+    assert_eq!(violation(json!([])), Some(("".to_string(), "expected object, found array".to_string())));
+    assert_eq!(violation(json!({})), Some(("".to_string(), "required field `timeout` is missing".to_string())));
+    assert_eq!(violation(json!({"timeout": 60})), Some(("/timeout".to_string(), "60 is out of the allowed range".to_string())));
+    assert_eq!(
+        violation(json!({"timeout": 1, "servers": [{"host": "a"}, {"host": "B"}]})),
+        Some(("/servers/1/host".to_string(), "\"B\" does not match `^[a-z.]+$`".to_string()))
+    );
+    assert_eq!(violation(json!({"timeout": 1, "mode": "slow"})).unwrap().0, "/mode");
+    assert_eq!(violation(json!({"timeout": 1, "retry": -1})).unwrap().1, "matches 0 of `oneOf`, expected exactly 1");
+    assert_eq!(violation(json!({"timeout": 1, "other": 1})), Some(("/other".to_string(), "no value is allowed here".to_string())));
+}
@@ -2,36 +2,132 @@
 
 use nom::branch::alt;
 use nom::bytes::complete::{escaped, tag, take_till1, take_while_m_n};
-use nom::character::complete::{alpha1, alphanumeric1, multispace0};
-use nom::combinator::{map, map_res, peek, recognize};
-use nom::{IResult, error::{context, ParseError}, multi::many0, sequence::{delimited, pair, preceded, tuple}};
+use nom::character::complete::{alpha1, alphanumeric1, char, digit1, multispace0, multispace1, one_of};
+use nom::combinator::{consumed, cut, map, map_res, not, opt, peek, recognize, value};
+use nom::{error::{context, ContextError, ErrorKind, FromExternalError, ParseError}, multi::many0, sequence::{delimited, pair, preceded, terminated, tuple}};
 
 use unescape::unescape;
 
-use crate::rule_engine::{Condition, LeafOperator, Value};
+use crate::rule_engine::{parse_timestamp, parse_weekday, Condition, LeafOperator, SemVer, Value};
 
 // reerence:
 // https://github.com/balajisivaraman/basic_calculator_rs/blob/master/src/parser.rs
 // https://zhuanlan.zhihu.com/p/146455601   (https://link.zhihu.com/?target=https%3A//github.com/PrivateRookie/jsonparse)
 
+/// Error of the rule parsers. Of all the alternatives tried, the one that got furthest into
+/// the input wins, so a typo is reported where it is, not where the enclosing expression
+/// started. `expected` is filled from the innermost `context` label.
+#[derive(Debug, PartialEq)]
+pub struct RuleParseError<I> {
+    pub input: I,
+    pub expected: Option<String>,
+}
+
+type IResult<I, O> = nom::IResult<I, O, RuleParseError<I>>;
+
+impl<I> RuleParseError<I> {
+    fn or_expected(mut self, expected: String) -> Self {
+        if self.expected.is_none() {
+            self.expected = Some(expected);
+        }
+        return self;
+    }
+}
+
+impl<'a> ParseError<&'a str> for RuleParseError<&'a str> {
+    fn from_error_kind(input: &'a str, _: ErrorKind) -> Self {
+        return RuleParseError { input, expected: None };
+    }
+
+    fn append(_: &'a str, _: ErrorKind, other: Self) -> Self {
+        return other;
+    }
+
+    fn or(self, other: Self) -> Self {
+        if self.input.len() < other.input.len()
+            || (self.input.len() == other.input.len() && other.expected.is_none())
+        {
+            return self;
+        }
+        return other;
+    }
+}
+
+impl<'a> ContextError<&'a str> for RuleParseError<&'a str> {
+    fn add_context(_: &'a str, ctx: &'static str, other: Self) -> Self {
+        return other.or_expected(ctx.to_owned());
+    }
+}
+
+impl<'a, E> FromExternalError<&'a str, E> for RuleParseError<&'a str> {
+    fn from_external_error(input: &'a str, kind: ErrorKind, _: E) -> Self {
+        return Self::from_error_kind(input, kind);
+    }
+}
+
+/// A rule that can not be parsed, `offset` is in bytes, `line` and `col` count from 1.
+#[derive(Debug, PartialEq)]
+pub struct SyntaxError {
+    pub offset: usize,
+    pub line: usize,
+    pub col: usize,
+    pub expected: String,
+    pub found: String,
+}
+
+impl SyntaxError {
+    fn new(full: &str, rest: &str, expected: String) -> Self {
+        let offset = full.len() - rest.len();
+        let before = &full[..offset];
+        let line = before.matches('\n').count() + 1;
+        let col = match before.rfind('\n') {
+            Some(pos) => before[pos + 1..].chars().count() + 1,
+            None => before.chars().count() + 1,
+        };
+
+        let token = rest.split(char::is_whitespace).next().unwrap_or("");
+        let found = if rest.is_empty() {
+            "end of rule".to_owned()
+        } else if token.chars().count() > 16 {
+            format!("`{}...`", token.chars().take(16).collect::<String>())
+        } else {
+            format!("`{}`", token)
+        };
+
+        return SyntaxError { offset, line, col, expected, found };
+    }
+}
+
 // priority(from higher to lower):
 // ()
 // !
-// ==, >=, >, <=, <, !=
+// ==, >=, >, <=, <, !=, in, not in, =~, glob, starts_with, ends_with, in_cidr (or within)
 // &&
 // ||
 
-fn leaf_binary_op(i: &str) -> IResult<&str, LeafOperator> {
-    let (i, op_str) = tag("==")(i)?;
-
-    let op = match op_str {
-        "==" => LeafOperator::Eq,
-        _ => {
-            panic!()
-        }
-    };
+fn compare_op(i: &str) -> IResult<&str, LeafOperator> {
+    // longer operators must be tried first, otherwise `>=` would be taken as `>`
+    alt((
+        value(LeafOperator::Eq, tag("==")),
+        value(LeafOperator::Ne, tag("!=")),
+        value(LeafOperator::Ge, tag(">=")),
+        value(LeafOperator::Le, tag("<=")),
+        value(LeafOperator::Gt, tag(">")),
+        value(LeafOperator::Lt, tag("<")),
+    ))(i)
+}
 
-    return Ok((i, op));
+fn leaf_binary_op(i: &str) -> IResult<&str, LeafOperator> {
+    alt((
+        compare_op,
+        value(LeafOperator::In, keyword("in")),
+        value(LeafOperator::NotIn, tuple((keyword("not"), multispace1, keyword("in")))),
+        value(LeafOperator::RegexMatch, tag("=~")),
+        value(LeafOperator::Glob, keyword("glob")),
+        value(LeafOperator::StartsWith, keyword("starts_with")),
+        value(LeafOperator::EndsWith, keyword("ends_with")),
+        value(LeafOperator::InCidr, alt((keyword("in_cidr"), keyword("within")))),
+    ))(i)
 }
 
 
@@ -42,37 +138,129 @@ pub fn identifier(input: &str) -> IResult<&str, &str> {
     ))(input)
 }
 
+/// Once a keyword or an operator has matched, there is no other way to read the input, so
+/// what follows must parse, and if it does not, the error is reported as is instead of
+/// backtracking into other alternatives.
+fn expect<'a, O, F>(label: &'static str, parser: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, O>,
+{
+    cut(context(label, parser))
+}
+
 /// A combinator that takes a parser `inner` and produces a parser that also consumes both leading and
 /// trailing whitespace, returning the output of `inner`.
-fn ws<'a, F: 'a, O, E: ParseError<&'a str>>(
+fn ws<'a, F, O, E: ParseError<&'a str>>(
     inner: F,
-) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
+) -> impl FnMut(&'a str) -> nom::IResult<&'a str, O, E>
 where
-    F: Fn(&'a str) -> IResult<&'a str, O, E>,
+    F: FnMut(&'a str) -> nom::IResult<&'a str, O, E> + 'a,
 {
     delimited(multispace0, inner, multispace0)
 }
 
 
+// literals:
+// "abc"        string
+// -12, 3       int
+// 1.5, -2e3    float
+// true, false  bool
+// v1.2.3       semver
+// ["a", 1]     list, only for `in`, `not in` and `in_cidr`
 fn rhs_literal(i: &str) -> IResult<&str, Value> {
-    map_res(string, unescape_str)(i)
+    alt((
+        list_literal,
+        scalar_literal,
+    ))(i)
+}
+
+fn list_literal(i: &str) -> IResult<&str, Value> {
+    map(|i| list_of(i, "a value", scalar_literal), Value::List)(i)
+}
+
+// `[a, b]`, the list may be empty, but may not end with `,`
+fn list_of<'a, O>(
+    i: &'a str,
+    label: &'static str,
+    item: fn(&'a str) -> IResult<&'a str, O>,
+) -> IResult<&'a str, Vec<O>> {
+    let (i, _) = pair(char('['), multispace0)(i)?;
+    let closing: IResult<&str, char> = char(']')(i);
+    if let Ok((i, _)) = closing {
+        return Ok((i, Vec::new()));
+    }
+
+    let (i, first) = expect(label, item)(i)?;
+    let (i, mut more) = many0(preceded(ws(char(',')), expect(label, item)))(i)?;
+    let (i, _) = pair(multispace0, expect("`,` or `]`", char(']')))(i)?;
+    more.insert(0, first);
+    return Ok((i, more));
+}
+
+fn scalar_literal(i: &str) -> IResult<&str, Value> {
+    alt((
+        semver_literal,
+        bool_literal,
+        number_literal,
+        map(string_literal, Value::Str),
+    ))(i)
+}
+
+fn keyword<'a>(kw: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    // a keyword must not be the prefix of a longer word, e.g., `trueish`
+    terminated(tag(kw), not(peek(alt((alphanumeric1, tag("_"))))))
+}
+
+fn bool_literal(i: &str) -> IResult<&str, Value> {
+    alt((
+        value(Value::Bool(true), keyword("true")),
+        value(Value::Bool(false), keyword("false")),
+    ))(i)
 }
 
-fn unescape_str(i: &str) -> Result<Value, String> {
-	if let Some(t) =  unescape(i) {
-        return Ok(Value::Str(t))
+fn semver_literal(i: &str) -> IResult<&str, Value> {
+    map_res(
+        recognize(tuple((char('v'), digit1, char('.'), digit1, char('.'), digit1))),
+        |s: &str| SemVer::parse(s).map(Value::SemVer).ok_or("semver out of range"),
+    )(i)
+}
+
+fn number_literal(i: &str) -> IResult<&str, Value> {
+    let (rest, num_str) = recognize(tuple((
+        opt(char('-')),
+        digit1,
+        opt(pair(char('.'), digit1)),
+        opt(tuple((one_of("eE"), opt(one_of("+-")), digit1))),
+    )))(i)?;
+
+    let ret = if num_str.contains(['.', 'e', 'E']) {
+        num_str.parse::<f64>().map(Value::Float).ok()
     } else {
-        return Err("can not parse enscaped string".into())
+        num_str.parse::<i64>().map(Value::Int).ok()
+    };
+
+    match ret {
+        Some(v) => return Ok((rest, v)),
+        None => {
+            return Err(nom::Err::Error(RuleParseError::from_error_kind(
+                i,
+                ErrorKind::Digit,
+            )))
+        }
     }
 }
 
+fn string_literal(i: &str) -> IResult<&str, String> {
+    map_res(string, |s| unescape(s).ok_or("can not parse enscaped string"))(i)
+}
 
 fn leaf_expr(i: &str) -> IResult<&str, Condition> {
-    let (i,(ident, op, val)) = tuple((
+    let (i,(ident, (op_str, op))) = tuple((
 		ws(identifier),
-		ws(leaf_binary_op),
-        ws(rhs_literal),
+		ws(context("an operator", consumed(leaf_binary_op))),
 	))(i)?;
+    let (i, val) = cut(ws(rhs_literal))(i)
+        .map_err(|e| e.map(|e| e.or_expected(format!("a value after `{}`", op_str))))?;
 
     let t = Condition::Leaf{
         lhs: String::from(ident),
@@ -83,17 +271,120 @@ fn leaf_expr(i: &str) -> IResult<&str, Condition> {
 
 }
 
-fn leaf_expr_or_paren(i: &str) -> IResult<&str, Condition> {
+fn leaf_unary_op(i: &str) -> IResult<&str, LeafOperator> {
     alt((
-        leaf_expr,
-        delimited(ws(tag("(")), expr_no_paren, ws(tag(")"))),
+        value(LeafOperator::Exists, keyword("exists")),
+        value(LeafOperator::Missing, keyword("missing")),
     ))(i)
 }
 
+// exists(key), missing(key)
+fn unary_leaf_expr(i: &str) -> IResult<&str, Condition> {
+    let (i, (op, ident)) = pair(
+        ws(leaf_unary_op),
+        preceded(
+            ws(tag("(")),
+            cut(terminated(ws(expect("a key", identifier)), ws(expect("`)`", tag(")"))))),
+        ),
+    )(i)?;
+
+    let t = Condition::Leaf{
+        lhs: String::from(ident),
+        op,
+        rhs: None,
+    };
+    return Ok((i, t))
+}
+
+fn percentage(i: &str) -> IResult<&str, Value> {
+    // `5` without `%` is reported at the number, not after it
+    terminated(number_literal, char('%'))(i)
+        .map_err(|e| e.map(|e| RuleParseError { input: i, ..e }))
+}
+
+// bucket(user_id, "feature-x") < 5%
+fn bucket_expr(i: &str) -> IResult<&str, Condition> {
+    let (i, (_, _, ident, _, salt, _, op, pct)) = tuple((
+        ws(keyword("bucket")),
+        ws(tag("(")),
+        ws(expect("a key", identifier)),
+        ws(expect("`,`", tag(","))),
+        ws(expect("a salt string", string_literal)),
+        ws(expect("`)`", tag(")"))),
+        ws(expect("a comparison operator", compare_op)),
+        ws(expect("a percentage like `5%`", percentage)),
+    ))(i)?;
+
+    let t = Condition::Bucket{
+        lhs: String::from(ident),
+        salt,
+        op,
+        rhs: pct,
+    };
+    return Ok((i, t))
+}
+
+fn timestamp_literal(i: &str) -> IResult<&str, i64> {
+    map_res(string_literal, |s| parse_timestamp(&s))(i)
+}
+
+// now between "2026-11-01T00:00Z" and "2026-11-02T00:00Z"
+fn now_between_expr(i: &str) -> IResult<&str, Condition> {
+    let (i, (_, _, start, _, end)) = tuple((
+        ws(keyword("now")),
+        ws(keyword("between")),
+        ws(expect("a timestamp like \"2026-11-01T00:00Z\"", timestamp_literal)),
+        ws(expect("`and`", keyword("and"))),
+        ws(expect("a timestamp like \"2026-11-01T00:00Z\"", timestamp_literal)),
+    ))(i)?;
+
+    return Ok((i, Condition::NowBetween{start, end}))
+}
+
+fn weekday_literal(i: &str) -> IResult<&str, u8> {
+    map_res(string_literal, |s| parse_weekday(&s).ok_or("unknown weekday"))(i)
+}
+
+// weekday in ["sat", "sun"]
+fn weekday_expr(i: &str) -> IResult<&str, Condition> {
+    let (i, (_, _, days)) = tuple((
+        ws(keyword("weekday")),
+        ws(keyword("in")),
+        ws(expect("`[`", |i| list_of(i, "a weekday like \"mon\"", weekday_literal))),
+    ))(i)?;
+
+    let mask = days.iter().fold(0u8, |mask, day| mask | (1 << day));
+    return Ok((i, Condition::WeekdayIn(mask)))
+}
+
+// rule("/common/prod_eu.json"), replaced by the referenced rule when the index is built
+fn rule_ref_expr(i: &str) -> IResult<&str, Condition> {
+    let (i, (_, _, path, _)) = tuple((
+        ws(keyword("rule")),
+        ws(tag("(")),
+        ws(expect("a rule path like \"/common/prod.json\"", string_literal)),
+        ws(expect("`)`", tag(")"))),
+    ))(i)?;
+
+    return Ok((i, Condition::RuleRef(path)))
+}
+
+fn leaf_expr_or_paren(i: &str) -> IResult<&str, Condition> {
+    context("a condition", alt((
+        rule_ref_expr,
+        now_between_expr,
+        weekday_expr,
+        bucket_expr,
+        unary_leaf_expr,
+        leaf_expr,
+        preceded(ws(tag("(")), cut(terminated(expr_no_paren, ws(expect("`)`", tag(")")))))),
+    )))(i)
+}
+
 
 fn expr_not(i: &str) -> IResult<&str, Condition> {
     alt((
-        map(pair(tag("!"), leaf_expr_or_paren), |(_, cond)|{Condition::Not(Box::new(cond))}),
+        map(preceded(tag("!"), cut(leaf_expr_or_paren)), |cond|{Condition::Not(Box::new(cond))}),
         leaf_expr_or_paren,
     ))(i)
 }
@@ -101,10 +392,10 @@ fn expr_not(i: &str) -> IResult<&str, Condition> {
 fn expr_and(i: &str) -> IResult<&str, Condition> {
     let (i, (first, more)) = tuple((
         ws(expr_not),
-        many0(preceded(ws(tag("&&")), ws(expr_not)))
+        many0(preceded(ws(tag("&&")), cut(ws(expr_not))))
     ))(i)?;
 
-    if more.len() == 0 {
+    if more.is_empty() {
         return Ok((i, first))
     } else {
         let mut children = Vec::with_capacity(1+more.len());
@@ -118,10 +409,10 @@ fn expr_and(i: &str) -> IResult<&str, Condition> {
 fn expr_or(i: &str) -> IResult<&str, Condition> {
     let (i, (first, more)) = tuple((
         ws(expr_and),
-        many0(preceded(ws(tag("||")), ws(expr_and)))
+        many0(preceded(ws(tag("||")), cut(ws(expr_and))))
     ))(i)?;
 
-    if more.len() == 0 {
+    if more.is_empty() {
         return Ok((i, first))
     } else {
         let mut children = Vec::with_capacity(1+more.len());
@@ -146,11 +437,29 @@ pub fn do_parse(i: &str) -> IResult<&str, Condition> {
     expr(i)
 }
 
+/// Parse a whole rule, anything left unparsed is an error.
+pub fn parse_rule(i: &str) -> Result<Condition, SyntaxError> {
+    match do_parse(i) {
+        Ok(("", cond)) => return Ok(cond),
+        Ok((rest, _)) => {
+            return Err(SyntaxError::new(i, rest, "`&&`, `||` or end of rule".to_owned()))
+        }
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            let expected = e.expected.unwrap_or_else(|| "a condition".to_owned());
+            return Err(SyntaxError::new(i, e.input, expected));
+        }
+        Err(nom::Err::Incomplete(_)) => {
+            return Err(SyntaxError::new(i, "", "more input".to_owned()))
+        }
+    }
+}
+
 fn string(i: &str) -> IResult<&str, &str> {
-    context(
-        "string",
-        alt((tag("\"\""), delimited(tag("\""), parse_str, tag("\"")))),
-    )(i)
+    alt((
+        // the empty string, `escaped` needs at least one char
+        value("", tag("\"\"")),
+        preceded(tag("\""), cut(terminated(parse_str, context("closing `\"`", tag("\""))))),
+    ))(i)
 }
 
 fn parse_str(i: &str) -> IResult<&str, &str> {
@@ -163,7 +472,7 @@ fn normal(i: &str) -> IResult<&str, &str> {
 
 fn escapable(i: &str) -> IResult<&str, &str> {
     context(
-        "escaped",
+        "a valid escape sequence",
         alt((
             tag("\""),
             tag("\\"),
@@ -180,7 +489,7 @@ fn escapable(i: &str) -> IResult<&str, &str> {
 
 fn parse_hex(i: &str) -> IResult<&str, &str> {
     context(
-        "hex string",
+        "a unicode escape like `\\u00e9`",
         preceded(
             peek(tag("u")),
             take_while_m_n(5, 5, |c: char| c.is_ascii_hexdigit() || c == 'u'),
@@ -191,9 +500,54 @@ fn parse_hex(i: &str) -> IResult<&str, &str> {
 #[test]
 fn rhs_literal_test() {
     assert_eq!(rhs_literal("\"3.0\""), Ok(("", Value::Str("3.0".into()))));
+    assert_eq!(rhs_literal("\"\""), Ok(("", Value::Str("".into()))));
 	assert_eq!(rhs_literal("\"3.0\\n\""), Ok(("", Value::Str("3.0\n".into()))));
 }
 
+#[test]
+fn rhs_typed_literal_test() {
+    assert_eq!(rhs_literal("-345"), Ok(("", Value::Int(-345))));
+    assert_eq!(rhs_literal("-1.234"), Ok(("", Value::Float(-1.234))));
+    assert_eq!(rhs_literal("2e3"), Ok(("", Value::Float(2000.0))));
+    assert_eq!(rhs_literal("true"), Ok(("", Value::Bool(true))));
+    assert_eq!(rhs_literal("false )"), Ok((" )", Value::Bool(false))));
+    assert_eq!(rhs_literal("v1.2.3"), Ok(("", Value::SemVer(SemVer::new(1, 2, 3)))));
+    assert_eq!(rhs_literal("\"v1.2.3\""), Ok(("", Value::Str("v1.2.3".into()))));
+    assert!(rhs_literal("trueish").is_err());
+    assert!(rhs_literal("v1.2").is_err());
+    assert!(rhs_literal("99999999999999999999").is_err());
+}
+
+#[test]
+fn list_literal_test() {
+    assert_eq!(
+        rhs_literal("[\"a\", 1 ,true]"),
+        Ok(("", Value::List(vec![Value::Str("a".into()), Value::Int(1), Value::Bool(true)])))
+    );
+    assert_eq!(rhs_literal("[ ]"), Ok(("", Value::List(vec![]))));
+    assert!(rhs_literal("[[1]]").is_err());
+
+    assert_eq!(
+        expr("region not  in [\"cn\",\"ru\"] && zone in [1]"),
+        Ok((
+            "",
+            Condition::And(vec![
+                Condition::Leaf{
+                    lhs: "region".into(),
+                    op: LeafOperator::NotIn,
+                    rhs: Some(Value::List(vec![Value::Str("cn".into()), Value::Str("ru".into())])),
+                },
+                Condition::Leaf{
+                    lhs: "zone".into(),
+                    op: LeafOperator::In,
+                    rhs: Some(Value::List(vec![Value::Int(1)])),
+                },
+            ]),
+        ))
+    );
+    assert!(leaf_binary_op("inside").is_err());
+}
+
 #[test]
 fn leaf_expr_test() {
     assert_eq!(
@@ -210,6 +564,119 @@ fn leaf_expr_test() {
     
 }
 
+#[test]
+fn leaf_binary_op_test() {
+    assert_eq!(leaf_binary_op("=="), Ok(("", LeafOperator::Eq)));
+    assert_eq!(leaf_binary_op("!="), Ok(("", LeafOperator::Ne)));
+    assert_eq!(leaf_binary_op(">="), Ok(("", LeafOperator::Ge)));
+    assert_eq!(leaf_binary_op("<="), Ok(("", LeafOperator::Le)));
+    assert_eq!(leaf_binary_op(">"), Ok(("", LeafOperator::Gt)));
+    assert_eq!(leaf_binary_op("<"), Ok(("", LeafOperator::Lt)));
+    assert_eq!(leaf_binary_op("=~"), Ok(("", LeafOperator::RegexMatch)));
+    assert_eq!(leaf_binary_op("glob"), Ok(("", LeafOperator::Glob)));
+    assert_eq!(leaf_binary_op("starts_with"), Ok(("", LeafOperator::StartsWith)));
+    assert_eq!(leaf_binary_op("ends_with"), Ok(("", LeafOperator::EndsWith)));
+    assert_eq!(leaf_binary_op("in_cidr"), Ok(("", LeafOperator::InCidr)));
+    assert_eq!(leaf_binary_op("within"), Ok(("", LeafOperator::InCidr)));
+    assert!(leaf_binary_op("=").is_err());
+
+    assert_eq!(
+        leaf_expr("build >= \"1200\""),
+        Ok((
+            "",
+            Condition::Leaf{
+                lhs: "build".into(),
+                op: LeafOperator::Ge,
+                rhs: Some(Value::Str("1200".into())),
+            },
+        ))
+    );
+    assert_eq!(
+        expr("!region != \"cn\""),
+        Ok((
+            "",
+            Condition::Not(Box::new(
+                Condition::Leaf{
+                    lhs: "region".into(),
+                    op: LeafOperator::Ne,
+                    rhs: Some(Value::Str("cn".into())),
+                }
+            )),
+        ))
+    );
+}
+
+#[test]
+fn unary_leaf_expr_test() {
+    assert_eq!(
+        expr("exists( canary ) && !missing(region)"),
+        Ok((
+            "",
+            Condition::And(vec![
+                Condition::Leaf{
+                    lhs: "canary".into(),
+                    op: LeafOperator::Exists,
+                    rhs: None,
+                },
+                Condition::Not(Box::new(Condition::Leaf{
+                    lhs: "region".into(),
+                    op: LeafOperator::Missing,
+                    rhs: None,
+                })),
+            ]),
+        ))
+    );
+    // `exists` is still usable as a key
+    assert_eq!(
+        expr("exists == \"1\""),
+        Ok((
+            "",
+            Condition::Leaf{
+                lhs: "exists".into(),
+                op: LeafOperator::Eq,
+                rhs: Some(Value::Str("1".into())),
+            },
+        ))
+    );
+}
+
+#[test]
+fn bucket_expr_test() {
+    assert_eq!(
+        expr("bucket( user_id , \"feature-x\" ) < 5% && region == \"eu\""),
+        Ok((
+            "",
+            Condition::And(vec![
+                Condition::Bucket{
+                    lhs: "user_id".into(),
+                    salt: "feature-x".into(),
+                    op: LeafOperator::Lt,
+                    rhs: Value::Int(5),
+                },
+                Condition::Leaf{
+                    lhs: "region".into(),
+                    op: LeafOperator::Eq,
+                    rhs: Some(Value::Str("eu".into())),
+                },
+            ]),
+        ))
+    );
+    assert_eq!(
+        bucket_expr("bucket(uid,\"s\")>=12.5%"),
+        Ok((
+            "",
+            Condition::Bucket{
+                lhs: "uid".into(),
+                salt: "s".into(),
+                op: LeafOperator::Ge,
+                rhs: Value::Float(12.5),
+            },
+        ))
+    );
+    assert!(bucket_expr("bucket(uid, \"s\") < 5").is_err());
+    assert!(bucket_expr("bucket(uid, \"s\") in 5%").is_err());
+}
+
 #[test]
 fn expr_not_test() {
     assert_eq!(
@@ -237,3 +704,102 @@ fn expr_test() {
 
     println!("{:#?}", expr("b==2&&(c==3||d==4)&&e==5"));
 }
+
+#[test]
+fn cidr_leaf_expr_test() {
+    assert_eq!(
+        leaf_expr("ip within \"10.0.0.0/8\""),
+        Ok((
+            "",
+            Condition::Leaf{
+                lhs: "ip".into(),
+                op: LeafOperator::InCidr,
+                rhs: Some(Value::Str("10.0.0.0/8".into())),
+            },
+        ))
+    );
+}
+
+#[test]
+fn time_expr_test() {
+    assert_eq!(
+        expr("now between \"2026-11-01T00:00Z\" and \"2026-11-02T00:00Z\" && region == \"eu\""),
+        Ok((
+            "",
+            Condition::And(vec![
+                Condition::NowBetween{start: 1793491200, end: 1793577600},
+                Condition::Leaf{
+                    lhs: "region".into(),
+                    op: LeafOperator::Eq,
+                    rhs: Some(Value::Str("eu".into())),
+                },
+            ])
+        ))
+    );
+    assert_eq!(
+        expr("weekday in [\"sat\", \"Sunday\"]"),
+        Ok(("", Condition::WeekdayIn(0b110_0000)))
+    );
+    // still a plain key when not followed by a weekday list
+    assert_eq!(
+        expr("now == \"x\""),
+        Ok(("", Condition::Leaf{lhs: "now".into(), op: LeafOperator::Eq, rhs: Some(Value::Str("x".into()))}))
+    );
+    assert!(now_between_expr("now between \"2026-11-01\" and \"soon\"").is_err());
+    assert!(weekday_expr("weekday in [\"someday\"]").is_err());
+}
+
+#[test]
+fn rule_ref_expr_test() {
+    assert_eq!(
+        expr("rule(\"/common/prod_eu.json\") && svc == \"x\""),
+        Ok((
+            "",
+            Condition::And(vec![
+                Condition::RuleRef("/common/prod_eu.json".into()),
+                Condition::Leaf{lhs: "svc".into(), op: LeafOperator::Eq, rhs: Some(Value::Str("x".into()))},
+            ])
+        ))
+    );
+    // a key may still be called `rule`
+    assert_eq!(
+        expr("rule == \"x\""),
+        Ok(("", Condition::Leaf{lhs: "rule".into(), op: LeafOperator::Eq, rhs: Some(Value::Str("x".into()))}))
+    );
+    assert!(parse_rule("rule(/common/prod_eu.json)").is_err());
+}
+
+#[test]
+fn parse_rule_error_test() {
+    let err = |rule: &str| {
+        let e = parse_rule(rule).unwrap_err();
+        (e.col, e.expected, e.found)
+    };
+
+    assert_eq!(err("region == "), (11, "a value after `==`".to_owned(), "end of rule".to_owned()));
+    assert_eq!(err("region == eu"), (11, "a value after `==`".to_owned(), "`eu`".to_owned()));
+    assert_eq!(err("region = \"eu\""), (8, "an operator".to_owned(), "`=`".to_owned()));
+    assert_eq!(err("region == \"eu"), (14, "closing `\"`".to_owned(), "end of rule".to_owned()));
+    assert_eq!(err("a == 1 & b == 2"), (8, "`&&`, `||` or end of rule".to_owned(), "`&`".to_owned()));
+    assert_eq!(err("a == 1 && "), (11, "a condition".to_owned(), "end of rule".to_owned()));
+    assert_eq!(err("(a == 1 || b == 2"), (18, "`)`".to_owned(), "end of rule".to_owned()));
+    assert_eq!(err("a in [1, 2"), (11, "`,` or `]`".to_owned(), "end of rule".to_owned()));
+    assert_eq!(err("a in [1, ]"), (10, "a value".to_owned(), "`]`".to_owned()));
+    assert_eq!(err("exists(a"), (9, "`)`".to_owned(), "end of rule".to_owned()));
+    assert_eq!(err("bucket(id, \"x\") < 5"), (19, "a percentage like `5%`".to_owned(), "`5`".to_owned()));
+    assert_eq!(
+        err("now between \"2026-11-01\" and \"soon\""),
+        (30, "a timestamp like \"2026-11-01T00:00Z\"".to_owned(), "`\"soon\"`".to_owned())
+    );
+    assert_eq!(err("weekday in [\"someday\"]"), (13, "a weekday like \"mon\"".to_owned(), "`\"someday\"]`".to_owned()));
+    assert_eq!(err("== 1"), (1, "a condition".to_owned(), "`==`".to_owned()));
+
+    let e = parse_rule("a == 1 &&\n  b ==").unwrap_err();
+    assert_eq!((e.offset, e.line, e.col), (16, 2, 7));
+    assert_eq!(
+        parse_rule("region == aVeryLongIdentifierValue").unwrap_err().found,
+        "`aVeryLongIdentif...`"
+    );
+
+    assert!(parse_rule("a == 1 && (b == 2 || !c == 3)").is_ok());
+}
@@ -0,0 +1,354 @@
+use std::cmp::Ordering;
+
+use super::{Condition, LeafOperator, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FindingKind {
+    // the condition can never match
+    Contradiction,
+    // the condition always matches
+    Tautology,
+    // the same condition appears twice under one `&&` or `||`
+    Duplicate,
+    // a branch that can be removed without changing the result
+    Subsumed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub message: String,
+}
+
+/// Look for conditions that are most likely mistakes in a rule. This is a best effort check,
+/// it only reports what it can prove and never rejects a rule.
+///
+/// Whoami values are assumed to be strings, which is what clients send through the FFI,
+/// e.g., `a == "1"` means the value of `a` is exactly `1`.
+pub fn analyze(cond: &Condition) -> Vec<Finding> {
+    let mut ret = Vec::new();
+    walk(cond, &mut ret);
+    return ret;
+}
+
+fn walk(cond: &Condition, out: &mut Vec<Finding>) {
+    match cond {
+        Condition::And(conds) => {
+            check_duplicates(cond, conds, out);
+            check_and(cond, conds, out);
+            check_subsumed(cond, conds, true, out);
+            conds.iter().for_each(|c| walk(c, out));
+        }
+        Condition::Or(conds) => {
+            check_duplicates(cond, conds, out);
+            check_or(cond, conds, out);
+            check_subsumed(cond, conds, false, out);
+            conds.iter().for_each(|c| walk(c, out));
+        }
+        Condition::Not(c) => walk(c, out),
+        Condition::WeekdayIn(0) => push(out, FindingKind::Contradiction, format!("`{}` can never match, no weekday is listed", cond)),
+        Condition::WeekdayIn(0x7f) => push(out, FindingKind::Tautology, format!("`{}` always matches, every weekday is listed", cond)),
+        Condition::Leaf { op: LeafOperator::In, rhs: Some(rhs), .. } if list_items(rhs).is_some_and(|t| t.is_empty()) => {
+            push(out, FindingKind::Contradiction, format!("`{}` can never match, the list is empty", cond));
+        }
+        _ => {}
+    }
+}
+
+fn push(out: &mut Vec<Finding>, kind: FindingKind, message: String) {
+    out.push(Finding { kind, message });
+}
+
+fn list_items(v: &Value) -> Option<&[Value]> {
+    match v {
+        Value::List(items) => return Some(items),
+        Value::Set(set) => return Some(set.items()),
+        _ => return None,
+    }
+}
+
+fn check_duplicates(node: &Condition, conds: &[Condition], out: &mut Vec<Finding>) {
+    for (i, c) in conds.iter().enumerate() {
+        if conds[..i].contains(c) && !conds[i + 1..].contains(c) {
+            push(out, FindingKind::Duplicate, format!("`{}` appears more than once in `{}`", c, node));
+        }
+    }
+}
+
+fn is_negation_of(a: &Condition, b: &Condition) -> bool {
+    return match (a, b) {
+        (Condition::Not(a), b) | (b, Condition::Not(a)) => **a == *b,
+        _ => false,
+    };
+}
+
+fn unary_pair<'a>(a: &'a Condition, b: &'a Condition) -> Option<&'a str> {
+    match (a, b) {
+        (
+            Condition::Leaf { lhs: l1, op: LeafOperator::Exists, rhs: None },
+            Condition::Leaf { lhs: l2, op: LeafOperator::Missing, rhs: None },
+        ) if l1 == l2 => return Some(l1),
+        _ => return None,
+    }
+}
+
+// the string that `cond` forces its key to be equal to
+fn forced_str(cond: &Condition) -> Option<(&str, &str)> {
+    match cond {
+        Condition::Leaf { lhs, op: LeafOperator::Eq, rhs: Some(Value::Str(v)) } => return Some((lhs, v)),
+        _ => return None,
+    }
+}
+
+// `key > 3` and the like
+fn bound(cond: &Condition) -> Option<(&str, &LeafOperator, &Value)> {
+    match cond {
+        Condition::Leaf { lhs, op, rhs: Some(rhs) }
+            if matches!(op, LeafOperator::Gt | LeafOperator::Ge | LeafOperator::Lt | LeafOperator::Le)
+                && matches!(rhs, Value::Int(_) | Value::Float(_) | Value::SemVer(_)) =>
+        {
+            return Some((lhs, op, rhs));
+        }
+        _ => return None,
+    }
+}
+
+fn is_lower(op: &LeafOperator) -> bool {
+    return *op == LeafOperator::Gt || *op == LeafOperator::Ge;
+}
+
+// Whether every whoami that matches `a` also matches `b`, only what can be told from the
+// shape of the two conditions.
+fn implies(a: &Condition, b: &Condition) -> bool {
+    if a == b {
+        return true;
+    }
+    if let Condition::Or(bs) = b {
+        if bs.iter().any(|b| implies(a, b)) {
+            return true;
+        }
+    }
+    if let Condition::And(as_) = a {
+        if as_.iter().any(|a| implies(a, b)) {
+            return true;
+        }
+    }
+    if let (Some((key, v)), Condition::Leaf { lhs, op, rhs }) = (forced_str(a), b) {
+        if key == lhs && rhs.is_some() {
+            let v = Value::Str(v.to_owned());
+            return Condition::eval_leaf(Some(&v), op, rhs.as_ref()) == Ok(true);
+        }
+    }
+    if let (Some((k1, op1, v1)), Some((k2, op2, v2))) = (bound(a), bound(b)) {
+        if k1 != k2 || is_lower(op1) != is_lower(op2) {
+            return false;
+        }
+        // `> 5` implies `> 3`, `>= 5` and `> 5` imply `>= 5`, but `>= 5` does not imply `> 5`
+        let strict1 = *op1 == LeafOperator::Gt || *op1 == LeafOperator::Lt;
+        let strict2 = *op2 == LeafOperator::Gt || *op2 == LeafOperator::Lt;
+        return match v1.compare(v2) {
+            Ok(Ordering::Equal) => strict1 || !strict2,
+            Ok(Ordering::Greater) => is_lower(op1),
+            Ok(Ordering::Less) => !is_lower(op1),
+            Err(_) => false,
+        };
+    }
+    return false;
+}
+
+fn check_and(node: &Condition, conds: &[Condition], out: &mut Vec<Finding>) {
+    let never = |reason: String| Finding {
+        kind: FindingKind::Contradiction,
+        message: format!("`{}` can never match, {}", node, reason),
+    };
+
+    for (i, a) in conds.iter().enumerate() {
+        for b in &conds[i + 1..] {
+            if is_negation_of(a, b) {
+                out.push(never(format!("`{}` and `{}` can not both hold", a, b)));
+            } else if let Some(key) = unary_pair(a, b).or_else(|| unary_pair(b, a)) {
+                out.push(never(format!("`{}` can not both exist and be missing", key)));
+            }
+        }
+    }
+
+    for (i, a) in conds.iter().enumerate() {
+        // `missing(k)` makes every binary leaf on `k` false
+        if let Condition::Leaf { lhs: key, op: LeafOperator::Missing, .. } = a {
+            for b in conds {
+                if let Condition::Leaf { lhs, rhs: Some(_), .. } = b {
+                    if lhs == key {
+                        out.push(never(format!("`{}` is false when `{}` is missing", b, key)));
+                    }
+                }
+            }
+        }
+
+        if let Some((key, v)) = forced_str(a) {
+            let v = Value::Str(v.to_owned());
+            for (j, b) in conds.iter().enumerate() {
+                // two `==` on the same key are reported once
+                if j < i && forced_str(b).is_some() {
+                    continue;
+                }
+                if let Condition::Leaf { lhs, op, rhs: Some(rhs) } = b {
+                    if lhs == key && Condition::eval_leaf(Some(&v), op, Some(rhs)) == Ok(false) {
+                        out.push(never(format!("`{}` and `{}` can not both hold", a, b)));
+                    }
+                }
+            }
+        }
+
+        if let Some((key, op, v)) = bound(a) {
+            for b in conds {
+                if let Some((key2, op2, v2)) = bound(b) {
+                    if key != key2 || !is_lower(op) || is_lower(op2) {
+                        continue;
+                    }
+                    let empty = match v.compare(v2) {
+                        Ok(Ordering::Greater) => true,
+                        Ok(Ordering::Equal) => *op == LeafOperator::Gt || *op2 == LeafOperator::Lt,
+                        _ => false,
+                    };
+                    if empty {
+                        out.push(never(format!("`{}` and `{}` can not both hold", a, b)));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut mask = 0x7f;
+    let mut weekdays = 0;
+    let mut window: Option<(i64, i64)> = None;
+    for c in conds {
+        match c {
+            Condition::WeekdayIn(m) => {
+                mask &= m;
+                weekdays += 1;
+            }
+            Condition::NowBetween { start, end } => {
+                let (s, e) = window.unwrap_or((*start, *end));
+                window = Some((s.max(*start), e.min(*end)));
+            }
+            _ => {}
+        }
+    }
+    if weekdays > 1 && mask == 0 {
+        out.push(never("the weekday lists have no day in common".to_owned()));
+    }
+    if let Some((s, e)) = window {
+        if s >= e {
+            out.push(never("the time windows do not overlap".to_owned()));
+        }
+    }
+}
+
+fn check_or(node: &Condition, conds: &[Condition], out: &mut Vec<Finding>) {
+    let always = |reason: String| Finding {
+        kind: FindingKind::Tautology,
+        message: format!("`{}` always matches, {}", node, reason),
+    };
+
+    for (i, a) in conds.iter().enumerate() {
+        for b in &conds[i + 1..] {
+            if is_negation_of(a, b) {
+                out.push(always(format!("one of `{}` and `{}` always holds", a, b)));
+            } else if let Some(key) = unary_pair(a, b).or_else(|| unary_pair(b, a)) {
+                out.push(always(format!("`{}` either exists or is missing", key)));
+            }
+        }
+    }
+
+    let mut mask = 0;
+    let mut weekdays = 0;
+    for c in conds {
+        if let Condition::WeekdayIn(m) = c {
+            mask |= m;
+            weekdays += 1;
+        }
+    }
+    if weekdays > 1 && mask == 0x7f {
+        out.push(always("the weekday lists cover every day".to_owned()));
+    }
+}
+
+// Under `&&`, a child implied by another child can be dropped. Under `||`, a child that
+// implies another child can be dropped.
+fn check_subsumed(node: &Condition, conds: &[Condition], is_and: bool, out: &mut Vec<Finding>) {
+    for (i, a) in conds.iter().enumerate() {
+        for (j, b) in conds.iter().enumerate() {
+            if i == j || a == b || !implies(a, b) {
+                continue;
+            }
+            // equivalent children, report only one of them
+            if implies(b, a) && i > j {
+                continue;
+            }
+            let (redundant, by) = if is_and { (b, a) } else { (a, b) };
+            push(
+                out,
+                FindingKind::Subsumed,
+                format!("`{}` is redundant in `{}`, `{}` already covers it", redundant, node, by),
+            );
+        }
+    }
+}
+
+#[test]
+fn test_analyze() {
+    use crate::parser::rule::do_parse;
+
+    let kinds = |rule: &str| {
+        let (_, mut cond) = do_parse(rule).unwrap();
+        cond.prepare().unwrap();
+        analyze(&cond).into_iter().map(|f| f.kind).collect::<Vec<_>>()
+    };
+
+    use FindingKind::*;
+    assert_eq!(kinds(r#"a == "1" && b == "2" || c in ["x", "y"]"#), vec![]);
+
+    assert_eq!(kinds(r#"a == "1" && a == "2""#), vec![Contradiction]);
+    assert_eq!(kinds(r#"a == "1" && a != "1""#), vec![Contradiction]);
+    assert_eq!(kinds(r#"a == "1" && a in ["2", "3"]"#), vec![Contradiction]);
+    assert_eq!(kinds(r#"a == "v" && a starts_with "x""#), vec![Contradiction]);
+    assert_eq!(kinds(r#"b == "1" && !(b == "1")"#), vec![Contradiction]);
+    assert_eq!(kinds(r#"exists(a) && missing(a)"#), vec![Contradiction]);
+    assert_eq!(kinds(r#"missing(a) && a != "1""#), vec![Contradiction]);
+    assert_eq!(kinds(r#"build > 10 && build < 5"#), vec![Contradiction]);
+    assert_eq!(kinds(r#"build >= 10 && build <= 10"#), vec![]);
+    assert_eq!(kinds(r#"build > 10 && build <= 10"#), vec![Contradiction]);
+    assert_eq!(kinds(r#"weekday in ["mon"] && weekday in ["sun"]"#), vec![Contradiction]);
+    assert_eq!(
+        kinds(r#"now between "2026-01-01" and "2026-02-01" && now between "2026-03-01" and "2026-04-01""#),
+        vec![Contradiction]
+    );
+    assert_eq!(kinds(r#"a in []"#), vec![Contradiction]);
+    assert_eq!(kinds(r#"weekday in []"#), vec![Contradiction]);
+
+    assert_eq!(kinds(r#"x == "1" || !(x == "1")"#), vec![Tautology]);
+    assert_eq!(kinds(r#"exists(a) || missing(a)"#), vec![Tautology]);
+    assert_eq!(kinds(r#"weekday in ["mon", "tue", "wed"] || weekday in ["thu", "fri", "sat", "sun"]"#), vec![Tautology]);
+    // a missing key fails both sides
+    assert_eq!(kinds(r#"a == "1" || a != "1""#), vec![]);
+
+    assert_eq!(kinds(r#"a == "1" && b == "2" && a == "1""#), vec![Duplicate]);
+    assert_eq!(kinds(r#"a == "1" || a == "1" || a == "1""#), vec![Duplicate]);
+
+    assert_eq!(kinds(r#"a == "1" || a == "1" && b == "2""#), vec![Subsumed]);
+    assert_eq!(kinds(r#"a == "1" && (a == "1" || b == "2")"#), vec![Subsumed]);
+    assert_eq!(kinds(r#"a == "x" && a in ["x", "y"]"#), vec![Subsumed]);
+    assert_eq!(kinds(r#"a == "x" || a in ["x", "y"]"#), vec![Subsumed]);
+    assert_eq!(kinds(r#"build > 10 && build > 5"#), vec![Subsumed]);
+    assert_eq!(kinds(r#"build > 10 || build >= 10"#), vec![Subsumed]);
+    assert_eq!(kinds(r#"a == "x" && a in ["x"]"#), vec![Subsumed]);
+
+    // nested branches are checked too
+    assert_eq!(kinds(r#"c == "1" || a == "1" && a == "2""#), vec![Contradiction]);
+
+    let (_, cond) = do_parse(r#"a == "1" && a == "2""#).unwrap();
+    assert_eq!(
+        analyze(&cond)[0].message,
+        r#"`a == "1" && a == "2"` can never match, `a == "1"` and `a == "2"` can not both hold"#
+    );
+}
@@ -0,0 +1,56 @@
+use sha2::{Digest, Sha256};
+
+use crate::error::RuleEvalError;
+
+use super::Value;
+
+/// Map a whoami value into a stable bucket in `[0, 100)` with a step of 0.01, used by
+/// `bucket(key, "salt") < 5%` in rules.
+///
+/// The algorithm is part of the rule file format and must never change, otherwise users
+/// would move in or out of a rollout when the library is upgraded:
+///
+/// 1. h = SHA-256(utf8(salt) || ":" || utf8(value))
+/// 2. n = the first 8 bytes of h as a big-endian u64
+/// 3. bucket = (n % 10000) / 100
+///
+/// Because the bucket of a value never changes, raising `< 5%` to `< 50%` only adds users
+/// to the rollout, nobody who already got the feature loses it. Use a different salt for
+/// each feature, so that the same 5% of users are not picked for everything.
+pub fn bucket_of(value: &str, salt: &str) -> f64 {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(b":");
+    hasher.update(value.as_bytes());
+    let h = hasher.finalize();
+
+    let mut n = [0u8; 8];
+    n.copy_from_slice(&h[..8]);
+    let n = u64::from_be_bytes(n);
+    return (n % 10000) as f64 / 100.0;
+}
+
+pub(crate) fn bucket_of_value(value: &Value, salt: &str) -> Result<f64, RuleEvalError> {
+    match value {
+        Value::Str(s) => return Ok(bucket_of(s, salt)),
+        Value::Int(i) => return Ok(bucket_of(&i.to_string(), salt)),
+        v => return Err(RuleEvalError::UnsupportedOperator("bucket", v.type_name())),
+    }
+}
+
+#[test]
+fn test_bucket_is_stable() {
+    // these numbers are part of the file format, see the doc of `bucket_of`,
+    // if this test fails, existing rollouts would be reshuffled
+    assert_eq!(bucket_of("user-1", "feature-x"), 29.52);
+    assert_eq!(bucket_of("user-2", "feature-x"), 47.84);
+    assert_eq!(bucket_of("user-1", "feature-y"), 41.51);
+    assert_eq!(bucket_of_value(&Value::Int(42), "s"), Ok(bucket_of("42", "s")));
+
+    // the buckets should be roughly uniform
+    let total = 20000;
+    let in_5_percent = (0..total)
+        .filter(|i| bucket_of(&format!("user-{}", i), "feature-x") < 5.0)
+        .count();
+    assert!(in_5_percent > total * 4 / 100 && in_5_percent < total * 6 / 100);
+}
@@ -0,0 +1,296 @@
+use std::fmt::{self, Write};
+
+use super::time::{format_timestamp, WEEKDAY_NAMES};
+use super::{Condition, LeafOperator, Value};
+
+// Canonical rule syntax, what `parser::rule::do_parse` reads back into an equal `Condition`.
+// Parentheses are only written where they are needed to keep the tree shape: around an `||`
+// inside `&&`, around a nested `&&` or `||` of the same kind, and around anything but an atom
+// after `!`.
+
+impl fmt::Display for LeafOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            LeafOperator::Eq => "==",
+            LeafOperator::Ne => "!=",
+            LeafOperator::Gt => ">",
+            LeafOperator::Ge => ">=",
+            LeafOperator::Lt => "<",
+            LeafOperator::Le => "<=",
+            LeafOperator::In => "in",
+            LeafOperator::NotIn => "not in",
+            LeafOperator::RegexMatch => "=~",
+            LeafOperator::Glob => "glob",
+            LeafOperator::StartsWith => "starts_with",
+            LeafOperator::EndsWith => "ends_with",
+            LeafOperator::InCidr => "in_cidr",
+            LeafOperator::Exists => "exists",
+            LeafOperator::Missing => "missing",
+        };
+        f.write_str(s)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write_str_literal(f, s),
+            Value::Int(i) => write!(f, "{}", i),
+            // `{:?}` always keeps a `.` or an exponent, so the literal stays a float
+            Value::Float(v) => write!(f, "{:?}", v),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::SemVer(v) => write!(f, "{}", v),
+            Value::List(items) => write_list(f, items),
+            Value::Set(set) => write_list(f, set.items()),
+            Value::Pattern(p) => write_str_literal(f, p.source()),
+            Value::Ip(ip) => write_str_literal(f, &ip.to_string()),
+            Value::Cidr(net) => write_str_literal(f, &net.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::And(conds) => write_logic(f, conds, "&&", |c| {
+                matches!(c, Condition::And(_) | Condition::Or(_))
+            }),
+            Condition::Or(conds) => write_logic(f, conds, "||", |c| matches!(c, Condition::Or(_))),
+            Condition::Not(cond) => {
+                if cond.is_atom() {
+                    write!(f, "!{}", cond)
+                } else {
+                    write!(f, "!({})", cond)
+                }
+            }
+            Condition::Leaf { lhs, op, rhs: None } => write!(f, "{}({})", op, lhs),
+            Condition::Leaf { lhs, op, rhs: Some(rhs) } => write!(f, "{} {} {}", lhs, op, rhs),
+            Condition::Bucket { lhs, salt, op, rhs } => {
+                write!(f, "bucket({}, ", lhs)?;
+                write_str_literal(f, salt)?;
+                write!(f, ") {} {}%", op, rhs)
+            }
+            Condition::NowBetween { start, end } => write!(
+                f,
+                "now between \"{}\" and \"{}\"",
+                format_timestamp(*start),
+                format_timestamp(*end)
+            ),
+            Condition::WeekdayIn(mask) => {
+                f.write_str("weekday in [")?;
+                let days = (0..7).filter(|day| mask & (1 << day) != 0);
+                for (i, day) in days.enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "\"{}\"", WEEKDAY_NAMES[day])?;
+                }
+                f.write_str("]")
+            }
+            Condition::RuleRef(path) => {
+                f.write_str("rule(")?;
+                write_str_literal(f, path)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl Condition {
+    fn is_atom(&self) -> bool {
+        return !matches!(self, Condition::And(_) | Condition::Or(_) | Condition::Not(_));
+    }
+}
+
+fn write_logic(
+    f: &mut fmt::Formatter<'_>,
+    conds: &[Condition],
+    op: &str,
+    needs_paren: impl Fn(&Condition) -> bool,
+) -> fmt::Result {
+    for (i, cond) in conds.iter().enumerate() {
+        if i > 0 {
+            write!(f, " {} ", op)?;
+        }
+        if needs_paren(cond) {
+            write!(f, "({})", cond)?;
+        } else {
+            write!(f, "{}", cond)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, items: &[Value]) -> fmt::Result {
+    f.write_str("[")?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    f.write_str("]")
+}
+
+fn write_str_literal(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\u{0008}' => f.write_str("\\b")?,
+            '\u{000C}' => f.write_str("\\f")?,
+            c if c.is_ascii_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+#[test]
+fn test_format_condition() {
+    use crate::parser::rule::do_parse;
+
+    let cases = [
+        r#"a == "x" && (b == 1 || c != 2.5) && !d > v1.2.3"#,
+        r#"(a == 1 && b == 2) && c == 3"#,
+        r#"a in ["x", 1, true] || b not in [] || !exists(c)"#,
+        r#"ip in_cidr ["10.0.0.0/8"] && host =~ "^web-\\d+$" && svc glob "pay*""#,
+        r#"bucket(user_id, "feature-x") < 5% && !(!missing(x))"#,
+        r#"now between "2026-11-01T00:00Z" and "2026-11-02T00:00:30Z" && weekday in ["sat", "sun"]"#,
+        r#"s == "quote\" slash\\ tab\t nl\n esc\u001b 中文""#,
+        r#"rule("/common/prod_eu.json") && !rule("/a \"b\".json")"#,
+    ];
+    for case in &cases {
+        let (rest, cond) = do_parse(case).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(cond.to_string(), *case);
+    }
+
+    let (_, cond) = do_parse("((a == 1)) || (b == 2 && (c == 3)) || !(d == 4)").unwrap();
+    assert_eq!(cond.to_string(), "a == 1 || b == 2 && c == 3 || !d == 4");
+}
+
+#[cfg(test)]
+fn random_canonical_value(rng: &mut super::program::XorShift) -> Value {
+    use super::SemVer;
+
+    match rng.below(5) {
+        0 => {
+            let chars = ['a', 'Z', '0', ' ', '"', '\\', '\n', '\t', '\u{1b}', '\u{7f}', 'é', '中', '/', '\''];
+            Value::Str((0..rng.below(6)).map(|_| chars[rng.below(chars.len())]).collect())
+        }
+        1 => Value::Int(rng.next() as i64 >> rng.below(64)),
+        2 => {
+            let v = f64::from_bits(rng.next());
+            if v.is_finite() {
+                Value::Float(v)
+            } else {
+                Value::Float(rng.below(1000) as f64 / 8.0 - 50.0)
+            }
+        }
+        3 => Value::Bool(rng.below(2) == 0),
+        _ => Value::SemVer(SemVer::new(rng.below(20) as u64, rng.below(20) as u64, rng.next() % 100000)),
+    }
+}
+
+#[cfg(test)]
+fn random_canonical_condition(rng: &mut super::program::XorShift, depth: usize) -> Condition {
+    let keys = ["region", "build", "user_id", "_k1", "nowhere"];
+    let kind = if depth == 0 { 3 + rng.below(6) } else { rng.below(9) };
+    match kind {
+        0 | 1 => {
+            // the parser never builds an `&&` or `||` with less than 2 children
+            let conds = (0..2 + rng.below(2)).map(|_| random_canonical_condition(rng, depth - 1)).collect();
+            if kind == 0 {
+                return Condition::And(conds);
+            }
+            return Condition::Or(conds);
+        }
+        2 => return Condition::Not(Box::new(random_canonical_condition(rng, depth - 1))),
+        3 => {
+            let start = (rng.next() % 4_000_000_000) as i64 - 1_000_000_000;
+            return Condition::NowBetween { start, end: start + 1 + rng.below(1_000_000) as i64 };
+        }
+        4 => return Condition::WeekdayIn(rng.below(128) as u8),
+        5 => {
+            let rhs = if rng.below(2) == 0 {
+                Value::Int(rng.below(101) as i64)
+            } else {
+                Value::Float(rng.below(10000) as f64 / 100.0)
+            };
+            return Condition::Bucket {
+                lhs: keys[rng.below(keys.len())].to_string(),
+                salt: match random_canonical_value(rng) {
+                    Value::Str(s) => s,
+                    _ => "salt".to_string(),
+                },
+                op: [LeafOperator::Lt, LeafOperator::Ge, LeafOperator::Eq][rng.below(3)].clone(),
+                rhs,
+            };
+        }
+        _ => {
+            let ops = [
+                LeafOperator::Eq,
+                LeafOperator::Ne,
+                LeafOperator::Gt,
+                LeafOperator::Ge,
+                LeafOperator::Lt,
+                LeafOperator::Le,
+                LeafOperator::In,
+                LeafOperator::NotIn,
+                LeafOperator::RegexMatch,
+                LeafOperator::Glob,
+                LeafOperator::StartsWith,
+                LeafOperator::EndsWith,
+                LeafOperator::InCidr,
+                LeafOperator::Exists,
+                LeafOperator::Missing,
+            ];
+            let op = ops[rng.below(ops.len())].clone();
+            let rhs = match op {
+                LeafOperator::In | LeafOperator::NotIn => {
+                    Some(Value::List((0..rng.below(4)).map(|_| random_canonical_value(rng)).collect()))
+                }
+                LeafOperator::RegexMatch => Some(Value::Str("^a[0-9]+\\.b$".to_string())),
+                LeafOperator::Glob => Some(Value::Str("web-*".to_string())),
+                LeafOperator::InCidr => Some(Value::List(vec![
+                    Value::Str("10.0.0.0/8".to_string()),
+                    Value::Str("fd00::/8".to_string()),
+                ])),
+                LeafOperator::Exists | LeafOperator::Missing => None,
+                _ => Some(random_canonical_value(rng)),
+            };
+            return Condition::Leaf {
+                lhs: keys[rng.below(keys.len())].to_string(),
+                op,
+                rhs,
+            };
+        }
+    }
+}
+
+#[test]
+fn test_format_round_trip() {
+    use crate::parser::rule::do_parse;
+
+    let mut rng = super::program::XorShift(0x2545f4914f6cdd1d);
+    for _ in 0..5000 {
+        let cond = random_canonical_condition(&mut rng, 4);
+        let text = cond.to_string();
+        let parsed = match do_parse(&text) {
+            Ok(("", parsed)) => parsed,
+            other => panic!("can not parse `{}`: {:?}", text, other),
+        };
+        assert_eq!(parsed, cond, "text = {}", text);
+
+        // prepared conditions print the same source
+        let mut prepared = cond.clone();
+        if prepared.prepare().is_ok() {
+            assert_eq!(prepared.to_string(), text);
+        }
+    }
+}
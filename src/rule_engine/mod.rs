@@ -1,8 +1,46 @@
+mod analysis;
+mod bucket;
+mod format;
+mod pattern;
+mod program;
+mod time;
+mod trace;
+mod value;
+
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
+use crate::error::RuleEvalError;
+
+pub use analysis::{analyze, Finding, FindingKind};
+#[cfg(test)]
+use bucket::bucket_of;
+pub use pattern::{Pattern, PatternKind};
+pub use program::{KeyInterner, Program};
+pub use time::{parse_timestamp, parse_weekday, Clock, SystemClock};
+#[cfg(test)]
+pub use time::ManualClock;
+pub use trace::EvalTrace;
+pub use value::{SemVer, Value, ValueSet};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum LeafOperator {
     Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    In,
+    NotIn,
+    RegexMatch,
+    Glob,
+    StartsWith,
+    EndsWith,
+    InCidr,
+    // unary operators, the leaf has no rhs
+    Exists,
+    Missing,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -15,68 +53,370 @@ pub enum Condition {
         op: LeafOperator,
         rhs: Option<Value>,
     },
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum Value {
-    Str(String),
+    // bucket(lhs, "salt") < 5%, see `bucket_of` for how the bucket is computed
+    Bucket {
+        lhs: String,
+        salt: String,
+        op: LeafOperator,
+        rhs: Value,
+    },
+    // now between "2026-11-01T00:00Z" and "2026-11-02T00:00Z", in unix seconds,
+    // `start` is inclusive and `end` is exclusive
+    NowBetween {
+        start: i64,
+        end: i64,
+    },
+    // weekday in ["sat", "sun"], days are in UTC, bit 0 is monday
+    WeekdayIn(u8),
+    // rule("/common/prod_eu.json"), the path of another rule under `/rules`, replaced by that
+    // rule when the index is built, see `Condition::inline_rule_refs`
+    RuleRef(String),
 }
 
 pub type MatchContext = HashMap<String, Value>;
 
 impl Condition {
+    // The tree walker is what the compiled `Program` is tested against, rules are only
+    // evaluated through a `Program` otherwise.
+
+    /// A rule that fails to evaluate, e.g., comparing a whoami value that is not a number
+    /// with an int literal, is treated as not matched.
+    #[cfg(test)]
     pub fn eval(&self, ctx: &MatchContext) -> bool {
+        return self.eval_at(ctx, SystemClock.now());
+    }
+
+    /// Like `eval`, time window conditions are evaluated at `now` (unix seconds).
+    #[cfg(test)]
+    pub fn eval_at(&self, ctx: &MatchContext, now: i64) -> bool {
+        return self.try_eval_at(ctx, now).unwrap_or(false);
+    }
+
+    /// Like `eval`, but report why the evaluation failed. An error in any evaluated leaf
+    /// fails the whole condition, `Not` does not turn an error into `true`.
+    #[cfg(test)]
+    pub fn try_eval(&self, ctx: &MatchContext) -> Result<bool, RuleEvalError> {
+        return self.try_eval_at(ctx, SystemClock.now());
+    }
+
+    #[cfg(test)]
+    pub fn try_eval_at(&self, ctx: &MatchContext, now: i64) -> Result<bool, RuleEvalError> {
         match self {
             Condition::And(conds) => {
                 for cond in conds {
-                    if cond.eval(ctx) == false {
-                        return false;
+                    if cond.try_eval_at(ctx, now)? == false {
+                        return Ok(false);
                     }
                 }
-                return true;
+                return Ok(true);
             }
             Condition::Or(conds) => {
                 for cond in conds {
-                    if cond.eval(ctx) == true {
-                        return true;
+                    if cond.try_eval_at(ctx, now)? == true {
+                        return Ok(true);
                     }
                 }
-                return false;
+                return Ok(false);
+            }
+            Condition::Not(cond) => return Ok(!cond.try_eval_at(ctx, now)?),
+            Condition::Leaf { lhs, .. } | Condition::Bucket { lhs, .. } => {
+                return self.eval_atom(ctx.get(lhs), now);
             }
-            Condition::Not(cond) => return !cond.eval(ctx),
-            Condition::Leaf { lhs, op, rhs } => {
-                return self.eval_leaf(ctx, lhs, op, rhs);
+            Condition::NowBetween { .. } | Condition::WeekdayIn(_) | Condition::RuleRef(_) => {
+                return self.eval_atom(None, now)
             }
         }
     }
 
+    /// Evaluate a condition that is not `And`, `Or` or `Not`, `lhs_value` is the whoami value
+    /// of its key, already looked up by the caller. Shared by the tree walker above and the
+    /// compiled `Program`.
     #[inline(always)]
-    fn eval_leaf(
-        &self,
-        ctx: &MatchContext,
-        lhs: &String,
-        op: &LeafOperator,
-        rhs: &Option<Value>,
-    ) -> bool {
-        let lhs_value = if let Some(v) = ctx.get(lhs) {
-            v
-        } else {
-            return false;
+    pub(crate) fn eval_atom(&self, lhs_value: Option<&Value>, now: i64) -> Result<bool, RuleEvalError> {
+        match self {
+            Condition::Leaf { op, rhs, .. } => {
+                return Self::eval_leaf(lhs_value, op, rhs.as_ref());
+            }
+            Condition::Bucket { salt, op, rhs, .. } => {
+                let lhs_value = match lhs_value {
+                    Some(v) => v,
+                    None => return Ok(false),
+                };
+                let b = Value::Float(bucket::bucket_of_value(lhs_value, salt)?);
+                return Self::eval_leaf(Some(&b), op, Some(rhs));
+            }
+            Condition::NowBetween { start, end } => return Ok(*start <= now && now < *end),
+            Condition::WeekdayIn(mask) => return Ok(mask & (1 << time::weekday_of(now)) != 0),
+            Condition::RuleRef(_) => return Err(RuleEvalError::MalformedLeaf("rule reference is not resolved")),
+            Condition::And(_) | Condition::Or(_) | Condition::Not(_) => {
+                return Err(RuleEvalError::MalformedLeaf("logic operator is not a leaf"))
+            }
+        }
+    }
+
+    /// Replace every `rule("/path")` in this condition with what `resolve` returns for the path.
+    pub fn inline_rule_refs<E>(
+        &mut self,
+        resolve: &mut dyn FnMut(&str) -> Result<Condition, E>,
+    ) -> Result<(), E> {
+        match self {
+            Condition::And(conds) | Condition::Or(conds) => {
+                for cond in conds {
+                    cond.inline_rule_refs(resolve)?;
+                }
+            }
+            Condition::Not(cond) => cond.inline_rule_refs(resolve)?,
+            Condition::RuleRef(path) => {
+                let cond = resolve(path)?;
+                *self = cond;
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    /// The first moment after `after` at which a time window in this condition opens or
+    /// closes, i.e., the result of the condition may change without any config change.
+    pub fn next_time_boundary(&self, after: i64) -> Option<i64> {
+        match self {
+            Condition::And(conds) | Condition::Or(conds) => {
+                return conds.iter().filter_map(|c| c.next_time_boundary(after)).min();
+            }
+            Condition::Not(cond) => return cond.next_time_boundary(after),
+            Condition::NowBetween { start, end } => {
+                if *start > after {
+                    return Some(*start);
+                } else if *end > after {
+                    return Some(*end);
+                }
+                return None;
+            }
+            Condition::WeekdayIn(mask) => return time::next_weekday_boundary(*mask, after),
+            Condition::Leaf { .. } | Condition::Bucket { .. } | Condition::RuleRef(_) => return None,
+        }
+    }
+
+    #[inline(always)]
+    fn eval_in(lhs_value: &Value, rhs_value: &Value) -> Result<bool, RuleEvalError> {
+        match rhs_value {
+            Value::Set(set) => return Ok(set.contains(lhs_value)),
+            // not prepared, still correct but slow
+            Value::List(items) => return Ok(lhs_value.is_in(items)),
+            _ => return Err(RuleEvalError::UnsupportedOperator("`in`", rhs_value.type_name())),
+        }
+    }
+
+    #[inline(always)]
+    fn str_operands<'a>(
+        op_name: &'static str,
+        lhs_value: &'a Value,
+        rhs_value: &'a Value,
+    ) -> Result<(&'a str, &'a str), RuleEvalError> {
+        match (lhs_value, rhs_value) {
+            (Value::Str(l), Value::Str(r)) => return Ok((l, r)),
+            (Value::Str(_), v) | (v, _) => {
+                return Err(RuleEvalError::UnsupportedOperator(op_name, v.type_name()))
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn eval_pattern(
+        kind: PatternKind,
+        lhs_value: &Value,
+        rhs_value: &Value,
+    ) -> Result<bool, RuleEvalError> {
+        let op_name = match kind {
+            PatternKind::Regex => "`=~`",
+            PatternKind::Glob => "`glob`",
+        };
+        let lhs_str = match lhs_value {
+            Value::Str(l) => l,
+            v => return Err(RuleEvalError::UnsupportedOperator(op_name, v.type_name())),
         };
+        match rhs_value {
+            Value::Pattern(p) => return Ok(p.is_match(lhs_str)),
+            // not prepared, still correct but slow
+            Value::Str(r) => {
+                let p = Pattern::new(kind, r)
+                    .map_err(|_| RuleEvalError::ConvertError(r.clone(), "pattern"))?;
+                return Ok(p.is_match(lhs_str));
+            }
+            v => return Err(RuleEvalError::UnsupportedOperator(op_name, v.type_name())),
+        }
+    }
+
+    /// Do the work that can be done once when a rule is loaded instead of on every query,
+    /// e.g., turn list literals into hash sets, compile regex and glob patterns, parse CIDRs,
+    /// and reject operands that can never be evaluated.
+    pub fn prepare(&mut self) -> Result<(), String> {
+        match self {
+            Condition::And(conds) | Condition::Or(conds) => {
+                for cond in conds {
+                    cond.prepare()?;
+                }
+            }
+            Condition::Not(cond) => cond.prepare()?,
+            Condition::Leaf { lhs, op, rhs } => match (&*op, rhs) {
+                (LeafOperator::In, Some(rhs_value)) | (LeafOperator::NotIn, Some(rhs_value)) => {
+                    match rhs_value {
+                        Value::List(items) => {
+                            let items = std::mem::take(items);
+                            *rhs_value = Value::Set(Box::new(ValueSet::new(items)));
+                        }
+                        Value::Set(_) => {}
+                        _ => {
+                            return Err(format!(
+                                "right side of `in` must be a list, key = `{}`",
+                                lhs
+                            ))
+                        }
+                    }
+                }
+                (LeafOperator::RegexMatch, Some(rhs_value)) | (LeafOperator::Glob, Some(rhs_value)) => {
+                    let kind = if *op == LeafOperator::RegexMatch {
+                        PatternKind::Regex
+                    } else {
+                        PatternKind::Glob
+                    };
+                    match rhs_value {
+                        Value::Str(source) => {
+                            *rhs_value = Value::Pattern(Pattern::new(kind, source)?);
+                        }
+                        Value::Pattern(_) => {}
+                        _ => {
+                            return Err(format!(
+                                "right side of a pattern match must be a string, key = `{}`",
+                                lhs
+                            ))
+                        }
+                    }
+                }
+                (LeafOperator::InCidr, Some(rhs_value)) => {
+                    *rhs_value = Self::prepare_cidrs(lhs, rhs_value)?;
+                }
+                (LeafOperator::StartsWith, Some(rhs_value)) | (LeafOperator::EndsWith, Some(rhs_value))
+                    if !matches!(rhs_value, Value::Str(_)) =>
+                {
+                    return Err(format!(
+                        "right side of starts_with / ends_with must be a string, key = `{}`",
+                        lhs
+                    ));
+                }
+                (LeafOperator::Exists, Some(_)) | (LeafOperator::Missing, Some(_)) => {
+                    return Err(format!("`exists` and `missing` take no right side, key = `{}`", lhs))
+                }
+                (LeafOperator::Exists, None) | (LeafOperator::Missing, None) => {}
+                (_, None) => return Err(format!("missing right side, key = `{}`", lhs)),
+                (_, Some(Value::List(_))) | (_, Some(Value::Set(_))) => {
+                    return Err(format!(
+                        "list can only be used with `in` and `not in`, key = `{}`",
+                        lhs
+                    ))
+                }
+                _ => {}
+            },
+            Condition::Bucket { lhs, op, rhs, .. } => {
+                match op {
+                    LeafOperator::Eq
+                    | LeafOperator::Ne
+                    | LeafOperator::Gt
+                    | LeafOperator::Ge
+                    | LeafOperator::Lt
+                    | LeafOperator::Le => {}
+                    _ => return Err(format!("bucket only supports comparison, key = `{}`", lhs)),
+                }
+                if !matches!(rhs, Value::Int(_) | Value::Float(_)) {
+                    return Err(format!("bucket must be compared with a percentage, key = `{}`", lhs));
+                }
+            }
+            Condition::NowBetween { start, end } => {
+                if start >= end {
+                    return Err(format!(
+                        "time window ends before it starts, {} >= {}",
+                        time::format_timestamp(*start),
+                        time::format_timestamp(*end)
+                    ));
+                }
+            }
+            Condition::WeekdayIn(_) | Condition::RuleRef(_) => {}
+        }
+        return Ok(());
+    }
 
+    fn prepare_cidrs(lhs: &str, rhs_value: &Value) -> Result<Value, String> {
+        match rhs_value {
+            Value::Str(s) => {
+                let net = value::parse_cidr(s).map_err(|e| format!("{}, key = `{}`", e, lhs))?;
+                return Ok(Value::Cidr(net));
+            }
+            Value::Cidr(_) => return Ok(rhs_value.clone()),
+            Value::List(items) => {
+                let mut nets = Vec::with_capacity(items.len());
+                for item in items {
+                    if let Value::List(_) = item {
+                        return Err(format!("nested list is not allowed, key = `{}`", lhs));
+                    }
+                    nets.push(Self::prepare_cidrs(lhs, item)?);
+                }
+                return Ok(Value::List(nets));
+            }
+            _ => {
+                return Err(format!(
+                    "right side of `in_cidr` must be a CIDR string or a list of them, key = `{}`",
+                    lhs
+                ))
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn eval_leaf(
+        lhs_value: Option<&Value>,
+        op: &LeafOperator,
+        rhs: Option<&Value>,
+    ) -> Result<bool, RuleEvalError> {
         if let Some(rhs_value) = rhs {
-            // this branch is for binary operators
+            // this branch is for binary operators, a key that the client does not report
+            // never matches, not even with `!=` or `not in`
+            let lhs_value = if let Some(v) = lhs_value {
+                v
+            } else {
+                return Ok(false);
+            };
+
             match op {
-                LeafOperator::Eq => match (lhs_value, rhs_value) {
-                    (Value::Str(lv), Value::Str(rv)) => return lv == rv,
-                },
+                LeafOperator::Eq => return lhs_value.equals(rhs_value),
+                LeafOperator::Ne => return Ok(!lhs_value.equals(rhs_value)?),
+                LeafOperator::Gt => return Ok(lhs_value.compare(rhs_value)? == Ordering::Greater),
+                LeafOperator::Ge => return Ok(lhs_value.compare(rhs_value)? != Ordering::Less),
+                LeafOperator::Lt => return Ok(lhs_value.compare(rhs_value)? == Ordering::Less),
+                LeafOperator::Le => return Ok(lhs_value.compare(rhs_value)? != Ordering::Greater),
+                LeafOperator::In => return Self::eval_in(lhs_value, rhs_value),
+                LeafOperator::NotIn => return Ok(!Self::eval_in(lhs_value, rhs_value)?),
+                LeafOperator::RegexMatch => {
+                    return Self::eval_pattern(PatternKind::Regex, lhs_value, rhs_value)
+                }
+                LeafOperator::Glob => return Self::eval_pattern(PatternKind::Glob, lhs_value, rhs_value),
+                LeafOperator::StartsWith => {
+                    let (l, r) = Self::str_operands("starts_with", lhs_value, rhs_value)?;
+                    return Ok(l.starts_with(r));
+                }
+                LeafOperator::EndsWith => {
+                    let (l, r) = Self::str_operands("ends_with", lhs_value, rhs_value)?;
+                    return Ok(l.ends_with(r));
+                }
+                LeafOperator::InCidr => return lhs_value.is_within(rhs_value),
+                LeafOperator::Exists | LeafOperator::Missing => {
+                    return Err(RuleEvalError::MalformedLeaf("unary operator with a right side"))
+                }
             }
         } else {
             // this branch is for unary operators
             match op {
-                _ => {
-                    panic!("should not reach here")
-                }
+                LeafOperator::Exists => return Ok(lhs_value.is_some()),
+                LeafOperator::Missing => return Ok(lhs_value.is_none()),
+                _ => return Err(RuleEvalError::MalformedLeaf("binary operator without a right side")),
             }
         }
     }
@@ -135,3 +475,272 @@ fn test_logic_op() {
     ]);
     assert!(cond.eval(&ctx) == true);
 }
+
+
+#[test]
+fn test_leaf_compare_ops() {
+    let mut ctx = MatchContext::new();
+    ctx.insert("region".into(), Value::Str("us".into()));
+
+    let leaf = |op: LeafOperator, rhs: &str| Condition::Leaf {
+        lhs: "region".to_string(),
+        op,
+        rhs: Some(Value::Str(rhs.to_string())),
+    };
+
+    assert!(leaf(LeafOperator::Ne, "cn").eval(&ctx) == true);
+    assert!(leaf(LeafOperator::Ne, "us").eval(&ctx) == false);
+    assert!(leaf(LeafOperator::Gt, "cn").eval(&ctx) == true);
+    assert!(leaf(LeafOperator::Ge, "us").eval(&ctx) == true);
+    assert!(leaf(LeafOperator::Lt, "us").eval(&ctx) == false);
+    assert!(leaf(LeafOperator::Le, "us").eval(&ctx) == true);
+    assert!(leaf(LeafOperator::Lt, "zz").eval(&ctx) == true);
+
+    // a missing key never matches, not even with `!=`
+    let cond = Condition::Leaf {
+        lhs: "missing".to_string(),
+        op: LeafOperator::Ne,
+        rhs: Some(Value::Str("cn".to_string())),
+    };
+    assert!(cond.eval(&ctx) == false);
+}
+
+#[test]
+fn test_typed_leaf_eval() {
+    let mut ctx = MatchContext::new();
+    ctx.insert("build".into(), Value::Str("1300".into()));
+    ctx.insert("canary".into(), Value::Str("true".into()));
+    ctx.insert("app_ver".into(), Value::Str("v2.10.1".into()));
+    ctx.insert("weight".into(), Value::Float(0.75));
+
+    let leaf = |lhs: &str, op: LeafOperator, rhs: Value| Condition::Leaf {
+        lhs: lhs.to_string(),
+        op,
+        rhs: Some(rhs),
+    };
+
+    // "1300" > "200" is false as strings, but true as numbers
+    assert!(leaf("build", LeafOperator::Gt, Value::Int(200)).eval(&ctx) == true);
+    assert!(leaf("build", LeafOperator::Gt, Value::Str("200".into())).eval(&ctx) == false);
+    assert!(leaf("canary", LeafOperator::Eq, Value::Bool(true)).eval(&ctx) == true);
+    assert!(leaf("app_ver", LeafOperator::Ge, Value::SemVer(SemVer::new(2, 9, 0))).eval(&ctx) == true);
+    assert!(leaf("weight", LeafOperator::Lt, Value::Int(1)).eval(&ctx) == true);
+
+    // mismatches are errors, and a failed leaf is not rescued by `!`
+    let cond = leaf("canary", LeafOperator::Gt, Value::Int(1));
+    assert_eq!(
+        cond.try_eval(&ctx),
+        Err(RuleEvalError::ConvertError("true".into(), "number"))
+    );
+    let cond = Condition::Not(Box::new(cond));
+    assert!(cond.try_eval(&ctx).is_err());
+    assert!(cond.eval(&ctx) == false);
+}
+
+#[test]
+fn test_in_op() {
+    let mut ctx = MatchContext::new();
+    ctx.insert("region".into(), Value::Str("eu-west".into()));
+    ctx.insert("build".into(), Value::Str("42".into()));
+
+    let mut cond = Condition::Leaf {
+        lhs: "region".to_string(),
+        op: LeafOperator::In,
+        rhs: Some(Value::List(vec![
+            Value::Str("us-east".into()),
+            Value::Str("eu-west".into()),
+        ])),
+    };
+    assert!(cond.eval(&ctx) == true);
+    cond.prepare().unwrap();
+    assert!(matches!(cond, Condition::Leaf { rhs: Some(Value::Set(_)), .. }));
+    assert!(cond.eval(&ctx) == true);
+
+    let mut cond = Condition::Leaf {
+        lhs: "build".to_string(),
+        op: LeafOperator::NotIn,
+        rhs: Some(Value::List(vec![Value::Int(41), Value::Int(43)])),
+    };
+    cond.prepare().unwrap();
+    assert!(cond.eval(&ctx) == true);
+
+    let mut cond = Condition::Leaf {
+        lhs: "build".to_string(),
+        op: LeafOperator::In,
+        rhs: Some(Value::Int(42)),
+    };
+    assert!(cond.prepare().is_err());
+
+    let mut cond = Condition::Leaf {
+        lhs: "build".to_string(),
+        op: LeafOperator::Eq,
+        rhs: Some(Value::List(vec![Value::Int(42)])),
+    };
+    assert!(cond.prepare().is_err());
+}
+
+#[test]
+fn test_pattern_ops() {
+    let mut ctx = MatchContext::new();
+    ctx.insert("host".into(), Value::Str("web-12.prod.example.com".into()));
+    ctx.insert("svc".into(), Value::Str("payment-api".into()));
+    ctx.insert("build".into(), Value::Int(3));
+
+    let leaf = |lhs: &str, op: LeafOperator, rhs: &str| Condition::Leaf {
+        lhs: lhs.to_string(),
+        op,
+        rhs: Some(Value::Str(rhs.to_string())),
+    };
+
+    let cases = vec![
+        (leaf("host", LeafOperator::RegexMatch, "^web-[0-9]+\\.prod"), true),
+        (leaf("host", LeafOperator::RegexMatch, "^db-"), false),
+        (leaf("svc", LeafOperator::Glob, "payment-*"), true),
+        (leaf("svc", LeafOperator::Glob, "payment"), false),
+        (leaf("svc", LeafOperator::StartsWith, "pay"), true),
+        (leaf("svc", LeafOperator::EndsWith, "-api"), true),
+        (leaf("svc", LeafOperator::EndsWith, "-web"), false),
+    ];
+    for (mut cond, expected) in cases {
+        assert_eq!(cond.eval(&ctx), expected, "{:?}", cond);
+        cond.prepare().unwrap();
+        assert_eq!(cond.eval(&ctx), expected, "{:?}", cond);
+    }
+
+    let mut cond = leaf("host", LeafOperator::RegexMatch, "web-(");
+    assert!(cond.prepare().is_err());
+
+    let cond = leaf("build", LeafOperator::StartsWith, "3");
+    assert_eq!(
+        cond.try_eval(&ctx),
+        Err(RuleEvalError::UnsupportedOperator("starts_with", "int"))
+    );
+}
+
+#[test]
+fn test_unary_ops() {
+    let mut ctx = MatchContext::new();
+    ctx.insert("canary".into(), Value::Str("".into()));
+
+    let leaf = |lhs: &str, op: LeafOperator| Condition::Leaf {
+        lhs: lhs.to_string(),
+        op,
+        rhs: None,
+    };
+
+    assert!(leaf("canary", LeafOperator::Exists).eval(&ctx) == true);
+    assert!(leaf("canary", LeafOperator::Missing).eval(&ctx) == false);
+    assert!(leaf("region", LeafOperator::Exists).eval(&ctx) == false);
+    assert!(leaf("region", LeafOperator::Missing).eval(&ctx) == true);
+
+    // malformed leaves are errors instead of panics
+    let mut cond = leaf("region", LeafOperator::Eq);
+    assert!(cond.try_eval(&ctx).is_err());
+    assert!(cond.prepare().is_err());
+    let mut cond = Condition::Leaf {
+        lhs: "canary".to_string(),
+        op: LeafOperator::Exists,
+        rhs: Some(Value::Int(1)),
+    };
+    assert!(cond.try_eval(&ctx).is_err());
+    assert!(cond.prepare().is_err());
+}
+
+#[test]
+fn test_bucket_cond() {
+    let cond = |rhs: f64| Condition::Bucket {
+        lhs: "user_id".to_string(),
+        salt: "feature-x".to_string(),
+        op: LeafOperator::Lt,
+        rhs: Value::Float(rhs),
+    };
+
+    let mut ctx = MatchContext::new();
+    ctx.insert("user_id".into(), Value::Str("user-1".into()));
+    let b = bucket_of("user-1", "feature-x");
+
+    assert!(cond(b).eval(&ctx) == false);
+    assert!(cond(b + 0.01).eval(&ctx) == true);
+    assert!(cond(100.0).eval(&ctx) == true);
+    assert!(cond(0.0).eval(&ctx) == false);
+
+    // no user_id, no rollout
+    assert!(cond(100.0).eval(&MatchContext::new()) == false);
+
+    let mut bad = Condition::Bucket {
+        lhs: "user_id".to_string(),
+        salt: "feature-x".to_string(),
+        op: LeafOperator::In,
+        rhs: Value::Float(5.0),
+    };
+    assert!(bad.prepare().is_err());
+}
+
+#[test]
+fn test_in_cidr_op() {
+    let mut ctx = MatchContext::new();
+    ctx.insert("ip".into(), Value::Str("10.3.2.1".into()));
+
+    let mut cond = Condition::Leaf {
+        lhs: "ip".to_string(),
+        op: LeafOperator::InCidr,
+        rhs: Some(Value::List(vec![
+            Value::Str("192.168.0.0/16".into()),
+            Value::Str("10.0.0.0/8".into()),
+        ])),
+    };
+    assert!(cond.eval(&ctx) == true);
+    cond.prepare().unwrap();
+    assert!(cond.eval(&ctx) == true);
+
+    let mut cond = Condition::Leaf {
+        lhs: "ip".to_string(),
+        op: LeafOperator::InCidr,
+        rhs: Some(Value::Str("2001:db8::/32".into())),
+    };
+    cond.prepare().unwrap();
+    assert!(matches!(cond, Condition::Leaf { rhs: Some(Value::Cidr(_)), .. }));
+    assert!(cond.eval(&ctx) == false);
+
+    let mut cond = Condition::Leaf {
+        lhs: "ip".to_string(),
+        op: LeafOperator::InCidr,
+        rhs: Some(Value::List(vec![Value::Str("10.0.0.0/40".into())])),
+    };
+    assert!(cond.prepare().is_err());
+}
+
+#[test]
+fn test_time_conds() {
+    let ctx = MatchContext::new();
+    let start = parse_timestamp("2026-11-01T00:00Z").unwrap();
+    let end = parse_timestamp("2026-11-02T00:00Z").unwrap();
+
+    let cond = Condition::NowBetween { start, end };
+    assert!(cond.eval_at(&ctx, start - 1) == false);
+    assert!(cond.eval_at(&ctx, start) == true);
+    assert!(cond.eval_at(&ctx, end - 1) == true);
+    assert!(cond.eval_at(&ctx, end) == false);
+    assert_eq!(cond.next_time_boundary(start - 100), Some(start));
+    assert_eq!(cond.next_time_boundary(start), Some(end));
+    assert_eq!(cond.next_time_boundary(end), None);
+
+    // sat and sun, 2026-11-01 is a sunday
+    let weekend = Condition::WeekdayIn(0b110_0000);
+    assert!(weekend.eval_at(&ctx, start) == true);
+    assert!(weekend.eval_at(&ctx, end) == false);
+
+    let cond = Condition::Or(vec![
+        Condition::Not(Box::new(weekend)),
+        Condition::NowBetween { start: end + 3600, end: end + 7200 },
+    ]);
+    assert_eq!(cond.next_time_boundary(start), Some(end));
+    assert_eq!(cond.next_time_boundary(end), Some(end + 3600));
+
+    let clock = ManualClock::new(start);
+    clock.advance(86400);
+    assert_eq!(clock.now(), end);
+
+    let mut cond = Condition::NowBetween { start: end, end: start };
+    assert!(cond.prepare().is_err());
+}
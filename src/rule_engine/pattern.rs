@@ -0,0 +1,138 @@
+use regex::Regex;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PatternKind {
+    Regex,
+    Glob,
+}
+
+/// A compiled `=~` or `glob` operand, the source text is kept for equality and display.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    kind: PatternKind,
+    source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(kind: PatternKind, source: &str) -> Result<Self, String> {
+        let regex = match kind {
+            PatternKind::Regex => Regex::new(source).map_err(|e| e.to_string()),
+            PatternKind::Glob => glob_to_regex(source).and_then(|r| Regex::new(&r).map_err(|e| e.to_string())),
+        }
+        .map_err(|e| format!("invalid {:?} pattern `{}`: {}", kind, source, e))?;
+
+        return Ok(Self {
+            kind,
+            source: source.to_owned(),
+            regex,
+        });
+    }
+
+    pub fn kind(&self) -> PatternKind {
+        self.kind
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        self.regex.is_match(s)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.source == other.source
+    }
+}
+
+// glob syntax, the whole string must match:
+// *        any sequence of chars except `/`
+// **       any sequence of chars
+// ?        any single char except `/`
+// [abc]    one of the chars, ranges like [a-z] and negation like [!a-z] are supported
+// \x       the char x itself
+pub fn glob_to_regex(glob: &str) -> Result<String, String> {
+    let mut ret = String::with_capacity(glob.len() * 2 + 2);
+    ret.push('^');
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => {
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                    ret.push_str(".*");
+                } else {
+                    ret.push_str("[^/]*");
+                }
+            }
+            '?' => ret.push_str("[^/]"),
+            '[' => {
+                let mut class = String::new();
+                let mut closed = false;
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    class.push('^');
+                }
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' | '[' | '&' | '~' | '^' => {
+                            class.push('\\');
+                            class.push(c);
+                        }
+                        _ => class.push(c),
+                    }
+                }
+                if !closed {
+                    return Err("unclosed `[` in glob".to_string());
+                }
+                ret.push('[');
+                ret.push_str(&class);
+                ret.push(']');
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    ret.push_str(&regex::escape(&c.to_string()));
+                } else {
+                    ret.push_str(r"\\");
+                }
+            }
+            _ => ret.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    ret.push('$');
+    return Ok(ret);
+}
+
+#[test]
+fn test_glob() {
+    let p = Pattern::new(PatternKind::Glob, "payment-*").unwrap();
+    assert!(p.is_match("payment-api"));
+    assert!(p.is_match("payment-"));
+    assert!(!p.is_match("xpayment-api"));
+    assert!(!p.is_match("payment-api/v2"));
+
+    let p = Pattern::new(PatternKind::Glob, "/dir/**.json").unwrap();
+    assert!(p.is_match("/dir/a/b.json"));
+    assert!(!p.is_match("/dir/a/b.yaml"));
+
+    let p = Pattern::new(PatternKind::Glob, "web-[0-9]?.prod").unwrap();
+    assert!(p.is_match("web-1a.prod"));
+    assert!(!p.is_match("web-xa.prod"));
+    assert!(!p.is_match("web-1a-prod"));
+
+    let p = Pattern::new(PatternKind::Glob, "[!a]\\*").unwrap();
+    assert!(p.is_match("b*"));
+    assert!(!p.is_match("a*"));
+    assert!(!p.is_match("bb"));
+
+    assert!(Pattern::new(PatternKind::Regex, "^web-[0-9]+\\.prod").unwrap().is_match("web-12.prod.example"));
+    assert!(Pattern::new(PatternKind::Regex, "web-(").is_err());
+    assert!(Pattern::new(PatternKind::Glob, "web-[a-").is_err());
+}
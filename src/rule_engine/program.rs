@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use crate::error::RuleEvalError;
+
+use super::{Condition, MatchContext, Value};
+
+/// Maps whoami keys used by rules to slot numbers. One interner is shared by all rules of
+/// an index, so a `MatchContext` is looked up once per query instead of once per leaf.
+#[derive(Debug, Default)]
+pub struct KeyInterner {
+    slots: HashMap<String, usize>,
+}
+
+impl KeyInterner {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn intern(&mut self, key: &str) -> usize {
+        if let Some(slot) = self.slots.get(key) {
+            return *slot;
+        }
+        let slot = self.slots.len();
+        self.slots.insert(key.to_owned(), slot);
+        return slot;
+    }
+
+    /// Convert a whoami into the slot array that `Program::try_eval` reads, keys that no rule
+    /// uses are dropped.
+    pub fn resolve<'a>(&self, ctx: &'a MatchContext) -> Vec<Option<&'a Value>> {
+        let mut ret = vec![None; self.slots.len()];
+        for (key, value) in ctx {
+            if let Some(slot) = self.slots.get(key) {
+                ret[*slot] = Some(value);
+            }
+        }
+        return ret;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Instr {
+    // acc = result of atoms[n], an error stops the program
+    Test(usize),
+    Const(bool),
+    Not,
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+}
+
+/// A `Condition` flattened into a list of instructions working on a single bool accumulator,
+/// `&&` and `||` become short-circuit jumps. Atoms (leaves, buckets and time windows) are
+/// evaluated by `Condition::eval_atom`, so a program returns exactly what the tree walker
+/// returns, errors included.
+#[derive(Debug)]
+pub struct Program {
+    code: Vec<Instr>,
+    // the slot of the atom's key, None for atoms without a key
+    atoms: Vec<(Option<usize>, Condition)>,
+}
+
+impl Program {
+    pub fn compile(cond: &Condition, keys: &mut KeyInterner) -> Self {
+        let mut ret = Program {
+            code: Vec::new(),
+            atoms: Vec::new(),
+        };
+        ret.emit(cond, keys);
+        return ret;
+    }
+
+    fn emit(&mut self, cond: &Condition, keys: &mut KeyInterner) {
+        match cond {
+            Condition::And(conds) => self.emit_logic(conds, false, keys),
+            Condition::Or(conds) => self.emit_logic(conds, true, keys),
+            Condition::Not(cond) => {
+                self.emit(cond, keys);
+                self.code.push(Instr::Not);
+            }
+            Condition::Leaf { lhs, .. } | Condition::Bucket { lhs, .. } => {
+                let slot = keys.intern(lhs);
+                self.atoms.push((Some(slot), cond.clone()));
+                self.code.push(Instr::Test(self.atoms.len() - 1));
+            }
+            Condition::NowBetween { .. } | Condition::WeekdayIn(_) | Condition::RuleRef(_) => {
+                self.atoms.push((None, cond.clone()));
+                self.code.push(Instr::Test(self.atoms.len() - 1));
+            }
+        }
+    }
+
+    // `&&` stops at the first false child and `||` at the first true one, in both cases
+    // the accumulator already holds the result when jumping to the end
+    fn emit_logic(&mut self, conds: &[Condition], stop_on: bool, keys: &mut KeyInterner) {
+        if conds.is_empty() {
+            self.code.push(Instr::Const(!stop_on));
+            return;
+        }
+        let mut jumps = Vec::with_capacity(conds.len() - 1);
+        for (i, cond) in conds.iter().enumerate() {
+            self.emit(cond, keys);
+            if i + 1 < conds.len() {
+                jumps.push(self.code.len());
+                self.code.push(Instr::Const(false)); // patched below
+            }
+        }
+        let end = self.code.len();
+        for pos in jumps {
+            self.code[pos] = if stop_on {
+                Instr::JumpIfTrue(end)
+            } else {
+                Instr::JumpIfFalse(end)
+            };
+        }
+    }
+
+    pub fn eval(&self, slots: &[Option<&Value>], now: i64) -> bool {
+        return self.try_eval(slots, now).unwrap_or(false);
+    }
+
+    pub fn try_eval(&self, slots: &[Option<&Value>], now: i64) -> Result<bool, RuleEvalError> {
+        let mut acc = false;
+        let mut pc = 0;
+        while pc < self.code.len() {
+            match self.code[pc] {
+                Instr::Test(n) => {
+                    let (slot, atom) = &self.atoms[n];
+                    let lhs_value = match slot {
+                        Some(slot) => slots.get(*slot).copied().flatten(),
+                        None => None,
+                    };
+                    acc = atom.eval_atom(lhs_value, now)?;
+                }
+                Instr::Const(b) => acc = b,
+                Instr::Not => acc = !acc,
+                Instr::JumpIfFalse(target) => {
+                    if acc == false {
+                        pc = target;
+                        continue;
+                    }
+                }
+                Instr::JumpIfTrue(target) => {
+                    if acc == true {
+                        pc = target;
+                        continue;
+                    }
+                }
+            }
+            pc += 1;
+        }
+        return Ok(acc);
+    }
+}
+
+#[cfg(test)]
+pub(super) struct XorShift(pub(super) u64);
+
+#[cfg(test)]
+impl XorShift {
+    pub(super) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        return self.0;
+    }
+
+    pub(super) fn below(&mut self, n: usize) -> usize {
+        return (self.next() % n as u64) as usize;
+    }
+}
+
+#[cfg(test)]
+fn random_value(rng: &mut XorShift) -> Value {
+    use super::SemVer;
+
+    match rng.below(6) {
+        0 | 1 => Value::Str(["1", "2", "x", "v1.2.3", "true"][rng.below(5)].to_string()),
+        2 => Value::Int(rng.below(3) as i64),
+        3 => Value::Float(rng.below(3) as f64 + 0.5),
+        4 => Value::Bool(rng.below(2) == 0),
+        _ => Value::SemVer(SemVer::new(1, rng.below(3) as u64, 3)),
+    }
+}
+
+#[cfg(test)]
+fn random_condition(rng: &mut XorShift, depth: usize) -> Condition {
+    use super::LeafOperator;
+
+    let keys = ["a", "b", "c", "d"];
+    let kind = if depth == 0 { 3 + rng.below(6) } else { rng.below(9) };
+    match kind {
+        0 | 1 => {
+            let conds = (0..rng.below(4)).map(|_| random_condition(rng, depth - 1)).collect();
+            if kind == 0 {
+                return Condition::And(conds);
+            }
+            return Condition::Or(conds);
+        }
+        2 => return Condition::Not(Box::new(random_condition(rng, depth - 1))),
+        3 => return Condition::NowBetween { start: 100, end: 200 },
+        4 => return Condition::WeekdayIn(rng.below(128) as u8),
+        5 => {
+            return Condition::Bucket {
+                lhs: keys[rng.below(4)].to_string(),
+                salt: "s".to_string(),
+                op: LeafOperator::Lt,
+                rhs: Value::Int(50),
+            }
+        }
+        _ => {
+            let ops = [
+                LeafOperator::Eq,
+                LeafOperator::Ne,
+                LeafOperator::Gt,
+                LeafOperator::Le,
+                LeafOperator::In,
+                LeafOperator::NotIn,
+                LeafOperator::StartsWith,
+                LeafOperator::Glob,
+                LeafOperator::Exists,
+                LeafOperator::Missing,
+            ];
+            let op = ops[rng.below(ops.len())].clone();
+            let rhs = match op {
+                LeafOperator::In | LeafOperator::NotIn => {
+                    Some(Value::List((0..rng.below(4)).map(|_| random_value(rng)).collect()))
+                }
+                LeafOperator::StartsWith => Some(Value::Str("v".to_string())),
+                LeafOperator::Glob => Some(Value::Str("[0-9]".to_string())),
+                LeafOperator::Exists | LeafOperator::Missing => None,
+                _ => Some(random_value(rng)),
+            };
+            return Condition::Leaf {
+                lhs: keys[rng.below(4)].to_string(),
+                op,
+                rhs,
+            };
+        }
+    }
+}
+
+#[test]
+fn test_program_matches_tree_eval() {
+    let mut rng = XorShift(0x9e3779b97f4a7c15);
+    let mut keys = KeyInterner::new();
+    let mut checked = 0;
+
+    for _ in 0..3000 {
+        let mut cond = random_condition(&mut rng, 4);
+        if rng.below(2) == 0 && cond.prepare().is_err() {
+            continue;
+        }
+        let program = Program::compile(&cond, &mut keys);
+
+        for _ in 0..8 {
+            let mut ctx = MatchContext::new();
+            for key in &["a", "b", "c", "d", "unused"] {
+                if rng.below(4) != 0 {
+                    ctx.insert(key.to_string(), random_value(&mut rng));
+                }
+            }
+            let now = [50, 150, 250][rng.below(3)] + 86400 * rng.below(7) as i64;
+            let slots = keys.resolve(&ctx);
+            assert_eq!(
+                program.try_eval(&slots, now),
+                cond.try_eval_at(&ctx, now),
+                "cond = {:?}, ctx = {:?}",
+                cond,
+                ctx
+            );
+            checked += 1;
+        }
+    }
+    assert!(checked > 10000);
+}
+
+#[test]
+fn test_program_short_circuit() {
+    use super::LeafOperator;
+
+    let mut keys = KeyInterner::new();
+    // the second leaf would fail with a type mismatch, but is never reached
+    let bad = Condition::Leaf {
+        lhs: "a".to_string(),
+        op: LeafOperator::Gt,
+        rhs: Some(Value::Bool(true)),
+    };
+    let missing = Condition::Leaf {
+        lhs: "b".to_string(),
+        op: LeafOperator::Exists,
+        rhs: None,
+    };
+    let cond = Condition::And(vec![missing.clone(), bad.clone()]);
+    let program = Program::compile(&cond, &mut keys);
+
+    let mut ctx = MatchContext::new();
+    ctx.insert("a".to_string(), Value::Int(1));
+    assert_eq!(program.try_eval(&keys.resolve(&ctx), 0), Ok(false));
+
+    ctx.insert("b".to_string(), Value::Int(1));
+    assert!(program.try_eval(&keys.resolve(&ctx), 0).is_err());
+
+    ctx.remove("b");
+    let program = Program::compile(&Condition::Or(vec![Condition::Not(Box::new(missing)), bad]), &mut keys);
+    assert_eq!(program.try_eval(&keys.resolve(&ctx), 0), Ok(true));
+    assert_eq!(Program::compile(&Condition::And(vec![]), &mut keys).try_eval(&[], 0), Ok(true));
+    assert_eq!(Program::compile(&Condition::Or(vec![]), &mut keys).try_eval(&[], 0), Ok(false));
+}
@@ -0,0 +1,257 @@
+#[cfg(test)]
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECS_PER_DAY: i64 = 86400;
+
+pub(crate) const WEEKDAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const WEEKDAY_FULL_NAMES: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// The source of `now` for time window conditions, in unix seconds.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> i64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => return d.as_secs() as i64,
+            Err(e) => return -(e.duration().as_secs() as i64),
+        }
+    }
+}
+
+/// A clock that only moves when told to, for tests.
+#[cfg(test)]
+pub struct ManualClock(AtomicI64);
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(now: i64) -> Self {
+        return ManualClock(AtomicI64::new(now));
+    }
+
+    pub fn advance(&self, secs: i64) {
+        self.0.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> i64 {
+        return self.0.load(Ordering::SeqCst);
+    }
+}
+
+/// Parse an ISO-8601 timestamp into unix seconds. Accepted forms are `2026-11-01`
+/// (midnight UTC) and `2026-11-01T08:30[:15]` followed by `Z`, `+08:00` or `-0500`.
+pub fn parse_timestamp(s: &str) -> Result<i64, String> {
+    let err = || format!("invalid timestamp `{}`", s);
+    let b = s.as_bytes();
+
+    if b.len() < 10 || b[4] != b'-' || b[7] != b'-' {
+        return Err(err());
+    }
+    let year = parse_digits(&b[0..4]).ok_or_else(err)?;
+    let month = parse_digits(&b[5..7]).ok_or_else(err)?;
+    let day = parse_digits(&b[8..10]).ok_or_else(err)?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(err());
+    }
+    let date = days_from_civil(year, month, day) * SECS_PER_DAY;
+
+    let rest = &b[10..];
+    if rest.is_empty() {
+        return Ok(date);
+    }
+    if rest.len() < 6 || (rest[0] != b'T' && rest[0] != b't') || rest[3] != b':' {
+        return Err(err());
+    }
+    let hour = parse_digits(&rest[1..3]).ok_or_else(err)?;
+    let minute = parse_digits(&rest[4..6]).ok_or_else(err)?;
+    let mut rest = &rest[6..];
+    let mut second = 0;
+    if rest.first() == Some(&b':') {
+        if rest.len() < 3 {
+            return Err(err());
+        }
+        second = parse_digits(&rest[1..3]).ok_or_else(err)?;
+        rest = &rest[3..];
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return Err(err());
+    }
+
+    let offset = match rest {
+        b"Z" | b"z" => 0,
+        [sign @ b'+', ..] | [sign @ b'-', ..] => {
+            let (h, m) = match &rest[1..] {
+                [h1, h2, b':', m1, m2] | [h1, h2, m1, m2] => (
+                    parse_digits(&[*h1, *h2]).ok_or_else(err)?,
+                    parse_digits(&[*m1, *m2]).ok_or_else(err)?,
+                ),
+                _ => return Err(err()),
+            };
+            if h > 23 || m > 59 {
+                return Err(err());
+            }
+            let offset = h * 3600 + m * 60;
+            if *sign == b'+' {
+                offset
+            } else {
+                -offset
+            }
+        }
+        // a time without a zone means something different on every client, reject it
+        _ => return Err(err()),
+    };
+
+    return Ok(date + hour * 3600 + minute * 60 + second - offset);
+}
+
+/// Format unix seconds the way `parse_timestamp` reads them back, always in UTC.
+pub fn format_timestamp(ts: i64) -> String {
+    let days = ts.div_euclid(SECS_PER_DAY);
+    let secs = ts.rem_euclid(SECS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    let (hour, minute, second) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if second == 0 {
+        return format!("{:04}-{:02}-{:02}T{:02}:{:02}Z", year, month, day, hour, minute);
+    }
+    return format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    );
+}
+
+/// `mon`, `Monday` and so on, 0 is monday.
+pub fn parse_weekday(s: &str) -> Option<u8> {
+    let s = s.to_ascii_lowercase();
+    for i in 0..7 {
+        if s == WEEKDAY_NAMES[i] || s == WEEKDAY_FULL_NAMES[i] {
+            return Some(i as u8);
+        }
+    }
+    return None;
+}
+
+/// The UTC weekday of a unix timestamp, 0 is monday.
+pub fn weekday_of(ts: i64) -> u8 {
+    // 1970-01-01 is a thursday
+    return (ts.div_euclid(SECS_PER_DAY) + 3).rem_euclid(7) as u8;
+}
+
+/// The first midnight (UTC) after `after` on which the result of `weekday in <mask>` changes.
+pub(crate) fn next_weekday_boundary(mask: u8, after: i64) -> Option<i64> {
+    let contains = |day: i64| mask & (1 << (day + 3).rem_euclid(7)) != 0;
+    let today = after.div_euclid(SECS_PER_DAY);
+    for d in today + 1..=today + 7 {
+        if contains(d) != contains(d - 1) {
+            return Some(d * SECS_PER_DAY);
+        }
+    }
+    return None;
+}
+
+fn parse_digits(b: &[u8]) -> Option<i64> {
+    let mut n = 0;
+    for c in b {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        n = n * 10 + (c - b'0') as i64;
+    }
+    return Some(n);
+}
+
+fn is_leap_year(y: i64) -> bool {
+    return (y % 4 == 0 && y % 100 != 0) || y % 400 == 0;
+}
+
+fn days_in_month(y: i64, m: i64) -> i64 {
+    match m {
+        2 if is_leap_year(y) => return 29,
+        2 => return 28,
+        4 | 6 | 9 | 11 => return 30,
+        _ => return 31,
+    }
+}
+
+// see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    return era * 146097 + doe - 719468;
+}
+
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = if m <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 };
+    return (y, m, d);
+}
+
+#[test]
+fn test_parse_timestamp() {
+    assert_eq!(parse_timestamp("1970-01-01"), Ok(0));
+    assert_eq!(parse_timestamp("1970-01-01T00:00Z"), Ok(0));
+    assert_eq!(parse_timestamp("2026-11-01T00:00Z"), Ok(1793491200));
+    assert_eq!(parse_timestamp("2026-11-01T08:00+08:00"), Ok(1793491200));
+    assert_eq!(parse_timestamp("2026-10-31T19:00-0500"), Ok(1793491200));
+    assert_eq!(parse_timestamp("2024-02-29T12:34:56Z"), Ok(1709210096));
+    assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), Ok(-1));
+
+    assert!(parse_timestamp("2026-11-01T00:00").is_err());
+    assert!(parse_timestamp("2025-02-29").is_err());
+    assert!(parse_timestamp("2026-13-01").is_err());
+    assert!(parse_timestamp("2026-11-01T24:00Z").is_err());
+    assert!(parse_timestamp("2026-11-01 00:00Z").is_err());
+    assert!(parse_timestamp("tomorrow").is_err());
+
+    for ts in &[0, -1, 1709210096, 1793491200, 253402300799] {
+        assert_eq!(parse_timestamp(&format_timestamp(*ts)), Ok(*ts));
+    }
+}
+
+#[test]
+fn test_weekday() {
+    assert_eq!(weekday_of(0), 3);
+    assert_eq!(weekday_of(-1), 2);
+    // 2026-11-01 is a sunday
+    assert_eq!(weekday_of(1793491200), 6);
+    assert_eq!(parse_weekday("Sat"), Some(5));
+    assert_eq!(parse_weekday("sunday"), Some(6));
+    assert_eq!(parse_weekday("weekend"), None);
+
+    // sat and sun
+    let mask = 0b110_0000;
+    // from a sunday, the next change is on monday
+    assert_eq!(next_weekday_boundary(mask, 1793491200 + 10), Some(1793491200 + SECS_PER_DAY));
+    // from that monday, the next change is on saturday
+    assert_eq!(
+        next_weekday_boundary(mask, 1793491200 + SECS_PER_DAY),
+        Some(1793491200 + 6 * SECS_PER_DAY)
+    );
+    assert_eq!(next_weekday_boundary(0, 0), None);
+    assert_eq!(next_weekday_boundary(0x7f, 0), None);
+}
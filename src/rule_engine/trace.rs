@@ -0,0 +1,181 @@
+use std::fmt;
+
+use crate::error::RuleEvalError;
+
+use super::time::format_timestamp;
+use super::{Condition, MatchContext, Value};
+
+/// What an atom of the trace read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceInput {
+    // the key and its whoami value, None if the whoami does not have the key
+    Key(String, Option<Value>),
+    // time windows read the clock
+    Now(i64),
+}
+
+/// How a condition was evaluated against a whoami, node by node, built by
+/// `Condition::trace_at` for explain mode. It follows the tree walker, so it short-circuits
+/// and fails exactly where `try_eval_at` does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalTrace {
+    // `&&`, `||`, `!` or the atom in rule syntax
+    pub node: String,
+    pub input: Option<TraceInput>,
+    pub result: Result<bool, RuleEvalError>,
+    pub children: Vec<EvalTrace>,
+    // children of `&&` / `||` not evaluated because the result was already known
+    pub skipped: usize,
+}
+
+impl Condition {
+    pub fn trace_at(&self, ctx: &MatchContext, now: i64) -> EvalTrace {
+        match self {
+            Condition::And(conds) | Condition::Or(conds) => {
+                let stop_on = matches!(self, Condition::Or(_));
+                let mut children = Vec::with_capacity(conds.len());
+                let mut result = Ok(!stop_on);
+                for cond in conds {
+                    let child = cond.trace_at(ctx, now);
+                    let child_result = child.result.clone();
+                    children.push(child);
+                    match child_result {
+                        Ok(b) if b != stop_on => {}
+                        r => {
+                            result = r;
+                            break;
+                        }
+                    }
+                }
+                return EvalTrace {
+                    node: if stop_on { "||" } else { "&&" }.to_owned(),
+                    input: None,
+                    result,
+                    skipped: conds.len() - children.len(),
+                    children,
+                };
+            }
+            Condition::Not(cond) => {
+                let child = cond.trace_at(ctx, now);
+                return EvalTrace {
+                    node: "!".to_owned(),
+                    input: None,
+                    result: child.result.clone().map(|b| !b),
+                    children: vec![child],
+                    skipped: 0,
+                };
+            }
+            Condition::Leaf { lhs, .. } | Condition::Bucket { lhs, .. } => {
+                let lhs_value = ctx.get(lhs);
+                return EvalTrace {
+                    node: self.to_string(),
+                    input: Some(TraceInput::Key(lhs.clone(), lhs_value.cloned())),
+                    result: self.eval_atom(lhs_value, now),
+                    children: Vec::new(),
+                    skipped: 0,
+                };
+            }
+            Condition::NowBetween { .. } | Condition::WeekdayIn(_) | Condition::RuleRef(_) => {
+                return EvalTrace {
+                    node: self.to_string(),
+                    input: Some(TraceInput::Now(now)),
+                    result: self.eval_atom(None, now),
+                    children: Vec::new(),
+                    skipped: 0,
+                };
+            }
+        }
+    }
+}
+
+// One node per line, children indented under their parent:
+//
+//   false &&, 1 not evaluated
+//     true  region == "eu"  (region = "eu")
+//     false build >= 1200  (build = "1100")
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+impl EvalTrace {
+    /// The atom that decided the result: `&&` stops at its first false child, `||` at its
+    /// first true one, an error stops everything, and a `||` where every branch failed
+    /// reports its last branch. In all cases it is the last evaluated child.
+    pub fn deciding_atom(&self) -> &EvalTrace {
+        match self.children.last() {
+            Some(child) => return child.deciding_atom(),
+            None => return self,
+        }
+    }
+
+    fn write_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        if depth > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{:width$}", "", width = depth * 2)?;
+        match &self.result {
+            Ok(b) => write!(f, "{:<5} {}", b, self.node)?,
+            Err(e) => write!(f, "error ({}) {}", e, self.node)?,
+        }
+        match &self.input {
+            Some(TraceInput::Key(key, Some(v))) => write!(f, "  ({} = {})", key, v)?,
+            Some(TraceInput::Key(key, None)) => write!(f, "  ({} is missing)", key)?,
+            Some(TraceInput::Now(now)) => write!(f, "  (now = {})", format_timestamp(*now))?,
+            None => {}
+        }
+        if self.skipped > 0 {
+            write!(f, ", {} not evaluated", self.skipped)?;
+        }
+        for child in &self.children {
+            child.write_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_trace() {
+    use crate::parser::rule::do_parse;
+
+    let (_, mut cond) = do_parse(r#"region == "eu" && (build >= 1200 || !exists(canary)) && svc == "x""#).unwrap();
+    cond.prepare().unwrap();
+    let mut ctx = MatchContext::new();
+    ctx.insert("region".to_string(), Value::Str("eu".to_string()));
+    ctx.insert("build".to_string(), Value::Str("1100".to_string()));
+    ctx.insert("canary".to_string(), Value::Str("1".to_string()));
+
+    let trace = cond.trace_at(&ctx, 0);
+    assert_eq!(trace.result, cond.try_eval_at(&ctx, 0));
+    assert_eq!(trace.deciding_atom().node, "exists(canary)");
+    assert_eq!(
+        trace.to_string(),
+        [
+            "false &&, 1 not evaluated",
+            "  true  region == \"eu\"  (region = \"eu\")",
+            "  false ||",
+            "    false build >= 1200  (build = \"1100\")",
+            "    false !",
+            "      true  exists(canary)  (canary = \"1\")",
+        ]
+        .join("\n")
+    );
+
+    // the first error stops the evaluation, like `try_eval_at`
+    ctx.insert("build".to_string(), Value::Str("new".to_string()));
+    let trace = cond.trace_at(&ctx, 0);
+    assert!(trace.result.is_err());
+    assert_eq!(trace.result, cond.try_eval_at(&ctx, 0));
+    assert_eq!(trace.children[1].children.len(), 1);
+
+    let (_, cond) = do_parse(r#"weekday in ["sun"] || missing(region)"#).unwrap();
+    assert_eq!(
+        cond.trace_at(&MatchContext::new(), 1793491200).to_string(),
+        [
+            "true  ||, 1 not evaluated",
+            "  true  weekday in [\"sun\"]  (now = 2026-11-01T00:00Z)",
+        ]
+        .join("\n")
+    );
+}
@@ -0,0 +1,580 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+
+use crate::error::RuleEvalError;
+
+use super::pattern::Pattern;
+
+type Result<T> = std::result::Result<T, RuleEvalError>;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    SemVer(SemVer),
+    // list literal as written in the rule
+    List(Vec<Value>),
+    // list literal prepared for membership test, see `Condition::prepare`, boxed because its
+    // indexes would make every Value several hundred bytes
+    Set(Box<ValueSet>),
+    // string literal of `=~` or `glob` prepared by `Condition::prepare`
+    Pattern(Pattern),
+    Ip(IpAddr),
+    // string literal of `in_cidr` prepared by `Condition::prepare`
+    Cidr(IpNet),
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct SemVer {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl SemVer {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        return Self {
+            major,
+            minor,
+            patch,
+        };
+    }
+
+    /// accept `1.2.3` and `v1.2.3`
+    pub fn parse(i: &str) -> Option<Self> {
+        let i = i.strip_prefix("v").unwrap_or(i);
+        let mut parts = i.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        let patch = parts.next()?.parse().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        return Some(Self::new(major, minor, patch));
+    }
+}
+
+impl fmt::Display for SemVer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+// Coercion rules used when the two sides of a comparison have different types:
+// * Int and Float are compared by their exact values, an Int is never rounded to a Float,
+//   so `9007199254740993 > 9007199254740992.0`. `in` sets follow the same rule.
+// * a Str meets a typed value: the Str is parsed into the type of the other side,
+//   numbers accept both integer and float form, Bool only accepts `true` and `false`,
+//   SemVer accepts `1.2.3` and `v1.2.3`. If parsing fails, it's an error.
+//   Ip accepts IPv4 and IPv6, an IPv4-mapped IPv6 address equals its IPv4 form.
+// * any other combination is a type mismatch error.
+// Whoami values passed through the FFI are always Str, so this is what makes
+// `build >= 1200` work against a client reporting `build=1300`.
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::SemVer(_) => "semver",
+            Value::List(_) | Value::Set(_) => "list",
+            Value::Pattern(_) => "pattern",
+            Value::Ip(_) => "ip",
+            Value::Cidr(_) => "cidr",
+        }
+    }
+
+    pub fn equals(&self, other: &Value) -> Result<bool> {
+        match (self, other) {
+            (Value::Bool(l), Value::Bool(r)) => return Ok(l == r),
+            (Value::Str(l), Value::Bool(r)) => return Ok(parse_bool(l)? == *r),
+            (Value::Bool(l), Value::Str(r)) => return Ok(*l == parse_bool(r)?),
+            _ => return Ok(self.compare(other)? == Ordering::Equal),
+        }
+    }
+
+    pub fn compare(&self, other: &Value) -> Result<Ordering> {
+        match (self, other) {
+            (Value::Str(l), Value::Str(r)) => return Ok(l.cmp(r)),
+            (Value::Int(l), Value::Int(r)) => return Ok(l.cmp(r)),
+            (Value::SemVer(l), Value::SemVer(r)) => return Ok(l.cmp(r)),
+            (Value::Ip(l), Value::Ip(r)) => return Ok(canonical_ip(*l).cmp(&canonical_ip(*r))),
+            (Value::Float(l), Value::Float(r)) => return cmp_float(*l, *r),
+            (Value::Int(l), Value::Float(r)) => return cmp_int_float(*l, *r),
+            (Value::Float(l), Value::Int(r)) => return cmp_int_float(*r, *l).map(Ordering::reverse),
+
+            (Value::Str(l), Value::Int(_)) | (Value::Str(l), Value::Float(_)) => {
+                return parse_number(l)?.compare(other);
+            }
+            (Value::Int(_), Value::Str(r)) | (Value::Float(_), Value::Str(r)) => {
+                return self.compare(&parse_number(r)?);
+            }
+            (Value::Str(l), Value::SemVer(r)) => return Ok(parse_semver(l)?.cmp(r)),
+            (Value::SemVer(l), Value::Str(r)) => return Ok(l.cmp(&parse_semver(r)?)),
+            (Value::Str(l), Value::Ip(r)) => return Ok(parse_ip(l)?.cmp(&canonical_ip(*r))),
+            (Value::Ip(l), Value::Str(r)) => return Ok(canonical_ip(*l).cmp(&parse_ip(r)?)),
+
+            (Value::Bool(_), Value::Bool(_))
+            | (Value::Str(_), Value::Bool(_))
+            | (Value::Bool(_), Value::Str(_)) => {
+                return Err(RuleEvalError::UnsupportedOperator("ordering", "bool"))
+            }
+            _ => {
+                return Err(RuleEvalError::TypeMismatch(
+                    self.type_name(),
+                    other.type_name(),
+                ))
+            }
+        }
+    }
+
+    /// membership test used by `in`, it has the same meaning as `==` against every item,
+    /// except that items which can not be compared with `self` are simply not equal.
+    pub fn is_in(&self, items: &[Value]) -> bool {
+        items.iter().any(|item| self.equals(item).unwrap_or(false))
+    }
+
+    /// used by `in_cidr`, `nets` is a Cidr, or a List of Cidr, or their unprepared Str form
+    pub fn is_within(&self, nets: &Value) -> Result<bool> {
+        let ip = match self {
+            Value::Ip(ip) => canonical_ip(*ip),
+            Value::Str(s) => parse_ip(s)?,
+            v => return Err(RuleEvalError::UnsupportedOperator("`in_cidr`", v.type_name())),
+        };
+        match nets {
+            Value::Cidr(net) => return Ok(net_contains(net, ip)),
+            Value::Str(s) => return Ok(net_contains(&parse_cidr(s)?, ip)),
+            Value::List(items) => {
+                for item in items {
+                    if Value::Ip(ip).is_within(item)? {
+                        return Ok(true);
+                    }
+                }
+                return Ok(false);
+            }
+            v => return Err(RuleEvalError::UnsupportedOperator("`in_cidr`", v.type_name())),
+        }
+    }
+}
+
+/// A list literal with hash indexes built once, so that `in` does not scan the list.
+/// It gives the same answer as `Value::is_in` on `items`.
+#[derive(Debug, Clone)]
+pub struct ValueSet {
+    items: Vec<Value>,
+    strs: HashSet<String>,
+    // built from typed items, probed by a Str after converting it
+    typed: TypedSet,
+    // built from Str items that can be converted, probed by a typed value
+    str_as_typed: TypedSet,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TypedSet {
+    nums: HashSet<NumKey>,
+    bools: HashSet<bool>,
+    semvers: HashSet<SemVer>,
+    ips: HashSet<IpAddr>,
+}
+
+// Int and Float with the same value must hash to the same key, because `1 == 1.0`. A Float
+// only has the key of an Int when `cmp_int_float` says they are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum NumKey {
+    Int(i64),
+    Float(u64),
+}
+
+impl NumKey {
+    fn from_value(v: &Value) -> Option<NumKey> {
+        match v {
+            Value::Int(i) => return Some(NumKey::Int(*i)),
+            Value::Float(f) => {
+                if f.is_nan() {
+                    return None;
+                }
+                if let Some(i) = float_as_int(*f) {
+                    return Some(NumKey::Int(i));
+                }
+                // `-0.0` is integral, so every Float left has a single bit pattern
+                return Some(NumKey::Float(f.to_bits()));
+            }
+            _ => return None,
+        }
+    }
+}
+
+impl TypedSet {
+    fn insert(&mut self, v: &Value) {
+        match v {
+            Value::Int(_) | Value::Float(_) => {
+                if let Some(k) = NumKey::from_value(v) {
+                    self.nums.insert(k);
+                }
+            }
+            Value::Bool(b) => {
+                self.bools.insert(*b);
+            }
+            Value::SemVer(ver) => {
+                self.semvers.insert(*ver);
+            }
+            Value::Ip(ip) => {
+                self.ips.insert(canonical_ip(*ip));
+            }
+            _ => {}
+        }
+    }
+
+    fn contains(&self, v: &Value) -> bool {
+        match v {
+            Value::Int(_) | Value::Float(_) => match NumKey::from_value(v) {
+                Some(k) => self.nums.contains(&k),
+                None => false,
+            },
+            Value::Bool(b) => self.bools.contains(b),
+            Value::SemVer(ver) => self.semvers.contains(ver),
+            Value::Ip(ip) => self.ips.contains(&canonical_ip(*ip)),
+            _ => false,
+        }
+    }
+
+    // convert the string only if there is something of that type to look for
+    fn contains_str(&self, s: &str) -> bool {
+        if !self.nums.is_empty() {
+            if let Ok(n) = parse_number(s) {
+                if self.contains(&n) {
+                    return true;
+                }
+            }
+        }
+        if !self.bools.is_empty() {
+            if let Ok(b) = parse_bool(s) {
+                if self.bools.contains(&b) {
+                    return true;
+                }
+            }
+        }
+        if !self.semvers.is_empty() {
+            if let Some(ver) = SemVer::parse(s) {
+                if self.semvers.contains(&ver) {
+                    return true;
+                }
+            }
+        }
+        if !self.ips.is_empty() {
+            if let Ok(ip) = parse_ip(s) {
+                if self.ips.contains(&ip) {
+                    return true;
+                }
+            }
+        }
+        return false;
+    }
+}
+
+impl ValueSet {
+    pub fn new(items: Vec<Value>) -> Self {
+        let mut strs = HashSet::new();
+        let mut typed = TypedSet::default();
+        let mut str_as_typed = TypedSet::default();
+        for item in &items {
+            match item {
+                Value::Str(s) => {
+                    strs.insert(s.clone());
+                    if let Ok(n) = parse_number(s) {
+                        str_as_typed.insert(&n);
+                    }
+                    if let Ok(b) = parse_bool(s) {
+                        str_as_typed.insert(&Value::Bool(b));
+                    }
+                    if let Some(ver) = SemVer::parse(s) {
+                        str_as_typed.insert(&Value::SemVer(ver));
+                    }
+                    if let Ok(ip) = parse_ip(s) {
+                        str_as_typed.insert(&Value::Ip(ip));
+                    }
+                }
+                _ => typed.insert(item),
+            }
+        }
+        return Self {
+            items,
+            strs,
+            typed,
+            str_as_typed,
+        };
+    }
+
+    pub fn items(&self) -> &[Value] {
+        &self.items
+    }
+
+    pub fn contains(&self, v: &Value) -> bool {
+        match v {
+            Value::Str(s) => return self.strs.contains(s) || self.typed.contains_str(s),
+            Value::List(_) | Value::Set(_) | Value::Pattern(_) | Value::Cidr(_) => return false,
+            _ => return self.typed.contains(v) || self.str_as_typed.contains(v),
+        }
+    }
+}
+
+impl PartialEq for ValueSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.items == other.items
+    }
+}
+
+fn cmp_float(l: f64, r: f64) -> Result<Ordering> {
+    l.partial_cmp(&r).ok_or(RuleEvalError::NotComparable)
+}
+
+// `2^63`, the first float above every i64
+const I64_END: f64 = 9223372036854775808.0;
+
+// compare without converting `l` to a float, which would round it above 2^53
+fn cmp_int_float(l: i64, r: f64) -> Result<Ordering> {
+    if r.is_nan() {
+        return Err(RuleEvalError::NotComparable);
+    }
+    if r >= I64_END {
+        return Ok(Ordering::Less);
+    }
+    if r < -I64_END {
+        return Ok(Ordering::Greater);
+    }
+    // in range, so the integral part converts exactly
+    let whole = r.trunc();
+    match l.cmp(&(whole as i64)) {
+        Ordering::Equal => return Ok(0.0f64.partial_cmp(&(r - whole)).expect("not NaN")),
+        t => return Ok(t),
+    }
+}
+
+// the i64 a float is equal to, if any
+fn float_as_int(f: f64) -> Option<i64> {
+    if f.fract() == 0.0 && (-I64_END..I64_END).contains(&f) {
+        return Some(f as i64);
+    }
+    return None;
+}
+
+fn parse_number(s: &str) -> Result<Value> {
+    if let Ok(v) = s.parse::<i64>() {
+        return Ok(Value::Int(v));
+    }
+    if let Ok(v) = s.parse::<f64>() {
+        return Ok(Value::Float(v));
+    }
+    return Err(RuleEvalError::ConvertError(s.to_owned(), "number"));
+}
+
+fn parse_bool(s: &str) -> Result<bool> {
+    match s {
+        "true" => return Ok(true),
+        "false" => return Ok(false),
+        _ => return Err(RuleEvalError::ConvertError(s.to_owned(), "bool")),
+    }
+}
+
+pub(crate) fn parse_ip(s: &str) -> Result<IpAddr> {
+    s.parse::<IpAddr>()
+        .map(canonical_ip)
+        .map_err(|_| RuleEvalError::ConvertError(s.to_owned(), "ip"))
+}
+
+/// accept `10.0.0.0/8` and `fe80::/10`, a bare address means the single host
+pub(crate) fn parse_cidr(s: &str) -> Result<IpNet> {
+    if let Ok(net) = s.parse::<IpNet>() {
+        return Ok(net);
+    }
+    if let Ok(ip) = s.parse::<IpAddr>() {
+        return Ok(IpNet::from(ip));
+    }
+    return Err(RuleEvalError::ConvertError(s.to_owned(), "cidr"));
+}
+
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    if let IpAddr::V6(v6) = ip {
+        if let Some(v4) = v6.to_ipv4_mapped() {
+            return IpAddr::V4(v4);
+        }
+    }
+    return ip;
+}
+
+fn net_contains(net: &IpNet, ip: IpAddr) -> bool {
+    net.contains(&ip)
+}
+
+fn parse_semver(s: &str) -> Result<SemVer> {
+    SemVer::parse(s).ok_or_else(|| RuleEvalError::ConvertError(s.to_owned(), "semver"))
+}
+
+#[test]
+fn test_value_coercion() {
+    let s = |v: &str| Value::Str(v.to_string());
+
+    // plain strings are still compared lexicographically
+    assert_eq!(s("10").compare(&s("9")), Ok(Ordering::Less));
+    // but a number on the other side makes it numeric
+    assert_eq!(s("10").compare(&Value::Int(9)), Ok(Ordering::Greater));
+    assert_eq!(Value::Int(-345).equals(&s("-345")), Ok(true));
+    assert_eq!(Value::Int(2).compare(&Value::Float(1.5)), Ok(Ordering::Greater));
+    assert_eq!(s("1.5").compare(&Value::Int(2)), Ok(Ordering::Less));
+
+    assert_eq!(s("true").equals(&Value::Bool(true)), Ok(true));
+    assert_eq!(Value::Bool(false).equals(&s("true")), Ok(false));
+    assert_eq!(
+        s("yes").equals(&Value::Bool(true)),
+        Err(RuleEvalError::ConvertError("yes".into(), "bool"))
+    );
+    assert!(Value::Bool(true).compare(&Value::Bool(false)).is_err());
+
+    assert_eq!(
+        s("v1.10.0").compare(&Value::SemVer(SemVer::new(1, 9, 3))),
+        Ok(Ordering::Greater)
+    );
+    assert_eq!(
+        s("1.2.3").equals(&Value::SemVer(SemVer::new(1, 2, 3))),
+        Ok(true)
+    );
+
+    assert_eq!(
+        s("abc").compare(&Value::Int(1)),
+        Err(RuleEvalError::ConvertError("abc".into(), "number"))
+    );
+    assert_eq!(
+        Value::Int(1).compare(&Value::SemVer(SemVer::new(1, 0, 0))),
+        Err(RuleEvalError::TypeMismatch("int", "semver"))
+    );
+    assert_eq!(
+        Value::Int(1).equals(&Value::Bool(true)),
+        Err(RuleEvalError::TypeMismatch("int", "bool"))
+    );
+    assert_eq!(
+        Value::Float(f64::NAN).compare(&Value::Int(1)),
+        Err(RuleEvalError::NotComparable)
+    );
+}
+
+#[test]
+fn test_int_float_exact() {
+    let two_53 = 9007199254740992i64;
+    // `2^53 + 1` rounds to `2^53` as a float, but it is still greater
+    assert_eq!(Value::Int(two_53 + 1).compare(&Value::Float(two_53 as f64)), Ok(Ordering::Greater));
+    assert_eq!(Value::Float(two_53 as f64).compare(&Value::Int(two_53 + 1)), Ok(Ordering::Less));
+    assert_eq!(Value::Int(two_53 + 1).equals(&Value::Float(two_53 as f64)), Ok(false));
+    assert_eq!(Value::Int(two_53).equals(&Value::Float(two_53 as f64)), Ok(true));
+    assert_eq!(Value::Str("9007199254740993".into()).compare(&Value::Float(9007199254740992.0)), Ok(Ordering::Greater));
+
+    assert_eq!(Value::Int(i64::MAX).compare(&Value::Float(9223372036854775808.0)), Ok(Ordering::Less));
+    assert_eq!(Value::Int(i64::MIN).equals(&Value::Float(-9223372036854775808.0)), Ok(true));
+    assert_eq!(Value::Int(i64::MIN).compare(&Value::Float(-1e19)), Ok(Ordering::Greater));
+    assert_eq!(Value::Int(0).compare(&Value::Float(f64::INFINITY)), Ok(Ordering::Less));
+    assert_eq!(Value::Int(0).compare(&Value::Float(f64::NEG_INFINITY)), Ok(Ordering::Greater));
+    assert_eq!(Value::Int(-3).compare(&Value::Float(-2.5)), Ok(Ordering::Less));
+    assert_eq!(Value::Int(-2).compare(&Value::Float(-2.5)), Ok(Ordering::Greater));
+    assert_eq!(Value::Int(0).equals(&Value::Float(-0.0)), Ok(true));
+
+    // `in` gives the same answers as `==`
+    let items = vec![Value::Float(two_53 as f64), Value::Int(i64::MAX), Value::Float(-0.0), Value::Float(1e19)];
+    let set = ValueSet::new(items.clone());
+    let probes = vec![
+        Value::Int(two_53),
+        Value::Int(two_53 + 1),
+        Value::Float(two_53 as f64),
+        Value::Str("9007199254740993".into()),
+        Value::Float(9223372036854775808.0),
+        Value::Int(i64::MAX),
+        Value::Int(0),
+        Value::Float(0.0),
+        Value::Float(1e19),
+    ];
+    for probe in probes {
+        assert_eq!(set.contains(&probe), probe.is_in(&items), "{:?}", probe);
+    }
+    assert!(set.contains(&Value::Int(two_53)));
+    assert!(!set.contains(&Value::Int(two_53 + 1)));
+    assert!(!set.contains(&Value::Float(9223372036854775808.0)));
+}
+
+#[test]
+fn test_value_set() {
+    let s = |v: &str| Value::Str(v.to_string());
+    let items = vec![
+        s("cn"),
+        s("05"),
+        s("true"),
+        Value::Int(7),
+        Value::Float(2.5),
+        Value::SemVer(SemVer::new(1, 2, 3)),
+        s("10.0.0.1"),
+        Value::Ip("10.0.0.2".parse().unwrap()),
+    ];
+    let set = ValueSet::new(items.clone());
+
+    let probes = vec![
+        s("cn"),
+        s("us"),
+        s("05"),
+        s("5"),
+        Value::Int(5),
+        s("7"),
+        s("7.0"),
+        Value::Float(7.0),
+        s("2.5"),
+        s("v1.2.3"),
+        s("1.2.4"),
+        Value::Bool(true),
+        Value::Bool(false),
+        s("true"),
+        s("TRUE"),
+        Value::SemVer(SemVer::new(1, 2, 3)),
+        Value::Float(f64::NAN),
+        Value::List(vec![s("cn")]),
+        Value::Ip("10.0.0.1".parse().unwrap()),
+        Value::Ip("::ffff:10.0.0.2".parse().unwrap()),
+        s("::ffff:10.0.0.1"),
+        s("10.0.0.2"),
+    ];
+    for probe in probes {
+        assert_eq!(set.contains(&probe), probe.is_in(&items), "{:?}", probe);
+    }
+    assert!(set.contains(&s("cn")));
+    assert!(!set.contains(&s("5")));
+    assert!(set.contains(&Value::Int(5)));
+    assert!(set.contains(&s("7.0")));
+}
+
+#[test]
+fn test_ip_within() {
+    let s = |v: &str| Value::Str(v.to_string());
+    let net = |v: &str| Value::Cidr(parse_cidr(v).unwrap());
+
+    assert_eq!(s("10.1.2.3").is_within(&net("10.0.0.0/8")), Ok(true));
+    assert_eq!(s("11.1.2.3").is_within(&net("10.0.0.0/8")), Ok(false));
+    assert_eq!(s("::ffff:10.1.2.3").is_within(&net("10.0.0.0/8")), Ok(true));
+    assert_eq!(s("fe80::1").is_within(&net("fe80::/10")), Ok(true));
+    assert_eq!(s("fe80::1").is_within(&net("10.0.0.0/8")), Ok(false));
+    assert_eq!(s("192.168.1.1").is_within(&net("192.168.1.1")), Ok(true));
+    assert_eq!(
+        Value::Ip("172.16.0.9".parse().unwrap())
+            .is_within(&Value::List(vec![net("10.0.0.0/8"), net("172.16.0.0/12")])),
+        Ok(true)
+    );
+    assert_eq!(s("10.1.2.3").is_within(&s("10.0.0.0/8")), Ok(true));
+
+    assert_eq!(
+        s("web-1").is_within(&net("10.0.0.0/8")),
+        Err(RuleEvalError::ConvertError("web-1".into(), "ip"))
+    );
+    assert!(parse_cidr("10.0.0.0/33").is_err());
+    assert!(parse_cidr("10.0.0/8").is_err());
+}
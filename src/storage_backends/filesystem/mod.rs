@@ -1,22 +1,19 @@
 use crate::{
-    error::{ListDirError, StorageBackendError},
+    error::StorageBackendError,
     model::object::{ObjectID, ObjectIDRef},
 };
 use std::{
-    cell::Cell,
-    collections::HashMap,
     fs,
     path::{Path, PathBuf},
     sync::{
-        mpsc::{channel, Receiver},
-        Arc, Mutex,
+        mpsc::{channel, Receiver}, Mutex,
     },
 };
 
 use super::{Result, VersionItem};
 use super::{DirItem, StorageBackend, StorageChangeEvent};
 use nom::AsBytes;
-use notify::{watcher, DebouncedEvent, PollWatcher, RecursiveMode, Watcher};
+use notify::{DebouncedEvent, PollWatcher, RecursiveMode, Watcher};
 use std::str;
 use std::thread;
 use std::time::Duration;
@@ -62,7 +59,7 @@ impl StorageBackend for FilesystemBackend {
     fn get_obj_by_hash(&self, hash: ObjectIDRef) -> Result<Vec<u8>> {
         let path = Path::new(
             str::from_utf8(hash)
-                .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "invalid path"))?,
+                .map_err(|_| std::io::Error::other("invalid path"))?,
         );
         Ok(fs::read(path)?)
     }
@@ -80,8 +77,7 @@ impl StorageBackend for FilesystemBackend {
                 ret.push(DirItem::new(
                     t,
                     new_fs_abs_path.is_dir(),
-                    Vec::from(new_fs_abs_path.to_str().ok_or(std::io::Error::new(
-                        std::io::ErrorKind::Other,
+                    Vec::from(new_fs_abs_path.to_str().ok_or(std::io::Error::other(
                         "invalid path",
                     ))?),
                 ));
@@ -94,8 +90,7 @@ impl StorageBackend for FilesystemBackend {
         let path = self.get_versioned_path(version, path)?;
         if let Ok(m) = fs::metadata(&path) {
             if m.is_file() {
-                return Ok(Vec::from(path.to_str().ok_or(std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                return Ok(Vec::from(path.to_str().ok_or(std::io::Error::other(
                     "invalid path",
                 ))?));
             }
@@ -142,9 +137,9 @@ impl StorageBackend for FilesystemBackend {
 
     fn get_diff_list(
         &self,
-        old_version: &VersionItem,
-        new_version: &VersionItem,
-        namespace: &str,
+        _old_version: &VersionItem,
+        _new_version: &VersionItem,
+        _namespace: &str,
     ) -> Result<Vec<String>> {
         return Ok(Vec::new());
     }
@@ -152,7 +147,20 @@ impl StorageBackend for FilesystemBackend {
     fn get_current_version(&self) -> Result<VersionItem> {
         read_version_from_fs(&self.base_path.join("head"))
     }
-    fn list_versions(&self, start: usize, limit: usize) -> Result<Vec<VersionItem>> {
+
+    fn resolve_version(&self, name: &str) -> Result<VersionItem> {
+        // every version is a directory next to `head`
+        let is_dir_name = !name.is_empty() && !name.contains('/') && name != "." && name != "..";
+        if !is_dir_name || !self.base_path.join(name).is_dir() {
+            return Err(StorageBackendError::VersionNotFound(name.to_owned()));
+        }
+        Ok(VersionItem {
+            name: name.to_owned(),
+            id: ObjectID::from(name.as_bytes()),
+        })
+    }
+
+    fn list_versions(&self, _start: usize, _limit: usize) -> Result<Vec<VersionItem>> {
         return Ok(Vec::new());
     }
 }
@@ -186,9 +194,8 @@ fn eafcc_watcher(rx: Receiver<DebouncedEvent>, path: PathBuf, cb: Box<dyn Fn(Ver
                 }
                 _ => continue,
             },
-            Err(e) => {
-                print_error_with_switch!("watch error: {:?}", e);
-            }
+            // the watcher was dropped with the backend
+            Err(_) => return,
         }
     }
 }
@@ -1,19 +1,19 @@
 use crate::{error::{StorageBackendError, WalkDirError}, model::object::{ObjectID, ObjectIDRef}};
 use core::time;
-use std::{cell::Cell, collections::HashMap, env, fs, path::{Path, PathBuf}, str::{FromStr, Utf8Error}, sync::{Arc, mpsc::{channel, Receiver}}};
+use std::{env, path::{Path, PathBuf}, sync::Arc};
+#[cfg(test)]
+use std::fs;
 
 use super::{DirItem, StorageBackend, StorageChangeEvent, VersionItem, WalkRetCtl};
 use super::Result;
 use std::str;
 use std::thread;
-use std::time::Duration;
-use git2::{Oid, Remote, Repository, TreeEntry, TreeWalkMode};
+use git2::{Oid, Repository, TreeWalkMode};
 use parking_lot::{ReentrantMutex, RwLock};
 
 
 pub struct GitBackend(Arc<RwLock<GitBackendInner>>);
 struct GitBackendInner {
-    local_repo_path: PathBuf,
     remote_repo_url: String,
     git_repo: Arc<ReentrantMutex<Repository>>,
     target_branch_name: String,
@@ -35,7 +35,6 @@ impl GitBackend {
             }
         };
         let ret = Self(Arc::new(RwLock::new(GitBackendInner{
-            local_repo_path,
             remote_repo_url:remote_repo_url.clone(),
 			git_repo: Arc::new(ReentrantMutex::new(git_repo)),
             target_branch_name:target_branch_name.clone(),
@@ -63,9 +62,9 @@ impl StorageBackend for GitBackend {
         Ok(Vec::from(blob.content()))
     }
 
-    fn list_dir(&self, version: &VersionItem, path: &Path) -> Result<Vec<DirItem>> {
+    fn list_dir(&self, _version: &VersionItem, _path: &Path) -> Result<Vec<DirItem>> {
 		// TODO 
-        let mut ret = Vec::new();
+        let ret = Vec::new();
         return Ok(ret);
     }
 
@@ -97,7 +96,9 @@ impl StorageBackend for GitBackend {
                 None => return Ok(()),
             }
         };
-        
+
+        // libgit2 only sees the return code, so the error of the callback is kept here
+        let mut cb_err = None;
         walk_start_point.walk(TreeWalkMode::PreOrder, |root_rel_path, entry| {
             let filename =  match entry.name(){
                 Some(t) => t,
@@ -105,10 +106,7 @@ impl StorageBackend for GitBackend {
             };
             let abs_path = path.to_owned().join(root_rel_path).join(filename);
             let hash = Vec::from(entry.id().as_bytes());
-            let is_dir = match entry.kind() {
-                Some(git2::ObjectType::Tree) => true,
-                _ => false,
-            };
+            let is_dir = matches!(entry.kind(), Some(git2::ObjectType::Tree));
             let dir_item = DirItem::new(abs_path, is_dir, hash);
             match cb(&dir_item) {
                 Ok(t) => match t {
@@ -116,15 +114,21 @@ impl StorageBackend for GitBackend {
                     WalkRetCtl::SkipCurrentNode => 1,
                     WalkRetCtl::StopWalking => -1,
                 },
-                Err(_) => 0,
+                Err(e) => {
+                    cb_err = Some(e);
+                    -1
+                }
             }
         })?;
-        
+
+        if let Some(e) = cb_err {
+            return Err(StorageBackendError::WalkDirError(Box::new(e)));
+        }
 
         return Ok(());
     }
 
-    fn get_hash_by_path(&self, version: &VersionItem, path: &Path) -> Result<ObjectID> {
+    fn get_hash_by_path(&self, _version: &VersionItem, _path: &Path) -> Result<ObjectID> {
         return Err(std::io::Error::new(
             std::io::ErrorKind::NotFound,
             "not exist or not a file",
@@ -133,7 +137,6 @@ impl StorageBackend for GitBackend {
 
     fn set_update_cb(&self, cb: Box<dyn Fn(StorageChangeEvent) + Send + Sync>) -> Result<()> {
         let backend_inner = self.0.read();
-        let repo_cloned = backend_inner.git_repo.clone();
         let remote_repo_url_cloned = backend_inner.remote_repo_url.clone();
         let backend_inner_cloned = self.0.clone();
         let branch_name_clone = backend_inner.target_branch_name.clone();
@@ -143,7 +146,7 @@ impl StorageBackend for GitBackend {
         return Ok(())
     }
 
-    fn get_diff_list(&self, old_version: &VersionItem, new_version: &VersionItem, namespace: &str) -> Result<Vec<String>>{
+    fn get_diff_list(&self, _old_version: &VersionItem, _new_version: &VersionItem, _namespace: &str) -> Result<Vec<String>>{
         return Ok(Vec::new())
     }
 
@@ -161,7 +164,19 @@ impl StorageBackend for GitBackend {
         })
 
     }
-	fn list_versions(&self, start: usize, limit: usize) -> Result<Vec<VersionItem>>{
+
+    fn resolve_version(&self, name: &str) -> Result<VersionItem> {
+        let backend_inner = self.0.read();
+        let git_repo = backend_inner.git_repo.lock();
+        let commit = git_repo.revparse_single(name)?.peel_to_commit()?;
+        let commit_name = str::from_utf8(commit.message_bytes())?;
+        Ok(VersionItem{
+            name: commit_name.to_string(),
+            id: ObjectID::from(commit.id().as_bytes()),
+        })
+    }
+
+	fn list_versions(&self, _start: usize, _limit: usize) -> Result<Vec<VersionItem>>{
         return Ok(Vec::new())
     }
 }
@@ -172,16 +187,13 @@ fn git_watcher(remote_repo_url: String, backend_inner: Arc<RwLock<GitBackendInne
     loop {
         thread::sleep(time::Duration::from_secs(2));
         
-        match git_sync_local_branch(&remote_repo_url, &backend_inner, &branch_name){
-            Ok(Some(new_version)) => {
-                cb(StorageChangeEvent{
-                    new_version: new_version.clone(),
-                });
-                
-                let mut backend_inner_guard = backend_inner.write();
-                backend_inner_guard.cur_version = new_version;
-            },
-            _ => {},
+        if let Ok(Some(new_version)) = git_sync_local_branch(&remote_repo_url, &backend_inner, &branch_name) {
+            cb(StorageChangeEvent{
+                new_version: new_version.clone(),
+            });
+            
+            let mut backend_inner_guard = backend_inner.write();
+            backend_inner_guard.cur_version = new_version;
         };
     }
 }
@@ -189,9 +201,9 @@ fn git_watcher(remote_repo_url: String, backend_inner: Arc<RwLock<GitBackendInne
 
 
 
-fn git_sync_local_branch(remote_repo_url: &String, backend_inner: &Arc<RwLock<GitBackendInner>>, branch_name: &String) -> Result<Option<VersionItem>>{
+fn git_sync_local_branch(remote_repo_url: &str, backend_inner: &Arc<RwLock<GitBackendInner>>, branch_name: &str) -> Result<Option<VersionItem>>{
 
-    let mut tmp_remote_probe = match git2::Remote::create_detached(remote_repo_url.as_str()) {
+    let mut tmp_remote_probe = match git2::Remote::create_detached(remote_repo_url) {
         Err(e) => {
             print_error_with_switch!("git watch error when create temp client to remote git repo: {:?}", e);
             return Err(StorageBackendError::Git2Error(e)) 
@@ -212,12 +224,9 @@ fn git_sync_local_branch(remote_repo_url: &String, backend_inner: &Arc<RwLock<Gi
         });
 
     // TODO timeout control
-    match tmp_remote_probe.connect_auth(git2::Direction::Fetch, Some(callbacks),None) {
-        Err(e) => {
-            print_error_with_switch!("git watch error when connect to remote git repo: {:?}", e);
-            return Err(StorageBackendError::Git2Error(e)) 
-        }, 
-        _ => {},
+    if let Err(e) = tmp_remote_probe.connect_auth(git2::Direction::Fetch, Some(callbacks),None) {
+        print_error_with_switch!("git watch error when connect to remote git repo: {:?}", e);
+        return Err(StorageBackendError::Git2Error(e)) 
     };
 
     let remote_heads = match tmp_remote_probe.list(){
@@ -228,7 +237,7 @@ fn git_sync_local_branch(remote_repo_url: &String, backend_inner: &Arc<RwLock<Gi
         Ok(t) => t,
     };  
     
-    let branch_name_in_refspec_format = "refs/heads/".to_owned() + &branch_name;
+    let branch_name_in_refspec_format = "refs/heads/".to_owned() + branch_name;
     let backend_inner_guard = backend_inner.read();
     let mut newest_remote_commit_id = None;
     for remote_head in remote_heads {
@@ -239,7 +248,7 @@ fn git_sync_local_branch(remote_repo_url: &String, backend_inner: &Arc<RwLock<Gi
             return Ok(None)
         }
 
-        newest_remote_commit_id = Some(remote_head.oid().clone());
+        newest_remote_commit_id = Some(remote_head.oid());
         break
     }
 
@@ -313,7 +322,7 @@ fn test_git_backend_smoke() {
     let project_base_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     let local_repo_path = project_base_dir
         .join(".git");
-    let mut backend = Box::new(GitBackend::new(local_repo_path, "".to_string(),"master".to_string()).unwrap());
+    let backend = Box::new(GitBackend::new(local_repo_path, "".to_string(),"master".to_string()).unwrap());
     let ver = backend.get_current_version().unwrap();
     println!("{}", ver.name);
     println!("{:?}", ver.id);
@@ -323,6 +332,52 @@ fn test_git_backend_smoke() {
         Ok(WalkRetCtl::Next)
     };
 
-    backend.walk_dir(&ver, &Path::new("/test/mock_data"), cb).unwrap();
+    backend.walk_dir(&ver, Path::new("/test/mock_data"), cb).unwrap();
     
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_git_walk_dir_error() {
+    let repo_path = env::temp_dir().join("eafcc_test").join("git_walk_dir_error.git");
+    let _ = fs::remove_dir_all(&repo_path);
+    let repo = Repository::init_bare(repo_path).unwrap();
+    let commit = {
+        let blob = repo.blob(b"{}").unwrap();
+        let mut sub = repo.treebuilder(None).unwrap();
+        sub.insert("a.json", blob, 0o100644).unwrap();
+        sub.insert("b.json", blob, 0o100644).unwrap();
+        let mut root = repo.treebuilder(None).unwrap();
+        root.insert("reses", sub.write().unwrap(), 0o040000).unwrap();
+        let tree = repo.find_tree(root.write().unwrap()).unwrap();
+        let sig = git2::Signature::now("eafcc", "eafcc@localhost").unwrap();
+        repo.commit(None, &sig, &sig, "v1", &tree, &[]).unwrap()
+    };
+
+    let backend = GitBackend(Arc::new(RwLock::new(GitBackendInner {
+        remote_repo_url: "".to_string(),
+        git_repo: Arc::new(ReentrantMutex::new(repo)),
+        target_branch_name: "master".to_string(),
+        cur_version: VersionItem {
+            name: "v1".to_string(),
+            id: ObjectID::from(commit.as_bytes()),
+        },
+    })));
+    let ver = backend.0.read().cur_version.clone();
+
+    let mut visited = Vec::new();
+    backend.walk_dir(&ver, Path::new("/"), &mut |d: &DirItem| {
+        visited.push(d.abs_path.clone());
+        Ok(WalkRetCtl::Next)
+    }).unwrap();
+    assert_eq!(visited, vec![PathBuf::from("/reses"), PathBuf::from("/reses/a.json"), PathBuf::from("/reses/b.json")]);
+
+    // the walk stops at the first error of the callback and returns it
+    let mut visited = Vec::new();
+    let ret = backend.walk_dir(&ver, Path::new("/reses"), &mut |d: &DirItem| {
+        visited.push(d.abs_path.clone());
+        return Err(WalkDirError::StorageBackendError(StorageBackendError::VersionNotFound("v2".to_string())));
+    });
+    assert!(matches!(ret, Err(StorageBackendError::WalkDirError(_))));
+    assert_eq!(ret.unwrap_err().to_string(), "error while operating on git backend: error while operating on git backend: version `v2` not found");
+    assert_eq!(visited, vec![PathBuf::from("/reses/a.json")]);
+}
@@ -0,0 +1,147 @@
+use crate::{
+    error::StorageBackendError,
+    model::object::{ObjectID, ObjectIDRef},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{DirItem, Result, StorageBackend, StorageChangeEvent, VersionItem};
+
+/*
+A backend that keeps every version in memory, for tests that need a config set that is a small
+change away from a fixture, e.g., one broken file, without writing it to disk. It never changes
+on its own, so update callbacks are never called.
+*/
+
+pub struct MemoryBackend {
+    head: String,
+    // version -> absolute path -> content
+    versions: BTreeMap<String, BTreeMap<PathBuf, Vec<u8>>>,
+}
+
+impl MemoryBackend {
+    pub fn new(head: &str) -> Self {
+        return Self {
+            head: head.to_owned(),
+            versions: BTreeMap::new(),
+        };
+    }
+
+    /// Everything a `FilesystemBackend` would read from `base_path`.
+    pub fn from_dir(base_path: &Path) -> Result<Self> {
+        let head = fs::read_to_string(base_path.join("head"))?;
+        let mut ret = Self::new(head.trim());
+        for entry in fs::read_dir(base_path)? {
+            let entry = entry?;
+            if entry.path().is_dir() {
+                let version = entry.file_name().to_string_lossy().into_owned();
+                ret.versions.entry(version.clone()).or_default();
+                ret.copy_dir(&version, &entry.path(), Path::new("/"))?;
+            }
+        }
+        return Ok(ret);
+    }
+
+    fn copy_dir(&mut self, version: &str, fs_path: &Path, path: &Path) -> Result<()> {
+        for entry in fs::read_dir(fs_path)? {
+            let entry = entry?;
+            let child = path.join(entry.file_name());
+            if entry.path().is_dir() {
+                self.copy_dir(version, &entry.path(), &child)?;
+            } else {
+                self.insert(version, &child.to_string_lossy(), fs::read(entry.path())?);
+            }
+        }
+        return Ok(());
+    }
+
+    /// Add or replace the file at the absolute `path` of `version`.
+    pub fn insert(&mut self, version: &str, path: &str, content: impl Into<Vec<u8>>) {
+        self.versions
+            .entry(version.to_owned())
+            .or_default()
+            .insert(PathBuf::from(path), content.into());
+    }
+
+    pub fn get(&self, version: &str, path: &str) -> Option<&[u8]> {
+        return self.versions.get(version)?.get(Path::new(path)).map(|t| &t[..]);
+    }
+
+    fn files(&self, version: &VersionItem) -> Result<&BTreeMap<PathBuf, Vec<u8>>> {
+        return self
+            .versions
+            .get(&version.name)
+            .ok_or_else(|| StorageBackendError::VersionNotFound(version.name.clone()));
+    }
+}
+
+fn not_found() -> StorageBackendError {
+    return std::io::Error::new(std::io::ErrorKind::NotFound, "not exist").into();
+}
+
+impl StorageBackend for MemoryBackend {
+    // the hash of a file is its version and path
+    fn get_obj_by_hash(&self, hash: ObjectIDRef) -> Result<Vec<u8>> {
+        let hash = std::str::from_utf8(hash)?;
+        let (version, path) = hash.split_once('\n').ok_or_else(not_found)?;
+        return self.get(version, path).map(|t| t.to_vec()).ok_or_else(not_found);
+    }
+
+    fn list_dir(&self, version: &VersionItem, path: &Path) -> Result<Vec<DirItem>> {
+        let files = self.files(version)?;
+        let mut children = BTreeSet::new();
+        for file in files.keys() {
+            if let Ok(rest) = file.strip_prefix(path) {
+                if let Some(name) = rest.iter().next() {
+                    children.insert((path.join(name), rest.iter().count() > 1));
+                }
+            }
+        }
+        if children.is_empty() {
+            return Err(not_found());
+        }
+        return Ok(children
+            .into_iter()
+            .map(|(child, is_dir)| {
+                let hash = format!("{}\n{}", version.name, child.to_string_lossy()).into_bytes();
+                DirItem::new(child, is_dir, hash)
+            })
+            .collect());
+    }
+
+    fn get_hash_by_path(&self, version: &VersionItem, path: &Path) -> Result<ObjectID> {
+        if self.files(version)?.contains_key(path) == false {
+            return Err(not_found());
+        }
+        return Ok(format!("{}\n{}", version.name, path.to_string_lossy()).into_bytes());
+    }
+
+    fn set_update_cb(&self, _cb: Box<dyn Fn(StorageChangeEvent) + Send + Sync>) -> Result<()> {
+        return Ok(());
+    }
+
+    fn get_diff_list(&self, _old_version: &VersionItem, _new_version: &VersionItem, _namespace: &str) -> Result<Vec<String>> {
+        return Ok(Vec::new());
+    }
+
+    fn get_current_version(&self) -> Result<VersionItem> {
+        return self.resolve_version(&self.head);
+    }
+
+    fn resolve_version(&self, name: &str) -> Result<VersionItem> {
+        if self.versions.contains_key(name) == false {
+            return Err(StorageBackendError::VersionNotFound(name.to_owned()));
+        }
+        return Ok(VersionItem {
+            name: name.to_owned(),
+            id: ObjectID::from(name.as_bytes()),
+        });
+    }
+
+    fn list_versions(&self, _start: usize, _limit: usize) -> Result<Vec<VersionItem>> {
+        return Ok(Vec::new());
+    }
+}
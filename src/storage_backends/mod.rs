@@ -1,10 +1,12 @@
 pub mod filesystem;
 pub mod git;
+#[cfg(test)]
+pub mod memory;
 
 use std::path::{Path, PathBuf};
 
 use crate::{
-    error::{CCLibError, StorageBackendError, WalkDirError},
+    error::{StorageBackendError, WalkDirError},
     model::object::{ObjectID, ObjectIDRef},
 };
 
@@ -62,6 +64,9 @@ pub trait StorageBackend {
         namespace: &str,
     ) -> Result<Vec<String>>;
     fn get_current_version(&self) -> Result<VersionItem>;
+    // find a version by a name the backend understands, e.g., a commit id, tag or branch for
+    // git, used by links that pin their resources to a version
+    fn resolve_version(&self, name: &str) -> Result<VersionItem>;
     fn list_versions(&self, start: usize, limit: usize) -> Result<Vec<VersionItem>>;
 
     fn walk_dir(
@@ -73,7 +78,7 @@ pub trait StorageBackend {
         let mut nodes_to_visit: Vec<PathBuf> = Vec::with_capacity(32);
         nodes_to_visit.push(path.to_owned());
         while let Some(ref parent_node) = nodes_to_visit.pop() {
-            for cur_node in self.list_dir(version, &parent_node)? {
+            for cur_node in self.list_dir(version, parent_node)? {
                 match cb(&cur_node) {
                     Ok(t) => match t {
                         WalkRetCtl::StopWalking => return Ok(()),